@@ -0,0 +1,209 @@
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::Utc;
+use hmac::Hmac;
+use hmac::Mac;
+use rocket::fairing::AdHoc;
+use rocket::http::Status;
+use rocket::request::FromRequest;
+use rocket::request::Outcome;
+use rocket::Request;
+use rocket::serde::json::serde_json;
+use serde::{Serialize, Deserialize};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+// JWT-compatible HS256 header, so tokens can be inspected with the usual tools
+const TOKEN_HEADER: &str = r#"{"alg":"HS256","typ":"JWT"}"#;
+const ALL_WORLDS: &str = "*";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Claims {
+    pub sub: String,
+    #[serde(default)]
+    pub admin: bool,
+    // worlds the subject may read and query; "*" grants every world
+    #[serde(default)]
+    pub worlds: Vec<String>,
+    // expiry as seconds since the unix epoch
+    pub exp: i64,
+}
+
+impl Claims {
+    pub fn can_access(&self, world: &str) -> bool {
+        self.admin || self.worlds.iter().any(|w| w == ALL_WORLDS || w == world)
+    }
+
+    pub fn can_modify(&self, owner: &str) -> bool {
+        self.admin || self.sub == owner
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum AuthError {
+    Missing,
+    Malformed,
+    BadSignature,
+    Expired,
+    NotConfigured,
+}
+
+pub struct Authenticator {
+    secret: Vec<u8>,
+}
+
+impl Authenticator {
+    pub fn new(secret: &[u8]) -> Authenticator {
+        Authenticator { secret: secret.to_vec() }
+    }
+
+    // Manages an Authenticator keyed with the `auth_secret` config value.
+    pub fn fairing() -> AdHoc {
+        AdHoc::try_on_ignite("Bearer Token Authentication", |rocket| async {
+            match rocket.figment().extract_inner::<String>("auth_secret") {
+                Ok(secret) if !secret.is_empty() => {
+                    Ok(rocket.manage(Authenticator::new(secret.as_bytes())))
+                },
+                _ => {
+                    log::error!("auth_secret must be configured");
+                    Err(rocket)
+                },
+            }
+        })
+    }
+
+    fn mac(&self) -> HmacSha256 {
+        HmacSha256::new_from_slice(&self.secret)
+        .expect("HMAC accepts keys of any length")
+    }
+
+    pub fn sign(&self, claims: &Claims) -> String {
+        let mut token = URL_SAFE_NO_PAD.encode(TOKEN_HEADER);
+        token.push('.');
+        token.push_str(&URL_SAFE_NO_PAD.encode(
+            serde_json::to_vec(claims).expect("Unable to serialize claims")
+        ));
+
+        let mut mac = self.mac();
+        mac.update(token.as_bytes());
+        let signature = mac.finalize().into_bytes();
+
+        token.push('.');
+        token.push_str(&URL_SAFE_NO_PAD.encode(signature));
+        token
+    }
+
+    pub fn verify(&self, token: &str) -> Result<Claims, AuthError> {
+        let (signed, signature) = token.rsplit_once('.').ok_or(AuthError::Malformed)?;
+        let (header, payload) = signed.split_once('.').ok_or(AuthError::Malformed)?;
+
+        let signature = URL_SAFE_NO_PAD.decode(signature).map_err(|_| AuthError::Malformed)?;
+        let mut mac = self.mac();
+        mac.update(signed.as_bytes());
+        mac.verify_slice(&signature).map_err(|_| AuthError::BadSignature)?;
+
+        let header = URL_SAFE_NO_PAD.decode(header).map_err(|_| AuthError::Malformed)?;
+        if header != TOKEN_HEADER.as_bytes() {
+            return Err(AuthError::Malformed);
+        }
+
+        let payload = URL_SAFE_NO_PAD.decode(payload).map_err(|_| AuthError::Malformed)?;
+        let claims: Claims = serde_json::from_slice(&payload).map_err(|_| AuthError::Malformed)?;
+        if claims.exp <= Utc::now().timestamp() {
+            return Err(AuthError::Expired);
+        }
+        Ok(claims)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Claims {
+    type Error = AuthError;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let auth = if let Some(auth) = request.rocket().state::<Authenticator>() {
+            auth
+        } else {
+            return Outcome::Error((Status::InternalServerError, AuthError::NotConfigured));
+        };
+
+        let token = match request.headers().get_one("Authorization") {
+            Some(value) => match value.strip_prefix("Bearer ") {
+                Some(token) => token.trim(),
+                None => return Outcome::Error((Status::Unauthorized, AuthError::Malformed)),
+            },
+            None => return Outcome::Error((Status::Unauthorized, AuthError::Missing)),
+        };
+
+        match auth.verify(token) {
+            Ok(claims) => Outcome::Success(claims),
+            Err(e) => Outcome::Error((Status::Unauthorized, e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claims() -> Claims {
+        Claims {
+            sub: "alice".to_string(),
+            admin: false,
+            worlds: vec!["arena".to_string()],
+            exp: Utc::now().timestamp() + 60,
+        }
+    }
+
+    #[test]
+    fn sign_and_verify() {
+        let auth = Authenticator::new(b"secret");
+        let token = auth.sign(&claims());
+        assert_eq!(auth.verify(&token), Ok(claims()));
+    }
+
+    #[test]
+    fn wrong_secret() {
+        let token = Authenticator::new(b"secret").sign(&claims());
+        assert_eq!(Authenticator::new(b"other").verify(&token), Err(AuthError::BadSignature));
+    }
+
+    #[test]
+    fn tampered_payload() {
+        let auth = Authenticator::new(b"secret");
+        let token = auth.sign(&claims());
+        let mut forged = claims();
+        forged.admin = true;
+        let forged_payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&forged).unwrap());
+        let parts: Vec<&str> = token.split('.').collect();
+        let tampered = format!("{}.{}.{}", parts[0], forged_payload, parts[2]);
+        assert_eq!(auth.verify(&tampered), Err(AuthError::BadSignature));
+        assert_eq!(auth.verify("not a token"), Err(AuthError::Malformed));
+    }
+
+    #[test]
+    fn expired() {
+        let auth = Authenticator::new(b"secret");
+        let mut c = claims();
+        c.exp = Utc::now().timestamp() - 1;
+        assert_eq!(auth.verify(&auth.sign(&c)), Err(AuthError::Expired));
+    }
+
+    #[test]
+    fn access() {
+        let c = claims();
+        assert!(c.can_access("arena"));
+        assert!(!c.can_access("lobby"));
+        assert!(c.can_modify("alice"));
+        assert!(!c.can_modify("bob"));
+
+        let admin = Claims { admin: true, ..claims() };
+        assert!(admin.can_access("lobby"));
+        assert!(admin.can_modify("bob"));
+
+        let everywhere = Claims { worlds: vec!["*".to_string()], ..claims() };
+        assert!(everywhere.can_access("lobby"));
+        assert!(!everywhere.can_modify("bob"));
+    }
+}
@@ -1,5 +1,15 @@
-use std::thread;
-use std::time::Duration;
+// The message types cover the whole protocol; only the phase handlers are
+// written so far.
+#![allow(dead_code)]
+
+use bangbang::logging;
+use bangbang::logging::LogConfig;
+use bangbang::telemetry::Span;
+use bangbang::telemetry::SpanKind;
+use bangbang::telemetry::TelemetryConfig;
+use bangbang::telemetry::Tracer;
+use std::env;
+use std::sync::Arc;
 
 enum Message {
     Request(RequestMessage),
@@ -16,19 +26,150 @@ enum Message {
 }
 struct RequestMessage {
     command: String,
-    params: Vec
+    params: Vec<String>
 }
+struct RequestReplyMessage {}
+struct ReadMessage {}
+struct ReadReplyMessage {}
+struct RequestAndReadMessage {}
+struct RequestAndReadReplyMessage {}
 
-fn main() {
-    thread::spawn(|| {
-        for i in 1..10 {
-            println!("hi number {} from the spawned thread!", i);
-            thread::sleep(Duration::from_millis(1));
+// An instance is a slot in the log of the replica that leads it; the ballot
+// orders leaders competing for the same instance.
+struct PreAcceptMessage {
+    replica: usize,
+    instance: u64,
+    ballot: u64,
+}
+struct PreAcceptOkMessage {
+    replica: usize,
+    instance: u64,
+    ballot: u64,
+}
+struct AcceptMessage {
+    replica: usize,
+    instance: u64,
+    ballot: u64,
+}
+struct AcceptOkMessage {
+    replica: usize,
+    instance: u64,
+    ballot: u64,
+}
+struct CommitMessage {
+    replica: usize,
+    instance: u64,
+    ballot: u64,
+}
+
+struct Replica {
+    id: usize,
+    tracer: Arc<Tracer>,
+}
+
+impl Replica {
+    fn handle(&self, message: Message) -> Option<Message> {
+        match message {
+            Message::PreAccept(m) => Some(Message::PreAcceptOk(self.pre_accept(m))),
+            Message::Accept(m) => Some(Message::AcceptOk(self.accept(m))),
+            Message::Commit(m) => {
+                self.commit(m);
+                None
+            },
+            _ => {
+                log::warn!("Replica {} has no handler for that message", self.id);
+                None
+            },
         }
-    });
+    }
+
+    fn phase_span(&self, name: &str, replica: usize, instance: u64, ballot: u64) -> Span {
+        let mut span = self.tracer.start_span(name, SpanKind::Server, None);
+        span.set_attribute("epaxos.replica", replica);
+        span.set_attribute("epaxos.instance", instance as i64);
+        span.set_attribute("epaxos.ballot", ballot as i64);
+        span.set_attribute("epaxos.receiver", self.id);
+        span
+    }
+
+    fn pre_accept(&self, m: PreAcceptMessage) -> PreAcceptOkMessage {
+        let span = self.phase_span("PreAccept", m.replica, m.instance, m.ballot);
+        log::debug!("Replica {} pre-accepting {}.{}", self.id, m.replica, m.instance);
+        span.end();
+        PreAcceptOkMessage { replica: m.replica, instance: m.instance, ballot: m.ballot }
+    }
+
+    fn accept(&self, m: AcceptMessage) -> AcceptOkMessage {
+        let span = self.phase_span("Accept", m.replica, m.instance, m.ballot);
+        log::debug!("Replica {} accepting {}.{}", self.id, m.replica, m.instance);
+        span.end();
+        AcceptOkMessage { replica: m.replica, instance: m.instance, ballot: m.ballot }
+    }
 
-    for i in 1..5 {
-        println!("hi number {} from the main thread!", i);
-        thread::sleep(Duration::from_millis(1));
+    fn commit(&self, m: CommitMessage) {
+        let span = self.phase_span("Commit", m.replica, m.instance, m.ballot);
+        log::debug!("Replica {} committing {}.{}", self.id, m.replica, m.instance);
+        span.end();
+    }
+}
+
+fn main() {
+    let mut log_config = LogConfig::default();
+    if let Ok(filter) = env::var("BANGBANG_LOG") {
+        log_config.filter = filter;
+    }
+    if let Ok(file) = env::var("BANGBANG_LOG_FILE") {
+        log_config.file = Some(file);
+    }
+    logging::init(&log_config)
+    .expect("Unable to initialize logging");
+
+    let mut telemetry = TelemetryConfig { service_name: "epaxos".to_string(), ..TelemetryConfig::default() };
+    telemetry.endpoint = env::var("BANGBANG_TRACE_ENDPOINT").ok();
+    telemetry.file = env::var("BANGBANG_TRACE_FILE").ok();
+    let tracer = Tracer::new(telemetry);
+
+    // replica 0 leads one instance through every phase on a three replica cluster
+    let replicas: Vec<Replica> = (0 .. 3).map(|id| Replica { id, tracer: tracer.clone() }).collect();
+    for r in &replicas[1 ..] {
+        r.handle(Message::PreAccept(PreAcceptMessage { replica: 0, instance: 0, ballot: 0 }));
+        r.handle(Message::Accept(AcceptMessage { replica: 0, instance: 0, ballot: 0 }));
+    }
+    for r in &replicas {
+        r.handle(Message::Commit(CommitMessage { replica: 0, instance: 0, ballot: 0 }));
+    }
+    tracer.flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bangbang::telemetry::read_exported_spans;
+
+    #[test]
+    fn phases_are_traced() {
+        let file = env::temp_dir()
+        .join(format!("bangbang-epaxos-spans-{}.json", std::process::id()))
+        .to_str().unwrap().to_string();
+        let tracer = Tracer::new(TelemetryConfig { file: Some(file.clone()), ..TelemetryConfig::default() });
+        let replica = Replica { id: 2, tracer: tracer.clone() };
+
+        let reply = replica.handle(Message::PreAccept(PreAcceptMessage { replica: 1, instance: 7, ballot: 3 }));
+        assert!(matches!(reply, Some(Message::PreAcceptOk(PreAcceptOkMessage { replica: 1, instance: 7, ballot: 3 }))));
+        let reply = replica.handle(Message::Accept(AcceptMessage { replica: 1, instance: 7, ballot: 3 }));
+        assert!(matches!(reply, Some(Message::AcceptOk(_))));
+        assert!(replica.handle(Message::Commit(CommitMessage { replica: 1, instance: 7, ballot: 3 })).is_none());
+        tracer.flush();
+
+        let spans = read_exported_spans(&file).unwrap();
+        let _ = std::fs::remove_file(&file);
+        let names: Vec<&str> = spans.iter().map(|s| s["name"].as_str().unwrap()).collect();
+        assert_eq!(names, ["PreAccept", "Accept", "Commit"]);
+        for span in &spans {
+            let attributes: Vec<(&str, &str)> = span["attributes"].as_array().unwrap().iter()
+                .map(|a| (a["key"].as_str().unwrap(), a["value"]["intValue"].as_str().unwrap()))
+                .collect();
+            assert_eq!(attributes, [("epaxos.replica", "1"), ("epaxos.instance", "7"), ("epaxos.ballot", "3"), ("epaxos.receiver", "2")]);
+        }
     }
 }
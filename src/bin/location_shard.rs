@@ -1,21 +1,338 @@
 #[macro_use]
 extern crate rocket;
 extern crate uuid;
+use bangbang::auth::Authenticator;
+use bangbang::auth::Claims;
 use bangbang::geometry::Shape3D;
 use bangbang::geometry::Vertex3D;
+use bangbang::http_client;
 use bangbang::logger_fairing::Logger;
-use rocket::response::status::NotFound;
+use bangbang::logger_fairing::RequestId;
+use bangbang::logging;
+use bangbang::logging::LogConfig;
+use bangbang::metrics_fairing::Measurements;
+use bangbang::metrics_fairing::Metrics;
+use bangbang::metrics_fairing::MetricsRegistry;
+use bangbang::rate_limit_fairing::RateLimiter;
+use bangbang::spatial::GridIndex;
+use bangbang::tracing_fairing::RequestSpan;
+use bangbang::tracing_fairing::Tracing;
+use rocket::fairing::AdHoc;
+use rocket::figment::Figment;
+use rocket::http::Status;
+use rocket::http::uri::Origin;
+use rocket::request;
+use rocket::request::FromRequest;
+use rocket::response;
+use rocket::response::Responder;
 use rocket::serde::json::Json;
 use rocket::serde::Deserialize;
 use rocket::serde::Serialize;
+use rocket::Build;
+use rocket::Request;
+use rocket::Rocket;
 use rocket::State;
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
 use std::sync::RwLock;
+use std::sync::RwLockReadGuard;
+use std::sync::RwLockWriteGuard;
+use std::time::Duration;
+use std::time::Instant;
 use uuid::Uuid;
 
+const DEFAULT_WORLD: &str = "default";
+const INDEX_CELL_SIZE: f32 = 100.0;
+
+const OBJECTS_METRIC: &str = "location_shard_objects";
+const LOCK_WAIT_METRIC: &str = "location_shard_lock_wait_seconds";
+const INDEX_RESULTS_METRIC: &str = "location_shard_index_results";
+const LOCK_WAIT_BUCKETS: &[f64] = &[0.00001, 0.0001, 0.001, 0.01, 0.1, 1.0];
+const INDEX_RESULT_BUCKETS: &[f64] = &[0.0, 1.0, 5.0, 10.0, 50.0, 100.0, 500.0, 1000.0];
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+struct AdminConfig {
+    enabled: bool,
+    mount: String,
+}
+
+impl AdminConfig {
+    // Rocket panics when mounting on anything but a plain absolute path.
+    fn validate(&self) -> Result<(), String> {
+        let plain = match Origin::parse(&self.mount) {
+            Ok(origin) => origin.path().starts_with('/') && origin.query().is_none() && !self.mount.contains('<'),
+            Err(_) => false,
+        };
+        if !plain {
+            return Err(format!("admin.mount must be an absolute path, not {:?}", self.mount));
+        }
+        Ok(())
+    }
+}
+
+impl Default for AdminConfig {
+    fn default() -> AdminConfig {
+        AdminConfig {
+            enabled: true,
+            mount: "/admin".to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+struct ShardConfig {
+    // host:port of the other shards and replicas that must be up for readiness
+    peers: Vec<String>,
+    peer_timeout_ms: u64,
+    // region of space this shard is responsible for
+    region: Option<Shape3D>,
+    admin: AdminConfig,
+}
+
+impl Default for ShardConfig {
+    fn default() -> ShardConfig {
+        ShardConfig {
+            peers: Vec::new(),
+            peer_timeout_ms: 500,
+            region: None,
+            admin: AdminConfig::default(),
+        }
+    }
+}
+
+#[derive(Responder, Debug)]
+enum ShardError {
+    #[response(status = 400)]
+    BadRequest(String),
+    #[response(status = 403)]
+    Forbidden(String),
+    #[response(status = 404)]
+    NotFound(String),
+    #[response(status = 409)]
+    Conflict(String),
+    #[response(status = 507)]
+    WorldFull(String),
+}
+
+struct TrackedObject {
+    location: Vertex3D,
+    owner: String,
+}
+
+struct World {
+    objects: HashMap<Uuid, TrackedObject>,
+    index: GridIndex<Uuid>,
+    max_objects: Option<usize>,
+}
+
+impl World {
+    fn new(max_objects: Option<usize>) -> World {
+        World {
+            objects: HashMap::new(),
+            index: GridIndex::new(INDEX_CELL_SIZE),
+            max_objects,
+        }
+    }
+
+    fn insert(&mut self, id: Uuid, location: Vertex3D, owner: &str) -> Result<(), ShardError> {
+        if let Some(max) = self.max_objects {
+            if !self.objects.contains_key(&id) && self.objects.len() >= max {
+                return Err(ShardError::WorldFull(format!("World is limited to {} objects", max)));
+            }
+        }
+        self.index.insert(id, &location);
+        self.objects.insert(id, TrackedObject { location, owner: owner.to_string() });
+        Ok(())
+    }
+
+    fn remove(&mut self, id: &Uuid) -> Option<TrackedObject> {
+        self.index.remove(id);
+        self.objects.remove(id)
+    }
+}
+
 struct AppState {
-    objects: Arc<RwLock<HashMap<Uuid, Vertex3D>>>,
+    worlds: Arc<RwLock<HashMap<String, Arc<RwLock<World>>>>>,
+    config: ShardConfig,
+    started: Instant,
+    loaded: AtomicBool,
+}
+
+impl AppState {
+    // Worlds are kept in memory, so storage is loaded once the shard has
+    // lifted off, and stays usable until a panic poisons one of its locks.
+    fn storage_loaded(&self) -> bool {
+        if !self.loaded.load(Ordering::SeqCst) {
+            return false;
+        }
+        match self.worlds.read() {
+            Ok(worlds) => worlds.values().all(|world| !world.is_poisoned()),
+            Err(_) => false,
+        }
+    }
+
+    fn world_summaries(&self, claims: &Claims) -> Vec<WorldSummary> {
+        let worlds = self.worlds.read()
+        .expect("Unable to get read lock on worlds");
+        let mut summaries: Vec<WorldSummary> = worlds.iter()
+        .filter(|(name, _)| claims.can_access(name))
+        .map(|(name, world)| {
+            let world = world.read()
+            .expect("Unable to get read lock on world");
+            WorldSummary {
+                name: name.clone(),
+                object_count: world.objects.len(),
+                max_objects: world.max_objects,
+            }
+        }).collect();
+        summaries.sort_by(|a, b| a.name.cmp(&b.name));
+        summaries
+    }
+}
+
+// The shard's state as one request sees it. Time spent waiting on locks is
+// noted on the request for the metrics fairing.
+struct Shard<'r> {
+    state: &'r AppState,
+    measurements: &'r Measurements,
+}
+
+impl<'r> Shard<'r> {
+    fn world(&self, name: &str) -> Result<Arc<RwLock<World>>, ShardError> {
+        let start = Instant::now();
+        let worlds = self.state.worlds.read()
+        .expect("Unable to get read lock on worlds");
+        self.measurements.observe_duration(LOCK_WAIT_METRIC, &[("lock", "worlds"), ("mode", "read")], start.elapsed());
+        match worlds.get(name) {
+            Some(world) => Ok(world.clone()),
+            None => Err(ShardError::NotFound(format!("Couldn't find world {}", name))),
+        }
+    }
+
+    fn read_world<'a>(&self, world: &'a RwLock<World>) -> RwLockReadGuard<'a, World> {
+        let start = Instant::now();
+        let guard = world.read()
+        .expect("Unable to get read lock on world");
+        self.measurements.observe_duration(LOCK_WAIT_METRIC, &[("lock", "world"), ("mode", "read")], start.elapsed());
+        guard
+    }
+
+    fn write_world<'a>(&self, world: &'a RwLock<World>) -> RwLockWriteGuard<'a, World> {
+        let start = Instant::now();
+        let guard = world.write()
+        .expect("Unable to get write lock on world");
+        self.measurements.observe_duration(LOCK_WAIT_METRIC, &[("lock", "world"), ("mode", "write")], start.elapsed());
+        guard
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Shard<'r> {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Shard<'r>, ()> {
+        match request.rocket().state::<AppState>() {
+            Some(state) => request::Outcome::Success(Shard { state, measurements: Measurements::of(request) }),
+            None => request::Outcome::Error((Status::InternalServerError, ())),
+        }
+    }
+}
+
+fn check_access(claims: &Claims, world: &str) -> Result<(), ShardError> {
+    if !claims.can_access(world) {
+        return Err(ShardError::Forbidden(format!("{} may not access world {}", claims.sub, world)));
+    }
+    Ok(())
+}
+
+fn check_admin(claims: &Claims) -> Result<(), ShardError> {
+    if !claims.admin {
+        return Err(ShardError::Forbidden(format!("{} is not an admin", claims.sub)));
+    }
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type")]
+struct CreateWorldRequest {
+    version: u32,
+    name: String,
+    max_objects: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type")]
+struct CreateWorldResponse {
+    version: u32,
+    name: String,
+    max_objects: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type")]
+struct WorldSummary {
+    name: String,
+    object_count: usize,
+    max_objects: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type")]
+struct ListWorldsResponse {
+    version: u32,
+    worlds: Vec<WorldSummary>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type")]
+struct DeleteWorldResponse {
+    version: u32,
+    name: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type")]
+struct PeerStatus {
+    peer: String,
+    reachable: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type")]
+struct ReadyResponse {
+    version: u32,
+    ready: bool,
+    storage_loaded: bool,
+    peers: Vec<PeerStatus>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type")]
+struct AdminResponse {
+    version: u32,
+    build_version: String,
+    uptime_seconds: u64,
+    config: ShardConfig,
+    object_count: usize,
+    worlds: Vec<WorldSummary>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type")]
+struct LoggingRequest {
+    version: u32,
+    filter: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type")]
+struct LoggingResponse {
+    version: u32,
+    filter: String,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -42,6 +359,18 @@ struct IndexResponse {
     object_ids: Vec<String>,
 }
 
+// Notes how many objects matched for the metrics fairing. A search that
+// found nothing is a 404.
+impl<'r> Responder<'r, 'static> for IndexResponse {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        Measurements::of(request).observe(INDEX_RESULTS_METRIC, &[], self.object_ids.len() as f64);
+        if self.object_ids.is_empty() {
+            return ShardError::NotFound("No matching objects found".to_string()).respond_to(request);
+        }
+        Json(self).respond_to(request)
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 #[serde(tag = "type")]
 struct ReadResponse {
@@ -72,24 +401,151 @@ struct DeleteResponse {
     object_id: String,
 }
 
-#[post("/", format = "application/json", data = "<request>")]
-fn create(state: &State<AppState>, request: Json<CreateRequest>) -> Result<Json<CreateResponse>,NotFound<String>> {
+#[get("/healthz")]
+fn healthz() -> &'static str {
+    "ok"
+}
+
+#[get("/readyz")]
+async fn readyz(state: &State<AppState>, request_id: RequestId, span: RequestSpan) -> (Status, Json<ReadyResponse>) {
+    let storage_loaded = state.storage_loaded();
+
+    let timeout = Duration::from_millis(state.config.peer_timeout_ms);
+    let mut checks = Vec::new();
+    for peer in state.config.peers.iter().cloned() {
+        let request_id = request_id.clone();
+        let traceparent = span.traceparent();
+        checks.push(rocket::tokio::task::spawn_blocking(move || {
+            let mut headers = vec![request_id.header()];
+            if let Some((name, value)) = &traceparent {
+                headers.push((name, value.as_str()));
+            }
+            let reachable = match http_client::get(&peer, "/healthz", &headers, timeout) {
+                Ok(response) => response.status == 200,
+                Err(_) => false,
+            };
+            PeerStatus { peer, reachable }
+        }));
+    }
+    let mut peers = Vec::new();
+    for check in checks {
+        peers.push(check.await.expect("Peer check panicked"));
+    }
+
+    let ready = storage_loaded && peers.iter().all(|p| p.reachable);
+    let status = if ready { Status::Ok } else { Status::ServiceUnavailable };
+    (status, Json::from(ReadyResponse {
+        version: 1,
+        ready,
+        storage_loaded,
+        peers,
+    }))
+}
+
+#[get("/")]
+fn admin(state: &State<AppState>, claims: Claims) -> Result<Json<AdminResponse>,ShardError> {
+    check_admin(&claims)?;
+
+    let worlds = state.world_summaries(&claims);
+    Ok(Json::from(AdminResponse {
+        version: 1,
+        build_version: env!("CARGO_PKG_VERSION").to_string(),
+        uptime_seconds: state.started.elapsed().as_secs(),
+        config: state.config.clone(),
+        object_count: worlds.iter().map(|w| w.object_count).sum(),
+        worlds,
+    }))
+}
+
+#[put("/logging", format = "application/json", data = "<request>")]
+fn set_logging(claims: Claims, request_id: RequestId, request: Json<LoggingRequest>) -> Result<Json<LoggingResponse>,ShardError> {
+    check_admin(&claims)?;
+
+    log::info!("[{}] LOGGING {}", request_id, request.filter);
+    logging::set_filter(&request.filter)
+    .map_err(ShardError::BadRequest)?;
+
+    Ok(Json::from(LoggingResponse {
+        version: 1,
+        filter: request.filter.clone(),
+    }))
+}
+
+#[post("/worlds", format = "application/json", data = "<request>")]
+fn create_world(state: &State<AppState>, claims: Claims, request_id: RequestId, request: Json<CreateWorldRequest>) -> Result<Json<CreateWorldResponse>,ShardError> {
+    check_admin(&claims)?;
+    // "worlds" would be shadowed by the world management routes
+    if request.name.is_empty() || request.name == "worlds" || request.name.contains('/') {
+        return Err(ShardError::BadRequest(format!("Invalid world name {}", request.name)));
+    }
+
+    log::info!("[{}] CREATE WORLD {}", request_id, request.name);
+
+    let mut worlds = state.worlds.write()
+    .expect("Unable to get write lock on worlds");
+    if worlds.contains_key(&request.name) {
+        return Err(ShardError::Conflict(format!("World {} already exists", request.name)));
+    }
+    worlds.insert(request.name.clone(), Arc::new(RwLock::new(World::new(request.max_objects))));
+
+    Ok(Json::from(CreateWorldResponse {
+        version: 1,
+        name: request.name.clone(),
+        max_objects: request.max_objects,
+    }))
+}
+
+#[get("/worlds")]
+fn list_worlds(state: &State<AppState>, claims: Claims) -> Json<ListWorldsResponse> {
+    Json::from(ListWorldsResponse {
+        version: 1,
+        worlds: state.world_summaries(&claims),
+    })
+}
+
+#[delete("/worlds/<name>")]
+fn delete_world(state: &State<AppState>, claims: Claims, request_id: RequestId, name: &str) -> Result<Json<DeleteWorldResponse>,ShardError> {
+    check_admin(&claims)?;
+    log::info!("[{}] DELETE WORLD {}", request_id, name);
+
+    let mut worlds = state.worlds.write()
+    .expect("Unable to get write lock on worlds");
+    if worlds.remove(name).is_none() {
+        return Err(ShardError::NotFound(format!("Couldn't find world {}", name)));
+    }
+
+    Ok(Json::from(DeleteWorldResponse {
+        version: 1,
+        name: name.to_string(),
+    }))
+}
+
+#[post("/<world>", format = "application/json", data = "<request>")]
+fn create(shard: Shard<'_>, claims: Claims, request_id: RequestId, world: &str, request: Json<CreateRequest>) -> Result<Json<CreateResponse>,ShardError> {
+    check_access(&claims, world)?;
+
     // parse id
     let id = Uuid::try_parse(request.object_id.as_str())
     .expect("Unable to parse id");
     // parse point
 
-    println!(
-        "CREATE {} at {}",
-        id.as_simple().to_string(),
-        request.location.to_string()
+    log::info!(
+        "[{}] CREATE {} in {} at {}",
+        request_id,
+        id.as_simple(),
+        world,
+        request.location
     );
 
     // start tracking object _uuid at given location
-    let arc = state.objects.clone();
-    let mut objects = arc.write()
-    .expect("Unable to get write lock on state");
-    objects.insert(id, request.location);
+    let arc = shard.world(world)?;
+    let mut world = shard.write_world(&arc);
+    if let Some(obj) = world.objects.get(&id) {
+        if !claims.can_modify(&obj.owner) {
+            return Err(ShardError::Forbidden("Object is owned by someone else".to_string()));
+        }
+    }
+    world.insert(id, request.location, &claims.sub)?;
 
     Ok(Json::from(CreateResponse {
         version: 1,
@@ -98,21 +554,30 @@ fn create(state: &State<AppState>, request: Json<CreateRequest>) -> Result<Json<
     }))
 }
 
-#[get("/<x>/<y>/<z>/<radius>")]
-fn index(state: &State<AppState>, x: f32, y: f32, z: f32, radius: f32) -> Result<Json<IndexResponse>,NotFound<String>> {
+#[get("/<world>/<x>/<y>/<z>/<radius>")]
+#[allow(clippy::too_many_arguments)]
+fn index(shard: Shard<'_>, claims: Claims, request_id: RequestId, span: RequestSpan, world: &str, x: f32, y: f32, z: f32, radius: f32) -> Result<IndexResponse,ShardError> {
+    check_access(&claims, world)?;
+
+    let mut query_span = span.child("index query");
+    query_span.set_attribute("world", world);
+    query_span.set_attribute("radius", radius);
+
     let sph = Shape3D::Sphere { center: Vertex3D { x, y, z }, radius };
     let pt: Vertex3D = Vertex3D { x, y, z };
-    println!("INDEX center={}, r={}", pt.to_string(), radius);
+    log::info!("[{}] INDEX {} center={}, r={}", request_id, world, pt, radius);
 
     let mut object_ids = Vec::new();
-    let arc = state.objects.clone();
-    let objects = arc.read()
-    .expect("Unable to get read lock on state");
-    // loop through objects and test within radius of x,y,z/r and add to return
-    for k in objects.keys() {
-        let obj_point = objects.get(k)
-        .expect(format!("Unable to find vertex for {}", k.to_string()).as_str());
-        println!("Checking object {} at {}", k, obj_point.to_string());
+    let arc = shard.world(world)?;
+    let world = shard.read_world(&arc);
+    // loop through candidate objects and test within radius of x,y,z/r and add to return
+    let candidates = world.index.query_sphere(&pt, radius);
+    query_span.set_attribute("candidates", candidates.len());
+    for k in candidates {
+        let obj_point = &world.objects.get(&k)
+        .unwrap_or_else(|| panic!("Unable to find vertex for {}", k))
+        .location;
+        log::trace!("[{}] Checking object {} at {}", request_id, k, obj_point);
 
         // TODO check object bbox or cylinder
         if obj_point.is_on_or_inside(&sph) {
@@ -120,35 +585,35 @@ fn index(state: &State<AppState>, x: f32, y: f32, z: f32, radius: f32) -> Result
         }
     }
 
-    if object_ids.len() <= 0 {
-        return Err(NotFound("No matching objects found".to_string()));
-    }
+    query_span.set_attribute("results", object_ids.len());
+    query_span.end();
 
     // TODO: long polling/pubsub
 
-    Ok(Json::from(IndexResponse {
+    Ok(IndexResponse {
         version: 1,
         search: sph,
-        object_ids: object_ids,
-    }))
+        object_ids,
+    })
 }
 
-#[get("/<id>")]
-fn read(state: &State<AppState>, id: &str) -> Result<Json<ReadResponse>,NotFound<String>> {
+#[get("/<world>/<id>")]
+fn read(shard: Shard<'_>, claims: Claims, request_id: RequestId, world: &str, id: &str) -> Result<Json<ReadResponse>,ShardError> {
+    check_access(&claims, world)?;
+
     // parse id
     let id = Uuid::try_parse(id)
     .expect("Unable to parse id");
 
-    println!("READ {}", id.as_simple().to_string());
+    log::info!("[{}] READ {} in {}", request_id, id.as_simple(), world);
 
     // TODO: find location of object and return
-    let arc = state.objects.clone();
-    let objects = arc.read()
-    .expect("Unable to get read lock on state");
-    let pt = if let Some(pt) = objects.get(&id) {
-        pt
+    let arc = shard.world(world)?;
+    let world = shard.read_world(&arc);
+    let pt = if let Some(obj) = world.objects.get(&id) {
+        &obj.location
     } else {
-        return Err(NotFound("Couldn't find object".to_string()));
+        return Err(ShardError::NotFound("Couldn't find object".to_string()));
     };
 
     // TODO: long polling/pubsub
@@ -159,29 +624,35 @@ fn read(state: &State<AppState>, id: &str) -> Result<Json<ReadResponse>,NotFound
     }))
 }
 
-#[put("/<id>", format = "application/json", data = "<request>")]
-fn update(state: &State<AppState>, id: &str, request: Json<UpdateRequest>) -> Result<Json<UpdateResponse>,NotFound<String>> {
+#[put("/<world>/<id>", format = "application/json", data = "<request>")]
+fn update(shard: Shard<'_>, claims: Claims, request_id: RequestId, world: &str, id: &str, request: Json<UpdateRequest>) -> Result<Json<UpdateResponse>,ShardError> {
+    check_access(&claims, world)?;
+
     // parse id
     let id = Uuid::try_parse(id)
     .expect("Unable to parse id");
 
-    println!(
-        "UPDATE {} at {}",
-        id.as_simple().to_string(),
-        request.location.to_string()
+    log::info!(
+        "[{}] UPDATE {} in {} at {}",
+        request_id,
+        id.as_simple(),
+        world,
+        request.location
     );
 
-    let arc = state.objects.clone();
-    let mut objects = arc.write()
-    .expect("Unable to get read lock on state");
+    let arc = shard.world(world)?;
+    let mut world = shard.write_world(&arc);
 
-    if !objects.contains_key(&id) {
-        return Err(NotFound("Object was not found".to_string()));
+    let owner = if let Some(obj) = world.objects.get(&id) {
+        obj.owner.clone()
+    } else {
+        return Err(ShardError::NotFound("Object was not found".to_string()));
+    };
+    if !claims.can_modify(&owner) {
+        return Err(ShardError::Forbidden("Object is owned by someone else".to_string()));
     }
 
-
-    objects.insert(id, request.location)
-    .expect("Object update failed");
+    world.insert(id, request.location, &owner)?;
 
     Ok(Json::from(UpdateResponse {
         version: 1,
@@ -190,21 +661,27 @@ fn update(state: &State<AppState>, id: &str, request: Json<UpdateRequest>) -> Re
     }))
 }
 
-#[delete("/<id>")]
-fn delete(state: &State<AppState>, id: &str) -> Result<Json<DeleteResponse>,NotFound<String>> {
+#[delete("/<world>/<id>")]
+fn delete(shard: Shard<'_>, claims: Claims, request_id: RequestId, world: &str, id: &str) -> Result<Json<DeleteResponse>,ShardError> {
+    check_access(&claims, world)?;
+
     // parse id
     let id = Uuid::try_parse(id)
     .expect("Unable to parse id");
 
-    println!("DELETE {}", id.as_simple().to_string());
+    log::info!("[{}] DELETE {} in {}", request_id, id.as_simple(), world);
 
     // stop tracking object _uuid
-    let arc = state.objects.clone();
-    let mut objects = arc.write()
-        .expect("Unable to get read lock on state");
-    if objects.remove(&id).is_none() {
-        return Err(NotFound("Couldn't find object".to_string()));
+    let arc = shard.world(world)?;
+    let mut world = shard.write_world(&arc);
+    match world.objects.get(&id) {
+        Some(obj) if !claims.can_modify(&obj.owner) => {
+            return Err(ShardError::Forbidden("Object is owned by someone else".to_string()));
+        },
+        Some(_) => {},
+        None => return Err(ShardError::NotFound("Couldn't find object".to_string())),
     }
+    world.remove(&id);
 
     Ok(Json::from(DeleteResponse {
         version: 1,
@@ -212,14 +689,94 @@ fn delete(state: &State<AppState>, id: &str) -> Result<Json<DeleteResponse>,NotF
     }))
 }
 
-#[launch]
-fn rocket() -> _ {
-    rocket::build()
+fn shard_metrics(worlds: Arc<RwLock<HashMap<String, Arc<RwLock<World>>>>>) -> Arc<MetricsRegistry> {
+    let metrics = Arc::new(MetricsRegistry::new());
+    metrics.register_gauge(OBJECTS_METRIC, "Objects tracked per world.");
+    metrics.register_histogram(LOCK_WAIT_METRIC, "Time spent waiting for state locks.", LOCK_WAIT_BUCKETS);
+    metrics.register_histogram(INDEX_RESULTS_METRIC, "Objects returned per index query.", INDEX_RESULT_BUCKETS);
+    metrics.add_collector(move |m| {
+        m.clear_gauge(OBJECTS_METRIC);
+        let worlds = worlds.read()
+        .expect("Unable to get read lock on worlds");
+        for (name, world) in worlds.iter() {
+            let count = world.read()
+            .expect("Unable to get read lock on world")
+            .objects.len();
+            m.set_gauge(OBJECTS_METRIC, &[("world", name)], count as f64);
+        }
+    });
+    metrics
+}
+
+fn shard(figment: Figment) -> Rocket<Build> {
+    let config: ShardConfig = match figment.extract() {
+        Ok(config) => config,
+        Err(e) => return refuse_launch(rocket::custom(figment), format!("Invalid shard configuration: {}", e)),
+    };
+    if let Err(e) = config.admin.validate() {
+        return refuse_launch(rocket::custom(figment), format!("Invalid shard configuration: {}", e));
+    }
+
+    let mut worlds = HashMap::new();
+    worlds.insert(DEFAULT_WORLD.to_string(), Arc::new(RwLock::new(World::new(None))));
+    let worlds = Arc::new(RwLock::new(worlds));
+    let metrics = shard_metrics(worlds.clone());
+
+    let admin_config = config.admin.clone();
+    let rocket = rocket::custom(figment)
         .manage(AppState {
-            objects: Arc::new(RwLock::new(HashMap::new())),
+            worlds,
+            config,
+            started: Instant::now(),
+            loaded: AtomicBool::new(false),
         })
-        .attach(Logger {})
-        .mount("/", routes![create, index, read, update, delete])
+        .attach(Metrics::new(metrics))
+        .attach(Logger::new())
+        .attach(Tracing::new())
+        .attach(Authenticator::fairing())
+        .attach(RateLimiter::new())
+        .attach(AdHoc::on_liftoff("Storage Loaded", |rocket| Box::pin(async move {
+            if let Some(state) = rocket.state::<AppState>() {
+                state.loaded.store(true, Ordering::SeqCst);
+            }
+        })))
+        .mount("/", routes![healthz, readyz])
+        .mount("/", routes![create_world, list_worlds, delete_world])
+        .mount("/", routes![create, index, read, update, delete]);
+
+    if admin_config.enabled {
+        return rocket.mount(admin_config.mount.as_str(), routes![admin, set_logging]);
+    }
+    rocket
+}
+
+// Logs why and stops the launch at ignition, rather than panicking while the
+// rocket is still being built.
+fn refuse_launch(rocket: Rocket<Build>, reason: String) -> Rocket<Build> {
+    rocket.attach(AdHoc::try_on_ignite("Shard Configuration", move |rocket| Box::pin(async move {
+        log::error!("{}", reason);
+        Err(rocket)
+    })))
+}
+
+// Installs logging from the `logging` table, then builds the shard.
+fn with_logging(figment: Figment) -> Rocket<Build> {
+    let log_config = match figment.find_value("logging") {
+        Ok(_) => match figment.extract_inner::<LogConfig>("logging") {
+            Ok(config) => config,
+            Err(e) => return refuse_launch(rocket::custom(figment), format!("Invalid logging configuration: {}", e)),
+        },
+        Err(_) => LogConfig::default(),
+    };
+    if let Err(e) = logging::init(&log_config) {
+        return refuse_launch(rocket::custom(figment), format!("Unable to initialize logging: {}", e));
+    }
+    shard(figment)
+}
+
+#[launch]
+fn rocket() -> _ {
+    with_logging(rocket::Config::figment())
 }
 
 #[cfg(test)]
@@ -227,25 +784,58 @@ mod test {
     use super::*;
 
     use bangbang::geometry::Shape3D;
+    use chrono::Utc;
     use rocket::http::ContentType;
+    use rocket::http::Header;
     use rocket::http::uri::Uri;
     use rocket::local::blocking::Client;
     use rocket::http::Status;
+    use std::io::Read;
+    use std::io::Write;
+    use std::net::TcpListener;
+    use bangbang::telemetry::Tracer;
+    use bangbang::telemetry::read_exported_spans;
+    use std::thread;
     use rocket::serde::json::serde_json;
     use uuid::Uuid;
 
     const TEST_ID: &str = "f1cc50ec66f14e9e87e2ed0ae8607b9f";
+    const TEST_SECRET: &str = "location shard test secret";
+    const TEST_USER: &str = "tester";
+
+    fn test_rocket() -> Rocket<Build> {
+        shard(rocket::Config::figment().merge(("auth_secret", TEST_SECRET)))
+    }
+
+    fn bearer(sub: &str, admin: bool, worlds: &[&str]) -> Header<'static> {
+        let token = Authenticator::new(TEST_SECRET.as_bytes()).sign(&Claims {
+            sub: sub.to_string(),
+            admin,
+            worlds: worlds.iter().map(|w| w.to_string()).collect(),
+            exp: Utc::now().timestamp() + 3600,
+        });
+        Header::new("Authorization", format!("Bearer {}", token))
+    }
+
+    fn user() -> Header<'static> {
+        bearer(TEST_USER, false, &["*"])
+    }
+
+    fn admin() -> Header<'static> {
+        bearer("admin", true, &[])
+    }
 
     #[test]
     fn create() {
-        let client = Client::tracked(rocket())
+        let client = Client::tracked(test_rocket())
         .expect("valid rocket instance");
         let req = CreateRequest {
             version: 1,
             object_id: TEST_ID.to_string(),
             location: Vertex3D { x: 0.0, y: 0.0, z: 0.0 },
         };
-        let response = client.post(uri!("/"))
+        let response = client.post(uri!("/default"))
+            .header(user())
             .header(ContentType::JSON)
             .body(serde_json::to_string(&req).unwrap())
             .dispatch();
@@ -260,20 +850,21 @@ mod test {
 
     #[test]
     fn index() {
-        let r = rocket();
+        let r = test_rocket();
 
         // set up state
         let state = r.state::<AppState>().unwrap();
-        let objects = state.objects.clone();
-        objects.write().unwrap().insert(Uuid::try_parse(&TEST_ID.to_string()).unwrap(), Vertex3D {
+        let world = state.worlds.read().unwrap()[DEFAULT_WORLD].clone();
+        world.write().unwrap().insert(Uuid::try_parse(TEST_ID).unwrap(), Vertex3D {
             x: 0.0,
             y: 0.0,
             z: 0.0,
-        });
+        }, TEST_USER).unwrap();
 
         let client = Client::tracked(r)
         .expect("valid rocket instance");
-        let response = client.get(uri!("/0.0/0.0/0.0/100.0"))
+        let response = client.get(uri!("/default/0.0/0.0/0.0/100.0"))
+            .header(user())
             .header(ContentType::JSON)
             .dispatch();
         assert_eq!(response.status(), Status::Ok);
@@ -290,21 +881,22 @@ mod test {
 
     #[test]
     fn read() {
-        let r = rocket();
+        let r = test_rocket();
 
         // set up state
         let state = r.state::<AppState>().unwrap();
-        let objects = state.objects.clone();
-        objects.write().unwrap().insert(Uuid::try_parse(&TEST_ID.to_string()).unwrap(), Vertex3D {
+        let world = state.worlds.read().unwrap()[DEFAULT_WORLD].clone();
+        world.write().unwrap().insert(Uuid::try_parse(TEST_ID).unwrap(), Vertex3D {
             x: 0.0,
             y: 0.0,
             z: 0.0,
-        });
+        }, TEST_USER).unwrap();
 
         let client = Client::tracked(r)
         .expect("valid rocket instance");
-        let path = format!("/{}", TEST_ID.to_string());
+        let path = format!("/{}/{}", DEFAULT_WORLD, TEST_ID);
         let response = client.get(Uri::parse_any(path.as_str()).unwrap())
+            .header(user())
             .header(ContentType::JSON)
             .dispatch();
         assert_eq!(response.status(), Status::Ok);
@@ -318,12 +910,13 @@ mod test {
 
     #[test]
     fn read_bad_id() {
-        let r = rocket();
+        let r = test_rocket();
 
         let client = Client::tracked(r)
         .expect("valid rocket instance");
-        let path = "/blah";
+        let path = "/default/blah";
         let response = client.get(Uri::parse_any(path).unwrap())
+            .header(user())
             .header(ContentType::JSON)
             .dispatch();
         assert_eq!(response.status(), Status::InternalServerError);
@@ -331,16 +924,16 @@ mod test {
 
     #[test]
     fn update() {
-        let r = rocket();
+        let r = test_rocket();
 
         // set up state
         let state = r.state::<AppState>().unwrap();
-        let objects = state.objects.clone();
-        objects.write().unwrap().insert(Uuid::try_parse(&TEST_ID.to_string()).unwrap(), Vertex3D {
+        let world = state.worlds.read().unwrap()[DEFAULT_WORLD].clone();
+        world.write().unwrap().insert(Uuid::try_parse(TEST_ID).unwrap(), Vertex3D {
             x: 0.0,
             y: 0.0,
             z: 0.0,
-        });
+        }, TEST_USER).unwrap();
 
         let req = UpdateRequest {
             version: 1,
@@ -348,8 +941,9 @@ mod test {
         };
         let client = Client::tracked(r)
         .expect("valid rocket instance");
-        let path = format!("/{}", TEST_ID.to_string());
+        let path = format!("/{}/{}", DEFAULT_WORLD, TEST_ID);
         let response = client.put(Uri::parse_any(path.as_str()).unwrap())
+            .header(user())
             .header(ContentType::JSON)
             .body(serde_json::to_string(&req).unwrap())
             .dispatch();
@@ -364,21 +958,22 @@ mod test {
 
     #[test]
     fn delete() {
-        let r = rocket();
+        let r = test_rocket();
 
         // set up state
         let state = r.state::<AppState>().unwrap();
-        let objects = state.objects.clone();
-        objects.write().unwrap().insert(Uuid::try_parse(&TEST_ID.to_string()).unwrap(), Vertex3D {
+        let world = state.worlds.read().unwrap()[DEFAULT_WORLD].clone();
+        world.write().unwrap().insert(Uuid::try_parse(TEST_ID).unwrap(), Vertex3D {
             x: 0.0,
             y: 0.0,
             z: 0.0,
-        });
+        }, TEST_USER).unwrap();
 
         let client = Client::tracked(r)
         .expect("valid rocket instance");
-        let path = format!("/{}", TEST_ID.to_string());
+        let path = format!("/{}/{}", DEFAULT_WORLD, TEST_ID);
         let response = client.delete(Uri::parse_any(path.as_str()).unwrap())
+            .header(user())
             .header(ContentType::JSON)
             .dispatch();
         assert_eq!(response.status(), Status::Ok);
@@ -391,14 +986,531 @@ mod test {
 
     #[test]
     fn delete_bad_id() {
-        let r = rocket();
+        let r = test_rocket();
 
         let client = Client::tracked(r)
         .expect("valid rocket instance");
-        let path = "/blah";
+        let path = "/default/blah";
         let response = client.delete(Uri::parse_any(path).unwrap())
+            .header(user())
             .header(ContentType::JSON)
             .dispatch();
         assert_eq!(response.status(), Status::InternalServerError);
     }
+
+    #[test]
+    fn create_world() {
+        let client = Client::tracked(test_rocket())
+        .expect("valid rocket instance");
+        let req = CreateWorldRequest {
+            version: 1,
+            name: "arena".to_string(),
+            max_objects: Some(10),
+        };
+        let response = client.post(uri!("/worlds"))
+            .header(admin())
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&req).unwrap())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.into_string().unwrap(), serde_json::to_string(&CreateWorldResponse {
+            version: 1,
+            name: "arena".to_string(),
+            max_objects: Some(10),
+        }).unwrap());
+
+        // duplicate names and the reserved name are rejected
+        let response = client.post(uri!("/worlds"))
+            .header(admin())
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&req).unwrap())
+            .dispatch();
+        assert_eq!(response.status(), Status::Conflict);
+        let response = client.post(uri!("/worlds"))
+            .header(admin())
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&CreateWorldRequest {
+                version: 1,
+                name: "worlds".to_string(),
+                max_objects: None,
+            }).unwrap())
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
+    #[test]
+    fn list_worlds() {
+        let r = test_rocket();
+
+        let state = r.state::<AppState>().unwrap();
+        state.worlds.write().unwrap().insert("arena".to_string(), Arc::new(RwLock::new(World::new(Some(5)))));
+        let world = state.worlds.read().unwrap()[DEFAULT_WORLD].clone();
+        world.write().unwrap().insert(Uuid::try_parse(TEST_ID).unwrap(), Vertex3D { x: 0.0, y: 0.0, z: 0.0 }, TEST_USER).unwrap();
+
+        let client = Client::tracked(r)
+        .expect("valid rocket instance");
+        let response = client.get(uri!("/worlds"))
+            .header(admin())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.into_string().unwrap(), serde_json::to_string(&ListWorldsResponse {
+            version: 1,
+            worlds: vec![
+                WorldSummary { name: "arena".to_string(), object_count: 0, max_objects: Some(5) },
+                WorldSummary { name: DEFAULT_WORLD.to_string(), object_count: 1, max_objects: None },
+            ],
+        }).unwrap());
+    }
+
+    #[test]
+    fn delete_world() {
+        let client = Client::tracked(test_rocket())
+        .expect("valid rocket instance");
+        let response = client.delete(uri!("/worlds/default"))
+            .header(admin())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let path = format!("/{}/{}", DEFAULT_WORLD, TEST_ID);
+        let response = client.get(Uri::parse_any(path.as_str()).unwrap())
+            .header(user())
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        let response = client.delete(uri!("/worlds/default"))
+            .header(admin())
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn worlds_are_isolated() {
+        let r = test_rocket();
+
+        let state = r.state::<AppState>().unwrap();
+        state.worlds.write().unwrap().insert("arena".to_string(), Arc::new(RwLock::new(World::new(None))));
+        let world = state.worlds.read().unwrap()[DEFAULT_WORLD].clone();
+        world.write().unwrap().insert(Uuid::try_parse(TEST_ID).unwrap(), Vertex3D { x: 0.0, y: 0.0, z: 0.0 }, TEST_USER).unwrap();
+
+        let client = Client::tracked(r)
+        .expect("valid rocket instance");
+        let path = format!("/arena/{}", TEST_ID);
+        let response = client.get(Uri::parse_any(path.as_str()).unwrap())
+            .header(user())
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        let response = client.get(uri!("/arena/0.0/0.0/0.0/100.0"))
+            .header(user())
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn create_in_full_world() {
+        let r = test_rocket();
+
+        let state = r.state::<AppState>().unwrap();
+        state.worlds.write().unwrap().insert("tiny".to_string(), Arc::new(RwLock::new(World::new(Some(1)))));
+
+        let client = Client::tracked(r)
+        .expect("valid rocket instance");
+        let mut req = CreateRequest {
+            version: 1,
+            object_id: TEST_ID.to_string(),
+            location: Vertex3D { x: 0.0, y: 0.0, z: 0.0 },
+        };
+        let response = client.post(uri!("/tiny"))
+            .header(user())
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&req).unwrap())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        req.object_id = Uuid::new_v4().as_simple().to_string();
+        let response = client.post(uri!("/tiny"))
+            .header(user())
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&req).unwrap())
+            .dispatch();
+        assert_eq!(response.status(), Status::InsufficientStorage);
+    }
+
+    #[test]
+    fn missing_token() {
+        let client = Client::tracked(test_rocket())
+        .expect("valid rocket instance");
+        let response = client.get(uri!("/default/0.0/0.0/0.0/100.0"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+        let response = client.get(uri!("/default/0.0/0.0/0.0/100.0"))
+            .header(Header::new("Authorization", "Bearer nonsense"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+    }
+
+    #[test]
+    fn world_access_is_per_claims() {
+        let client = Client::tracked(test_rocket())
+        .expect("valid rocket instance");
+        let response = client.get(uri!("/default/0.0/0.0/0.0/100.0"))
+            .header(bearer(TEST_USER, false, &["arena"]))
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+
+        let response = client.post(uri!("/worlds"))
+            .header(user())
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&CreateWorldRequest {
+                version: 1,
+                name: "arena".to_string(),
+                max_objects: None,
+            }).unwrap())
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+    }
+
+    #[test]
+    fn only_owner_or_admin_may_modify() {
+        let r = test_rocket();
+
+        let state = r.state::<AppState>().unwrap();
+        let world = state.worlds.read().unwrap()[DEFAULT_WORLD].clone();
+        world.write().unwrap().insert(Uuid::try_parse(TEST_ID).unwrap(), Vertex3D { x: 0.0, y: 0.0, z: 0.0 }, TEST_USER).unwrap();
+
+        let client = Client::tracked(r)
+        .expect("valid rocket instance");
+        let path = format!("/{}/{}", DEFAULT_WORLD, TEST_ID);
+        let response = client.put(Uri::parse_any(path.as_str()).unwrap())
+            .header(bearer("mallory", false, &["*"]))
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&UpdateRequest {
+                version: 1,
+                location: Vertex3D { x: 1.0, y: 1.0, z: 1.0 },
+            }).unwrap())
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+
+        let response = client.delete(Uri::parse_any(path.as_str()).unwrap())
+            .header(bearer("mallory", false, &["*"]))
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+
+        // others can still see it
+        let response = client.get(Uri::parse_any(path.as_str()).unwrap())
+            .header(bearer("mallory", false, &["*"]))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        let response = client.delete(Uri::parse_any(path.as_str()).unwrap())
+            .header(admin())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    #[test]
+    fn owner_needs_world_access_to_modify() {
+        let r = test_rocket();
+
+        let state = r.state::<AppState>().unwrap();
+        let world = state.worlds.read().unwrap()[DEFAULT_WORLD].clone();
+        world.write().unwrap().insert(Uuid::try_parse(TEST_ID).unwrap(), Vertex3D { x: 0.0, y: 0.0, z: 0.0 }, TEST_USER).unwrap();
+
+        let client = Client::tracked(r)
+        .expect("valid rocket instance");
+        let path = format!("/{}/{}", DEFAULT_WORLD, TEST_ID);
+        // still the owner, but scoped to another world
+        let scoped = || bearer(TEST_USER, false, &["arena"]);
+        let response = client.put(Uri::parse_any(path.as_str()).unwrap())
+            .header(scoped())
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&UpdateRequest {
+                version: 1,
+                location: Vertex3D { x: 1.0, y: 1.0, z: 1.0 },
+            }).unwrap())
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+
+        let response = client.delete(Uri::parse_any(path.as_str()).unwrap())
+            .header(scoped())
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+
+        let response = client.get(Uri::parse_any(path.as_str()).unwrap())
+            .header(user())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let read: ReadResponse = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(read.location, Vertex3D { x: 0.0, y: 0.0, z: 0.0 });
+    }
+
+    #[test]
+    fn metrics() {
+        let r = test_rocket();
+
+        let state = r.state::<AppState>().unwrap();
+        let world = state.worlds.read().unwrap()[DEFAULT_WORLD].clone();
+        world.write().unwrap().insert(Uuid::try_parse(TEST_ID).unwrap(), Vertex3D { x: 0.0, y: 0.0, z: 0.0 }, TEST_USER).unwrap();
+
+        let client = Client::tracked(r)
+        .expect("valid rocket instance");
+        let response = client.get(uri!("/default/0.0/0.0/0.0/100.0"))
+            .header(user())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        // an empty search is still counted
+        let response = client.get(uri!("/default/5000.0/0.0/0.0/1.0"))
+            .header(user())
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+
+        let response = client.get(uri!("/metrics"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let body = response.into_string().unwrap();
+        assert!(body.contains("location_shard_objects{world=\"default\"} 1\n"));
+        assert!(!body.contains("location_shard_subscriptions"));
+        assert!(body.contains("location_shard_index_results_bucket{le=\"0\"} 1\n"));
+        assert!(body.contains("location_shard_index_results_count 2\n"));
+        assert!(body.contains("location_shard_lock_wait_seconds_count{lock=\"world\",mode=\"read\"} 2\n"));
+        assert!(body.contains("http_requests_total{method=\"GET\",route=\"/<world>/<x>/<y>/<z>/<radius>\",status=\"200\"} 1\n"));
+    }
+
+    #[test]
+    fn healthz() {
+        let client = Client::tracked(test_rocket())
+        .expect("valid rocket instance");
+        let response = client.get(uri!("/healthz"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.into_string().unwrap(), "ok");
+    }
+
+    #[test]
+    fn readyz() {
+        let client = Client::tracked(test_rocket())
+        .expect("valid rocket instance");
+        let response = client.get(uri!("/readyz"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.into_string().unwrap(), serde_json::to_string(&ReadyResponse {
+            version: 1,
+            ready: true,
+            storage_loaded: true,
+            peers: Vec::new(),
+        }).unwrap());
+    }
+
+    #[test]
+    fn readyz_reflects_world_state() {
+        let r = test_rocket();
+        let state = r.state::<AppState>().unwrap();
+        // not ready until launched
+        assert!(!state.storage_loaded());
+        let world = state.worlds.read().unwrap()[DEFAULT_WORLD].clone();
+        let client = Client::tracked(r)
+        .expect("valid rocket instance");
+        assert_eq!(client.get(uri!("/readyz")).dispatch().status(), Status::Ok);
+
+        // a handler that panics holding a world's lock leaves it unusable
+        let poisoned = thread::spawn(move || {
+            let _guard = world.write().unwrap();
+            panic!("poisoning the default world");
+        }).join();
+        assert!(poisoned.is_err());
+        let response = client.get(uri!("/readyz"))
+            .dispatch();
+        assert_eq!(response.status(), Status::ServiceUnavailable);
+        let body: ReadyResponse = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert!(!body.ready);
+        assert!(!body.storage_loaded);
+    }
+
+    #[test]
+    fn bad_config_refuses_launch() {
+        for figment in [
+            rocket::Config::figment().merge(("admin.mount", "admin")),
+            rocket::Config::figment().merge(("admin.mount", "/admin/<name>")),
+            rocket::Config::figment().merge(("peers", 5)),
+        ] {
+            let Err(error) = Client::tracked(shard(figment.merge(("auth_secret", TEST_SECRET)))) else {
+                panic!("launch should fail");
+            };
+            assert!(matches!(error.kind(), rocket::error::ErrorKind::FailedFairings(_)));
+        }
+
+        // both fail before the installed logger is touched
+        for figment in [
+            rocket::Config::figment().merge(("logging.filter", "info,bangbang=loud")),
+            rocket::Config::figment().merge(("logging.max_files", "many")),
+        ] {
+            let Err(error) = Client::tracked(with_logging(figment.merge(("auth_secret", TEST_SECRET)))) else {
+                panic!("launch should fail");
+            };
+            assert!(matches!(error.kind(), rocket::error::ErrorKind::FailedFairings(_)));
+        }
+    }
+
+    #[test]
+    fn readyz_unreachable_peer() {
+        // nothing listens on the discard port
+        let figment = rocket::Config::figment()
+            .merge(("auth_secret", TEST_SECRET))
+            .merge(("peers", vec!["127.0.0.1:9"]));
+        let client = Client::tracked(shard(figment))
+        .expect("valid rocket instance");
+        let response = client.get(uri!("/readyz"))
+            .dispatch();
+        assert_eq!(response.status(), Status::ServiceUnavailable);
+        assert_eq!(response.into_string().unwrap(), serde_json::to_string(&ReadyResponse {
+            version: 1,
+            ready: false,
+            storage_loaded: true,
+            peers: vec![PeerStatus { peer: "127.0.0.1:9".to_string(), reachable: false }],
+        }).unwrap());
+    }
+
+    #[test]
+    fn admin_endpoint() {
+        let client = Client::tracked(test_rocket())
+        .expect("valid rocket instance");
+        let response = client.get(uri!("/admin"))
+            .header(user())
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+
+        let response = client.get(uri!("/admin"))
+            .header(admin())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let body: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(body["build_version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(body["object_count"], 0);
+        assert_eq!(body["config"]["admin"]["mount"], "/admin");
+        assert_eq!(body["worlds"][0]["name"], DEFAULT_WORLD);
+    }
+
+    #[test]
+    fn admin_can_be_moved_or_disabled() {
+        let figment = rocket::Config::figment()
+            .merge(("auth_secret", TEST_SECRET))
+            .merge(("admin.mount", "/internal/admin"));
+        let client = Client::tracked(shard(figment))
+        .expect("valid rocket instance");
+        let response = client.get(uri!("/internal/admin"))
+            .header(admin())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        let figment = rocket::Config::figment()
+            .merge(("auth_secret", TEST_SECRET))
+            .merge(("admin.enabled", false));
+        let client = Client::tracked(shard(figment))
+        .expect("valid rocket instance");
+        let response = client.get(uri!("/admin"))
+            .header(admin())
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn readyz_forwards_request_id() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let peer = listener.local_addr().unwrap().to_string();
+        let fake_peer = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0u8; 1024];
+            let n = stream.read(&mut buf).unwrap();
+            stream.write_all(b"HTTP/1.1 200 OK\r\n\r\nok").unwrap();
+            String::from_utf8_lossy(&buf[..n]).to_string()
+        });
+
+        let figment = rocket::Config::figment()
+            .merge(("auth_secret", TEST_SECRET))
+            .merge(("peers", vec![peer.clone()]));
+        let client = Client::tracked(shard(figment))
+        .expect("valid rocket instance");
+        let response = client.get(uri!("/readyz"))
+            .header(Header::new("X-Request-Id", "trace-me"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.headers().get_one("X-Request-Id"), Some("trace-me"));
+
+        let forwarded = fake_peer.join().unwrap();
+        assert!(forwarded.starts_with("GET /healthz HTTP/1.1\r\n"));
+        assert!(forwarded.contains("X-Request-Id: trace-me\r\n"));
+    }
+
+    #[test]
+    fn set_logging() {
+        // another test may already have let Rocket install its own logger,
+        // which only stops our logger from receiving records
+        let _ = logging::init(&LogConfig::default());
+
+        let client = Client::tracked(test_rocket())
+        .expect("valid rocket instance");
+        let req = LoggingRequest {
+            version: 1,
+            filter: "info,location_shard=trace".to_string(),
+        };
+        let response = client.put(uri!("/admin/logging"))
+            .header(user())
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&req).unwrap())
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+
+        let response = client.put(uri!("/admin/logging"))
+            .header(admin())
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&req).unwrap())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let filters = logging::logger().unwrap().filters();
+        assert_eq!(filters.level_for("location_shard"), log::LevelFilter::Trace);
+        assert_eq!(filters.level_for("rocket"), log::LevelFilter::Info);
+
+        let response = client.put(uri!("/admin/logging"))
+            .header(admin())
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&LoggingRequest {
+                version: 1,
+                filter: "location_shard=loud".to_string(),
+            }).unwrap())
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
+    #[test]
+    fn index_is_traced() {
+        let file = std::env::temp_dir()
+        .join(format!("bangbang-shard-trace-{}.json", Uuid::new_v4().as_simple()))
+        .to_str().unwrap().to_string();
+        let figment = rocket::Config::figment()
+            .merge(("auth_secret", TEST_SECRET))
+            .merge(("telemetry.file", file.clone()));
+        let r = shard(figment);
+        let state = r.state::<AppState>().unwrap();
+        state.worlds.read().unwrap()[DEFAULT_WORLD].clone().write().unwrap()
+        .insert(Uuid::new_v4(), Vertex3D { x: 1.0, y: 0.0, z: 0.0 }, TEST_USER).unwrap();
+
+        let client = Client::tracked(r)
+        .expect("valid rocket instance");
+        let response = client.get(uri!("/default/0.0/0.0/0.0/10.0"))
+            .header(user())
+            .header(Header::new("X-Request-Id", "traced"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        client.rocket().state::<Arc<Tracer>>().unwrap().flush();
+
+        let spans = read_exported_spans(&file).unwrap();
+        let server = spans.iter().find(|s| s["name"] == "GET /<world>/<x>/<y>/<z>/<radius>").unwrap();
+        let query = spans.iter().find(|s| s["name"] == "index query").unwrap();
+        assert_eq!(query["parentSpanId"], server["spanId"]);
+        assert_eq!(query["traceId"], server["traceId"]);
+        let attribute = |span: &serde_json::Value, key: &str| span["attributes"].as_array().unwrap().iter()
+            .find(|a| a["key"] == key)
+            .map(|a| a["value"].clone());
+        assert_eq!(attribute(query, "results").unwrap()["intValue"], "1");
+        assert_eq!(attribute(server, "request.id").unwrap()["stringValue"], "traced");
+
+        std::fs::remove_file(&file).unwrap();
+    }
 }
@@ -0,0 +1,664 @@
+use crate::geometry::Shape3D;
+use crate::geometry::Vertex3D;
+
+const EPSILON: f32 = 1e-6;
+// separations below this count as touching
+pub(crate) const TOUCHING: f32 = 1e-4;
+const MAX_ITERATIONS: usize = 64;
+
+const X: Vertex3D = Vertex3D { x: 1.0, y: 0.0, z: 0.0 };
+const Y: Vertex3D = Vertex3D { x: 0.0, y: 1.0, z: 0.0 };
+const Z: Vertex3D = Vertex3D { x: 0.0, y: 0.0, z: 1.0 };
+
+// `normal` is a unit vector pointing from the first shape into the second;
+// moving the first shape by -normal * depth separates them. Touching shapes
+// have a contact with zero depth.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Contact {
+    pub normal: Vertex3D,
+    pub depth: f32,
+}
+
+impl Contact {
+    fn flipped(self) -> Contact {
+        Contact { normal: -self.normal, depth: self.depth }
+    }
+}
+
+pub fn intersects(a: &Shape3D, b: &Shape3D) -> bool {
+    collide(a, b).is_some()
+}
+
+// Spheres and boxes are handled exactly; every other pair goes through
+// GJK/EPA, treating Polygon3D and Polyhedron as the convex hull of their
+// vertices.
+pub fn collide(a: &Shape3D, b: &Shape3D) -> Option<Contact> {
+    match (a, b) {
+        (Shape3D::Sphere { center: ca, radius: ra }, Shape3D::Sphere { center: cb, radius: rb }) => {
+            sphere_sphere(*ca, *ra, *cb, *rb)
+        },
+        (Shape3D::Sphere { center, radius }, _) if obb(b).is_some() => {
+            sphere_box(*center, *radius, &obb(b)?)
+        },
+        (_, Shape3D::Sphere { center, radius }) if obb(a).is_some() => {
+            sphere_box(*center, *radius, &obb(a)?).map(Contact::flipped)
+        },
+        _ => match (obb(a), obb(b)) {
+            (Some(box_a), Some(box_b)) => box_box(&box_a, &box_b),
+            _ => gjk_epa(a, b),
+        },
+    }
+}
+
+// Furthest point of the shape in `direction`, which need not be normalized.
+pub fn support(shape: &Shape3D, direction: Vertex3D) -> Vertex3D {
+    match shape {
+        Shape3D::Sphere { center, radius } => {
+            let len = direction.length();
+            if len < EPSILON {
+                return *center + Y * *radius;
+            }
+            *center + direction * (radius / len)
+        },
+        Shape3D::Cube { .. } | Shape3D::Cuboid { .. } => {
+            let b = obb(shape).expect("Boxes always have an OBB");
+            let mut point = b.center;
+            for i in 0 .. 3 {
+                let sign = if direction.dot(b.axes[i]) < 0.0 { -1.0 } else { 1.0 };
+                point += b.axes[i] * (sign * b.half[i]);
+            }
+            point
+        },
+        // axis along the cylinder's own y, center halfway up
+        Shape3D::Cylinder { center, radius, height, orientation } => {
+            let local = orientation.conjugate().rotate(direction);
+            let rim = radial(local, *radius);
+            let y = if local.y < 0.0 { -height / 2.0 } else { height / 2.0 };
+            *center + orientation.rotate(Vertex3D { x: rim.x, y, z: rim.z })
+        },
+        // base at the bottom, apex at the top of the cone's own y, center
+        // halfway up
+        Shape3D::Cone { center, radius, height, orientation } => {
+            let local = orientation.conjugate().rotate(direction);
+            let apex = Vertex3D { x: 0.0, y: height / 2.0, z: 0.0 };
+            let rim = radial(local, *radius);
+            let base = Vertex3D { x: rim.x, y: -height / 2.0, z: rim.z };
+            *center + orientation.rotate(if apex.dot(local) >= base.dot(local) { apex } else { base })
+        },
+        Shape3D::Polygon3D(poly) => furthest(poly.vertices.iter(), direction),
+        Shape3D::Polyhedron { faces } => furthest(faces.iter().flat_map(|f| f.vertices.iter()), direction),
+    }
+}
+
+fn radial(direction: Vertex3D, radius: f32) -> Vertex3D {
+    let len = (direction.x * direction.x + direction.z * direction.z).sqrt();
+    if len < EPSILON {
+        return Vertex3D::ZERO;
+    }
+    Vertex3D { x: direction.x * radius / len, y: 0.0, z: direction.z * radius / len }
+}
+
+fn furthest<'a, I: Iterator<Item = &'a Vertex3D>>(vertices: I, direction: Vertex3D) -> Vertex3D {
+    vertices.fold((f32::NEG_INFINITY, Vertex3D::ZERO), |(best, point), v| {
+        let d = v.dot(direction);
+        if d > best { (d, *v) } else { (best, point) }
+    }).1
+}
+
+// An oriented box; axes are unit vectors and half the box's extents.
+struct Obb {
+    center: Vertex3D,
+    axes: [Vertex3D; 3],
+    half: [f32; 3],
+}
+
+fn obb(shape: &Shape3D) -> Option<Obb> {
+    let (center, half, orientation) = match shape {
+        Shape3D::Cube { center, side, orientation } => (center, [side / 2.0; 3], orientation),
+        Shape3D::Cuboid { center, width, height, length, orientation } => {
+            (center, [width / 2.0, height / 2.0, length / 2.0], orientation)
+        },
+        _ => return None,
+    };
+    Some(Obb {
+        center: *center,
+        axes: [orientation.rotate(X), orientation.rotate(Y), orientation.rotate(Z)],
+        half,
+    })
+}
+
+fn sphere_sphere(ca: Vertex3D, ra: f32, cb: Vertex3D, rb: f32) -> Option<Contact> {
+    let d = cb - ca;
+    let distance = d.length();
+    if distance > ra + rb {
+        return None;
+    }
+    let normal = if distance < EPSILON { Y } else { d / distance };
+    Some(Contact { normal, depth: ra + rb - distance })
+}
+
+fn sphere_box(center: Vertex3D, radius: f32, b: &Obb) -> Option<Contact> {
+    let d = center - b.center;
+    let local: Vec<f32> = b.axes.iter().map(|axis| d.dot(*axis)).collect();
+    let inside = (0 .. 3).all(|i| local[i].abs() <= b.half[i]);
+
+    if inside {
+        // push out through the nearest face
+        let i = (0 .. 3)
+            .min_by(|&i, &j| (b.half[i] - local[i].abs()).total_cmp(&(b.half[j] - local[j].abs())))
+            .expect("Boxes have three axes");
+        let sign = if local[i] < 0.0 { -1.0 } else { 1.0 };
+        return Some(Contact {
+            normal: b.axes[i] * -sign,
+            depth: radius + b.half[i] - local[i].abs(),
+        });
+    }
+
+    let mut closest = b.center;
+    for (i, l) in local.iter().enumerate() {
+        closest += b.axes[i] * l.clamp(-b.half[i], b.half[i]);
+    }
+    let delta = closest - center;
+    let distance = delta.length();
+    if distance > radius {
+        return None;
+    }
+    Some(Contact { normal: delta / distance, depth: radius - distance })
+}
+
+// Separating axis test over both boxes' face normals and the nine edge
+// cross products; the contact is along the axis of least overlap.
+fn box_box(a: &Obb, b: &Obb) -> Option<Contact> {
+    let d = b.center - a.center;
+    let mut axes: Vec<Vertex3D> = a.axes.iter().chain(b.axes.iter()).cloned().collect();
+    for i in 0 .. 3 {
+        for j in 0 .. 3 {
+            let axis = a.axes[i].cross(b.axes[j]);
+            if axis.length() > EPSILON {
+                axes.push(axis.normalize());
+            }
+        }
+    }
+
+    let projected = |o: &Obb, axis: Vertex3D| -> f32 {
+        (0 .. 3).map(|i| (o.axes[i].dot(axis) * o.half[i]).abs()).sum()
+    };
+    let mut best: Option<Contact> = None;
+    for axis in axes {
+        let distance = d.dot(axis);
+        let overlap = projected(a, axis) + projected(b, axis) - distance.abs();
+        if overlap < 0.0 {
+            return None;
+        }
+        if best.is_none_or(|c| overlap < c.depth) {
+            let normal = if distance < 0.0 { -axis } else { axis };
+            best = Some(Contact { normal, depth: overlap });
+        }
+    }
+    best
+}
+
+fn minkowski(a: &Shape3D, b: &Shape3D, direction: Vertex3D) -> Vertex3D {
+    support(a, direction) - support(b, -direction)
+}
+
+enum Gjk {
+    Separated { distance: f32, normal: Vertex3D },
+    Overlapping(Vec<Vertex3D>),
+}
+
+// Distance GJK on the Minkowski difference a - b.
+fn gjk(a: &Shape3D, b: &Shape3D) -> Gjk {
+    let mut simplex = vec![minkowski(a, b, X)];
+    let mut closest = simplex[0];
+    for _ in 0 .. MAX_ITERATIONS {
+        closest = closest_to_origin(&mut simplex);
+        let vv = closest.dot(closest);
+        if vv < EPSILON * EPSILON {
+            return Gjk::Overlapping(simplex);
+        }
+        let w = minkowski(a, b, -closest);
+        let converged = vv - closest.dot(w) <= 1e-6 * vv;
+        if converged || simplex.iter().any(|p| (*p - w).length() < EPSILON) {
+            break;
+        }
+        simplex.push(w);
+    }
+    let distance = closest.length();
+    Gjk::Separated { distance, normal: closest * (-1.0 / distance) }
+}
+
+// Reduces the simplex to the feature nearest the origin and returns the
+// nearest point on it, or the origin if a tetrahedron contains it.
+fn closest_to_origin(simplex: &mut Vec<Vertex3D>) -> Vertex3D {
+    match simplex.len() {
+        1 => simplex[0],
+        2 => {
+            let (point, keep) = closest_on_segment(simplex[0], simplex[1]);
+            *simplex = keep;
+            point
+        },
+        3 => {
+            let (point, keep) = closest_on_triangle(simplex[0], simplex[1], simplex[2]);
+            *simplex = keep;
+            point
+        },
+        _ => {
+            let [a, b, c, d] = [simplex[0], simplex[1], simplex[2], simplex[3]];
+            // a nearly flat tetrahedron's face normals are mostly rounding
+            // error, so don't trust which side of them the origin is on
+            let size = [b - a, c - a, d - a, c - b, d - b, d - c].iter().map(|e| e.length()).fold(0.0, f32::max);
+            let flat = (b - a).dot((c - a).cross(d - a)).abs() <= 1e-6 * size * size * size;
+            let mut best: Option<(f32, Vertex3D, Vec<Vertex3D>)> = None;
+            for (p, q, r, opposite) in [(a, b, c, d), (a, c, d, b), (a, d, b, c), (b, d, c, a)] {
+                let n = (q - p).cross(r - p);
+                let origin_side = -n.dot(p);
+                let opposite_side = n.dot(opposite - p);
+                // the origin is outside this face, or the tetrahedron is flat
+                if flat || origin_side * opposite_side < 0.0 {
+                    let (point, keep) = closest_on_triangle(p, q, r);
+                    let distance = point.dot(point);
+                    if best.as_ref().is_none_or(|(d, _, _)| distance < *d) {
+                        best = Some((distance, point, keep));
+                    }
+                }
+            }
+            match best {
+                Some((_, point, keep)) => {
+                    *simplex = keep;
+                    point
+                },
+                None => Vertex3D::ZERO,
+            }
+        },
+    }
+}
+
+fn closest_on_segment(a: Vertex3D, b: Vertex3D) -> (Vertex3D, Vec<Vertex3D>) {
+    let ab = b - a;
+    let denom = ab.dot(ab);
+    if denom < EPSILON * EPSILON {
+        return (a, vec![a]);
+    }
+    let t = -a.dot(ab) / denom;
+    if t <= 0.0 {
+        (a, vec![a])
+    } else if t >= 1.0 {
+        (b, vec![b])
+    } else {
+        (a + ab * t, vec![a, b])
+    }
+}
+
+// Ericson, Real-Time Collision Detection 5.1.5, with the origin as the query point.
+fn closest_on_triangle(a: Vertex3D, b: Vertex3D, c: Vertex3D) -> (Vertex3D, Vec<Vertex3D>) {
+    let ab = b - a;
+    let ac = c - a;
+    let ap = -a;
+    let d1 = ab.dot(ap);
+    let d2 = ac.dot(ap);
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return (a, vec![a]);
+    }
+    let bp = -b;
+    let d3 = ab.dot(bp);
+    let d4 = ac.dot(bp);
+    if d3 >= 0.0 && d4 <= d3 {
+        return (b, vec![b]);
+    }
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        let v = d1 / (d1 - d3);
+        return (a + ab * v, vec![a, b]);
+    }
+    let cp = -c;
+    let d5 = ab.dot(cp);
+    let d6 = ac.dot(cp);
+    if d6 >= 0.0 && d5 <= d6 {
+        return (c, vec![c]);
+    }
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        let w = d2 / (d2 - d6);
+        return (a + ac * w, vec![a, c]);
+    }
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && (d4 - d3) >= 0.0 && (d5 - d6) >= 0.0 {
+        let w = (d4 - d3) / ((d4 - d3) + (d5 - d6));
+        return (b + (c - b) * w, vec![b, c]);
+    }
+    let sum = va + vb + vc;
+    if sum.abs() < EPSILON * EPSILON {
+        // degenerate triangle; settle for the best edge
+        return [closest_on_segment(a, b), closest_on_segment(a, c), closest_on_segment(b, c)]
+            .into_iter()
+            .min_by(|x, y| x.0.dot(x.0).total_cmp(&y.0.dot(y.0)))
+            .expect("Three edges");
+    }
+    let v = vb / sum;
+    let w = vc / sum;
+    (a + ab * v + ac * w, vec![a, b, c])
+}
+
+fn gjk_epa(a: &Shape3D, b: &Shape3D) -> Option<Contact> {
+    match gjk(a, b) {
+        Gjk::Separated { distance, normal } if distance <= TOUCHING => Some(Contact { normal, depth: 0.0 }),
+        Gjk::Separated { .. } => None,
+        Gjk::Overlapping(simplex) => Some(epa(a, b, simplex)),
+    }
+}
+
+fn perpendicular(v: Vertex3D) -> Vertex3D {
+    let other = if v.x.abs() < 0.57 { X } else if v.y.abs() < 0.57 { Y } else { Z };
+    v.cross(other).normalize()
+}
+
+struct Face {
+    indices: [usize; 3],
+    normal: Vertex3D,
+    distance: f32,
+}
+
+// Expanding polytope algorithm: grows the GJK simplex out to the boundary
+// of a - b and returns the face nearest the origin.
+fn epa(a: &Shape3D, b: &Shape3D, mut points: Vec<Vertex3D>) -> Contact {
+    // grow the simplex to a solid around the origin
+    if points.len() == 1 {
+        for direction in [X, Y, Z, -X, -Y, -Z] {
+            let w = minkowski(a, b, direction);
+            if (w - points[0]).length() > EPSILON {
+                points.push(w);
+                break;
+            }
+        }
+    }
+    if points.len() == 2 {
+        let axis = points[1] - points[0];
+        let mut direction = perpendicular(axis);
+        for _ in 0 .. 6 {
+            let w = minkowski(a, b, direction);
+            if (w - points[0]).cross(axis).length() > EPSILON {
+                points.push(w);
+                break;
+            }
+            // rotate 60 degrees about the segment
+            direction = (direction * 0.5 + axis.normalize().cross(direction) * 0.866).normalize();
+        }
+    }
+    if points.len() < 3 {
+        return flat_contact(a, b, Y);
+    }
+    if points.len() == 3 {
+        let n = (points[1] - points[0]).cross(points[2] - points[0]).normalize();
+        for direction in [n, -n] {
+            let w = minkowski(a, b, direction);
+            if (w - points[0]).dot(direction).abs() > EPSILON {
+                points.push(w);
+            }
+        }
+        if points.len() == 3 {
+            return flat_contact(a, b, n);
+        }
+    }
+
+    let interior = points.iter().fold(Vertex3D::ZERO, |s, p| s + *p) * (1.0 / points.len() as f32);
+    let mut faces: Vec<Face> = Vec::new();
+    let candidates: Vec<[usize; 3]> = if points.len() == 4 {
+        vec![[0, 1, 2], [0, 3, 1], [0, 2, 3], [1, 3, 2]]
+    } else {
+        // triangle plus an apex on each side
+        vec![[0, 1, 3], [1, 2, 3], [2, 0, 3], [0, 1, 4], [1, 2, 4], [2, 0, 4]]
+    };
+    for indices in candidates {
+        if let Some(face) = make_face(&points, indices, interior) {
+            faces.push(face);
+        }
+    }
+
+    for _ in 0 .. MAX_ITERATIONS {
+        let nearest = match faces.iter().min_by(|x, y| x.distance.total_cmp(&y.distance)) {
+            Some(face) => face,
+            None => break,
+        };
+        let (normal, distance) = (nearest.normal, nearest.distance);
+        let w = minkowski(a, b, normal);
+        if w.dot(normal) - distance <= 1e-4 * distance.max(1.0) {
+            return Contact { normal, depth: distance.max(0.0) };
+        }
+
+        // remove every face the new point can see and patch the hole
+        let index = points.len();
+        points.push(w);
+        let mut horizon: Vec<(usize, usize)> = Vec::new();
+        faces.retain(|face| {
+            if face.normal.dot(w - points[face.indices[0]]) <= EPSILON {
+                return true;
+            }
+            for k in 0 .. 3 {
+                let edge = (face.indices[k], face.indices[(k + 1) % 3]);
+                match horizon.iter().position(|&(p, q)| p == edge.1 && q == edge.0) {
+                    Some(shared) => {
+                        horizon.swap_remove(shared);
+                    },
+                    None => horizon.push(edge),
+                }
+            }
+            false
+        });
+        for (p, q) in horizon {
+            if let Some(face) = make_face(&points, [p, q, index], interior) {
+                faces.push(face);
+            }
+        }
+    }
+
+    // out of iterations; the best face found is still a reasonable answer
+    match faces.iter().min_by(|x, y| x.distance.total_cmp(&y.distance)) {
+        Some(face) => Contact { normal: face.normal, depth: face.distance.max(0.0) },
+        None => flat_contact(a, b, Y),
+    }
+}
+
+fn make_face(points: &[Vertex3D], indices: [usize; 3], interior: Vertex3D) -> Option<Face> {
+    let [i, j, k] = indices;
+    let n = (points[j] - points[i]).cross(points[k] - points[i]);
+    if n.length() < EPSILON * EPSILON {
+        return None;
+    }
+    let mut normal = n.normalize();
+    let mut indices = indices;
+    // keep faces wound outwards
+    if normal.dot(points[i] - interior) < 0.0 {
+        normal = -normal;
+        indices = [i, k, j];
+    }
+    Some(Face { indices, normal, distance: normal.dot(points[i]) })
+}
+
+fn centroid(shape: &Shape3D) -> Vertex3D {
+    let vertices: Vec<Vertex3D> = match shape {
+        Shape3D::Polygon3D(poly) => poly.vertices.clone(),
+        Shape3D::Polyhedron { faces } => faces.iter().flat_map(|f| f.vertices.iter().cloned()).collect(),
+        Shape3D::Cube { center, .. } | Shape3D::Cuboid { center, .. } | Shape3D::Cone { center, .. }
+        | Shape3D::Cylinder { center, .. } | Shape3D::Sphere { center, .. } => vec![*center],
+    };
+    if vertices.is_empty() {
+        return Vertex3D::ZERO;
+    }
+    vertices.iter().fold(Vertex3D::ZERO, |s, v| s + *v) * (1.0 / vertices.len() as f32)
+}
+
+// Overlapping shapes whose Minkowski difference has no volume, e.g. two
+// coplanar polygons: report a zero-depth contact along `normal`, facing b.
+fn flat_contact(a: &Shape3D, b: &Shape3D, normal: Vertex3D) -> Contact {
+    let towards = centroid(b) - centroid(a);
+    let normal = if normal.dot(towards) < 0.0 { -normal } else { normal };
+    Contact { normal, depth: 0.0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Polygon3D;
+    use crate::geometry::Quaternion;
+
+    fn v(x: f32, y: f32, z: f32) -> Vertex3D {
+        Vertex3D { x, y, z }
+    }
+
+    fn sphere(x: f32, y: f32, z: f32, radius: f32) -> Shape3D {
+        Shape3D::Sphere { center: v(x, y, z), radius }
+    }
+
+    fn cube(x: f32, y: f32, z: f32, side: f32) -> Shape3D {
+        Shape3D::Cube { center: v(x, y, z), side, orientation: Quaternion::IDENTITY }
+    }
+
+    // a cube as a Polyhedron, to force the GJK/EPA path
+    fn hull_cube(c: Vertex3D, side: f32) -> Shape3D {
+        let h = side / 2.0;
+        let corner = |x: f32, y: f32, z: f32| v(c.x + x * h, c.y + y * h, c.z + z * h);
+        let face = |vs: [Vertex3D; 4]| Polygon3D { vertices: vs.to_vec() };
+        Shape3D::Polyhedron { faces: vec![
+            face([corner(-1.0, -1.0, -1.0), corner(1.0, -1.0, -1.0), corner(1.0, 1.0, -1.0), corner(-1.0, 1.0, -1.0)]),
+            face([corner(-1.0, -1.0, 1.0), corner(1.0, -1.0, 1.0), corner(1.0, 1.0, 1.0), corner(-1.0, 1.0, 1.0)]),
+        ] }
+    }
+
+    fn assert_contact(contact: Option<Contact>, normal: Vertex3D, depth: f32) {
+        let contact = contact.expect("Expected a contact");
+        assert!((contact.normal - normal).length() < 1e-3, "normal {:?} != {:?}", contact.normal, normal);
+        assert!((contact.depth - depth).abs() < 1e-3, "depth {} != {}", contact.depth, depth);
+    }
+
+    #[test]
+    fn nearly_flat_simplex_is_not_an_overlap() {
+        // GJK ends on a tetrahedron with three almost identical corners here
+        let orientation = Quaternion { w: 0.42661756, x: 0.15010762, y: 0.7600965, z: -0.46660322 };
+        let cylinder = Shape3D::Cylinder { center: v(-1.895401, 4.2819405, 2.7316384), radius: 0.3, height: 3.0, orientation };
+        let ball = sphere(-2.8693068, 1.6500854, 1.2668848, 1.4623246);
+        assert!(collide(&cylinder, &ball).is_none());
+    }
+
+    #[test]
+    fn rotated_shapes() {
+        let tilt = Quaternion::from_axis_angle(v(0.0, 0.0, 1.0), std::f32::consts::PI / 4.0);
+        let diamond = |y: f32| Shape3D::Cube { center: v(0.0, y, 0.0), side: 2.0, orientation: tilt };
+        let ground = Shape3D::Cuboid { center: v(0.0, -1.0, 0.0), width: 20.0, height: 2.0, length: 20.0, orientation: Quaternion::IDENTITY };
+        let corner = 2f32.sqrt();
+
+        // the tipped cube's corner reaches further than its face did
+        assert!(collide(&cube(0.0, 0.0, 0.0, 2.0), &sphere(0.0, 1.8, 0.0, 0.5)).is_none());
+        assert_contact(collide(&diamond(0.0), &sphere(0.0, 1.8, 0.0, 0.5)), Y, 0.5 - (1.8 - corner));
+        assert_contact(collide(&ground, &diamond(1.3)), Y, corner - 1.3);
+        assert_contact(collide(&ground, &diamond(1.3).translated(v(0.0, 0.0, 5.0))), Y, corner - 1.3);
+
+        // a log lying along x sinks by its radius, not half its length
+        let lying = Quaternion::from_axis_angle(v(0.0, 0.0, 1.0), std::f32::consts::PI / 2.0);
+        let log = Shape3D::Cylinder { center: v(0.0, 0.4, 0.0), radius: 0.5, height: 4.0, orientation: lying };
+        assert_contact(collide(&ground, &log), Y, 0.1);
+        assert!((support(&log, v(1.0, 0.0, 0.0)).x - 2.0).abs() < 1e-5);
+
+        // a cone balanced on its apex
+        let flipped = Quaternion::from_axis_angle(v(1.0, 0.0, 0.0), std::f32::consts::PI);
+        let top = Shape3D::Cone { center: v(0.0, 0.9, 0.0), radius: 1.0, height: 2.0, orientation: flipped };
+        assert_contact(collide(&ground, &top), Y, 0.1);
+    }
+
+    #[test]
+    fn sphere_sphere() {
+        assert_contact(collide(&sphere(0.0, 0.0, 0.0, 1.0), &sphere(2.0, 0.0, 0.0, 1.0)), X, 0.0);
+        assert!(collide(&sphere(0.0, 0.0, 0.0, 1.0), &sphere(2.01, 0.0, 0.0, 1.0)).is_none());
+        assert_contact(collide(&sphere(0.0, 0.0, 0.0, 1.0), &sphere(0.0, -0.5, 0.0, 2.0)), -Y, 2.5);
+        // concentric still gets a usable normal
+        assert_contact(collide(&sphere(1.0, 1.0, 1.0, 1.0), &sphere(1.0, 1.0, 1.0, 1.0)), Y, 2.0);
+    }
+
+    #[test]
+    fn sphere_box() {
+        let b = cube(0.0, 0.0, 0.0, 2.0);
+        assert_contact(collide(&sphere(0.0, 3.0, 0.0, 2.0), &b), -Y, 0.0);
+        assert!(collide(&sphere(2.0, 2.0, 0.0, 1.0), &b).is_none());
+        assert_contact(collide(&sphere(2.5, 0.0, 0.0, 2.0), &b), -X, 0.5);
+        // center inside the box
+        assert_contact(collide(&sphere(0.0, 0.0, 0.8, 0.5), &b), -Z, 0.7);
+        assert_contact(collide(&b, &sphere(2.5, 0.0, 0.0, 2.0)), X, 0.5);
+        // corner
+        let d = 1.0 + 0.5 / 3f32.sqrt();
+        assert_contact(collide(&sphere(d, d, d, 1.0), &b), v(-1.0, -1.0, -1.0).normalize(), 0.5);
+    }
+
+    #[test]
+    fn box_box() {
+        let a = cube(0.0, 0.0, 0.0, 2.0);
+        assert_contact(collide(&a, &cube(2.0, 0.5, 0.0, 2.0)), X, 0.0);
+        assert!(collide(&a, &cube(0.0, 0.0, 2.1, 2.0)).is_none());
+        let cuboid = Shape3D::Cuboid { center: v(0.3, 1.5, 0.0), width: 4.0, height: 2.0, length: 4.0, orientation: Quaternion::IDENTITY };
+        assert_contact(collide(&a, &cuboid), Y, 0.5);
+        assert_contact(collide(&cuboid, &a), -Y, 0.5);
+    }
+
+    #[test]
+    fn gjk_matches_exact_cases() {
+        let a = hull_cube(v(0.0, 0.0, 0.0), 2.0);
+        assert!(collide(&a, &hull_cube(v(0.0, 0.0, 2.5), 2.0)).is_none());
+        assert_contact(collide(&a, &hull_cube(v(0.0, 1.7, 0.3), 2.0)), Y, 0.3);
+        assert_contact(collide(&a, &hull_cube(v(-1.2, 0.0, 0.0), 2.0)), -X, 0.8);
+        // fully overlapping with the origin on the first simplex
+        assert_contact(collide(&a, &hull_cube(v(0.0, 0.0, 0.1), 2.0)), Z, 1.9);
+        // touching
+        assert_contact(collide(&a, &hull_cube(v(0.0, -2.0, 0.5), 2.0)), -Y, 0.0);
+
+        let mixed = collide(&sphere(0.0, 0.0, 2.5, 2.0), &a);
+        assert_contact(mixed, -Z, 0.5);
+        let separated = sphere(4.0, 0.0, 0.0, 1.0);
+        assert!(collide(&a, &separated).is_none());
+    }
+
+    #[test]
+    fn gjk_agrees_with_sat() {
+        let mut state = 12345u32;
+        let mut next = || {
+            state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+            (state >> 8) as f32 / (1 << 24) as f32 * 4.0 - 2.0
+        };
+        for _ in 0 .. 200 {
+            let offset = v(next(), next(), next());
+            let exact = collide(&cube(0.0, 0.0, 0.0, 2.0), &Shape3D::Cube { center: offset, side: 1.5, orientation: Quaternion::IDENTITY });
+            let hull = collide(&hull_cube(Vertex3D::ZERO, 2.0), &hull_cube(offset, 1.5));
+            match (exact, hull) {
+                (Some(e), Some(h)) => assert!((e.depth - h.depth).abs() < 1e-3, "{:?}: {:?} vs {:?}", offset, e, h),
+                (None, None) => {},
+                (e, h) => assert!(e.or(h).unwrap().depth < 1e-3, "{:?}: {:?} vs {:?}", offset, e, h),
+            }
+        }
+    }
+
+    #[test]
+    fn cylinders_and_cones() {
+        let cylinder = Shape3D::Cylinder { center: v(0.0, 0.0, 0.0), radius: 1.0, height: 4.0, orientation: Quaternion::IDENTITY };
+        // against the curved side
+        assert_contact(collide(&cylinder, &sphere(1.5, 0.0, 0.0, 1.0)), X, 0.5);
+        // against the top cap
+        assert_contact(collide(&cylinder, &sphere(0.0, 2.8, 0.0, 1.0)), Y, 0.2);
+        assert!(collide(&cylinder, &sphere(0.0, 3.5, 0.0, 1.0)).is_none());
+        // touching the rim of the cap
+        let d = 1.0 / 2f32.sqrt();
+        assert!(collide(&cylinder, &sphere(1.0 + d, 2.0 + d, 0.0, 1.0)).is_some_and(|c| c.depth < 1e-3));
+
+        let cone = Shape3D::Cone { center: v(0.0, 0.0, 0.0), radius: 1.0, height: 2.0, orientation: Quaternion::IDENTITY };
+        // apex poking into a box above
+        assert_contact(collide(&cone, &cube(0.0, 1.9, 0.0, 2.0)), Y, 0.1);
+        assert!(collide(&cone, &cube(0.0, 2.1, 0.0, 2.0)).is_none());
+        // deep: the box swallows the base
+        assert_contact(collide(&cone, &Shape3D::Cuboid { center: v(0.0, -2.0, 0.0), width: 10.0, height: 4.0, length: 10.0, orientation: Quaternion::IDENTITY }), -Y, 1.0);
+    }
+
+    #[test]
+    fn support_points() {
+        assert_eq!(support(&cube(1.0, 0.0, 0.0, 2.0), v(1.0, -1.0, 1.0)), v(2.0, -1.0, 1.0));
+        assert_eq!(support(&sphere(0.0, 0.0, 0.0, 2.0), v(0.0, 0.0, -5.0)), v(0.0, 0.0, -2.0));
+        let tri = Shape3D::Polygon3D(Polygon3D { vertices: vec![v(0.0, 0.0, 0.0), v(1.0, 0.0, 0.0), v(0.0, 1.0, 0.0)] });
+        assert_eq!(support(&tri, v(0.0, 1.0, 0.1)), v(0.0, 1.0, 0.0));
+    }
+}
@@ -1,14 +1,20 @@
 use std::f32::consts::PI;
 use serde::{Serialize, Deserialize};
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Div, Mul, MulAssign, Neg, Sub, SubAssign};
+use crate::units::Area;
+use crate::units::Length;
+use crate::units::Volume;
 
-#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
 #[serde(tag = "type")]
 pub struct Vertex2D {
     pub x: f32,
     pub y: f32,
 }
 
-#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
 #[serde(tag = "type")]
 pub struct Vertex3D {
     pub x: f32,
@@ -16,65 +22,643 @@ pub struct Vertex3D {
     pub z: f32,
 }
 
+// The same vector algebra for both vertex types, component by component.
+macro_rules! vector {
+    ($name:ident, $($field:ident),+) => {
+        impl $name {
+            pub const ZERO: $name = $name { $($field: 0.0),+ };
+
+            pub fn dot(self, other: $name) -> f32 {
+                0.0 $(+ self.$field * other.$field)+
+            }
+
+            pub fn length_squared(self) -> f32 {
+                self.dot(self)
+            }
+
+            pub fn length(self) -> f32 {
+                self.length_squared().sqrt()
+            }
+
+            // The zero vector has no direction and stays zero.
+            pub fn normalize(self) -> $name {
+                let length = self.length();
+                if length > 0.0 { self / length } else { self }
+            }
+
+            pub fn distance_squared(self, other: $name) -> f32 {
+                (self - other).length_squared()
+            }
+
+            pub fn distance(self, other: $name) -> f32 {
+                (self - other).length()
+            }
+
+            // self at t = 0, other at t = 1
+            pub fn lerp(self, other: $name, t: f32) -> $name {
+                self + (other - self) * t
+            }
+
+            pub fn min(self, other: $name) -> $name {
+                $name { $($field: self.$field.min(other.$field)),+ }
+            }
+
+            pub fn max(self, other: $name) -> $name {
+                $name { $($field: self.$field.max(other.$field)),+ }
+            }
+
+            pub fn approx_eq(self, other: $name, epsilon: f32) -> bool {
+                true $(&& (self.$field - other.$field).abs() <= epsilon)+
+            }
+        }
+
+        impl Add for $name {
+            type Output = $name;
+
+            fn add(self, other: $name) -> $name {
+                $name { $($field: self.$field + other.$field),+ }
+            }
+        }
+
+        impl Sub for $name {
+            type Output = $name;
+
+            fn sub(self, other: $name) -> $name {
+                $name { $($field: self.$field - other.$field),+ }
+            }
+        }
+
+        impl Neg for $name {
+            type Output = $name;
+
+            fn neg(self) -> $name {
+                $name { $($field: -self.$field),+ }
+            }
+        }
+
+        impl Mul<f32> for $name {
+            type Output = $name;
+
+            fn mul(self, s: f32) -> $name {
+                $name { $($field: self.$field * s),+ }
+            }
+        }
+
+        impl Mul<$name> for f32 {
+            type Output = $name;
+
+            fn mul(self, v: $name) -> $name {
+                v * self
+            }
+        }
+
+        impl Div<f32> for $name {
+            type Output = $name;
+
+            fn div(self, s: f32) -> $name {
+                $name { $($field: self.$field / s),+ }
+            }
+        }
+
+        impl AddAssign for $name {
+            fn add_assign(&mut self, other: $name) {
+                *self = *self + other;
+            }
+        }
+
+        impl SubAssign for $name {
+            fn sub_assign(&mut self, other: $name) {
+                *self = *self - other;
+            }
+        }
+
+        impl MulAssign<f32> for $name {
+            fn mul_assign(&mut self, s: f32) {
+                *self = *self * s;
+            }
+        }
+
+        impl Sum for $name {
+            fn sum<I: Iterator<Item = $name>>(iter: I) -> $name {
+                iter.fold($name::ZERO, |a, b| a + b)
+            }
+        }
+    };
+}
+
+vector!(Vertex2D, x, y);
+vector!(Vertex3D, x, y, z);
+
+impl Vertex2D {
+    // z of the 3D cross product; positive when other is anticlockwise of self
+    pub fn cross(self, other: Vertex2D) -> f32 {
+        self.x * other.y - self.y * other.x
+    }
+}
+
+impl fmt::Display for Vertex2D {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}, {}", self.x, self.y)
+    }
+}
+
+impl fmt::Display for Vertex3D {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}, {}, {}", self.x, self.y, self.z)
+    }
+}
+
 impl Vertex3D {
-    pub fn to_string(&self) -> String {
-        let mut s: String = self.x.to_string().to_owned();
-        s.push_str(", ");
-        s.push_str(&self.y.to_string().to_owned());
-        s.push_str(", ");
-        s.push_str(&self.z.to_string().to_owned());
-        s
+    pub fn cross(self, other: Vertex3D) -> Vertex3D {
+        Vertex3D {
+            x: self.y * other.z - self.z * other.y,
+            y: self.z * other.x - self.x * other.z,
+            z: self.x * other.y - self.y * other.x,
+        }
     }
 
     pub fn is_inside(&self, s: &Shape3D) -> bool {
-        match s {
-            Shape3D::Sphere { center, radius } => {
-                let x1 = std::primitive::f32::powf(self.x - center.x, 2.0);
-                let y1 = std::primitive::f32::powf(self.y - center.y, 2.0);
-                let z1 = std::primitive::f32::powf(self.z - center.z, 2.0);
-                (x1 + y1 + z1) < (radius * radius)
-            },
-            _ => panic!("Unimplemented")
-        }
+        self.excess(s) < 0.0
     }
 
     pub fn is_on(&self, s: &Shape3D) -> bool {
-        match s {
-            Shape3D::Sphere { center, radius } => {
-                let x1 = std::primitive::f32::powf(self.x - center.x, 2.0);
-                let y1 = std::primitive::f32::powf(self.y - center.y, 2.0);
-                let z1 = std::primitive::f32::powf(self.z - center.z, 2.0);
-                (x1 + y1 + z1) == (radius * radius)
-            },
-            _ => panic!("Unimplemented")
-        }
+        self.excess(s) == 0.0
     }
 
     pub fn is_on_or_inside(&self, s: &Shape3D) -> bool {
+        self.excess(s) <= 0.0
+    }
+
+    pub fn is_outside(&self, s: &Shape3D) -> bool {
+        self.excess(s) > 0.0
+    }
+
+    // How far the point is outside the shape's tightest constraint:
+    // negative inside, zero on the surface, positive outside. Not a true
+    // distance, only the sign is meaningful.
+    fn excess(&self, s: &Shape3D) -> f32 {
+        // the point relative to the center, in the primitive's own frame
+        let local = |center: &Vertex3D, orientation: &Quaternion| orientation.conjugate().rotate(*self - *center);
         match s {
             Shape3D::Sphere { center, radius } => {
-                let x1 = std::primitive::f32::powf(self.x - center.x, 2.0);
-                let y1 = std::primitive::f32::powf(self.y - center.y, 2.0);
-                let z1 = std::primitive::f32::powf(self.z - center.z, 2.0);
-                (x1 + y1 + z1) <= (radius * radius)
+                self.distance_squared(*center) - radius * radius
+            },
+            Shape3D::Cube { center, side, orientation } => {
+                let d = local(center, orientation);
+                d.x.abs().max(d.y.abs()).max(d.z.abs()) - side / 2.0
+            },
+            Shape3D::Cuboid { center, width, height, length, orientation } => {
+                let d = local(center, orientation);
+                (d.x.abs() - width / 2.0).max(d.y.abs() - height / 2.0).max(d.z.abs() - length / 2.0)
+            },
+            Shape3D::Cylinder { center, radius, height, orientation } => {
+                let d = local(center, orientation);
+                let radial = Vertex2D { x: d.x, y: d.z }.length();
+                (radial - radius).max(d.y.abs() - height / 2.0)
+            },
+            Shape3D::Cone { center, radius, height, orientation } => {
+                // apex up, base down
+                let d = local(center, orientation);
+                let radial = Vertex2D { x: d.x, y: d.z }.length();
+                let below_apex = height / 2.0 - d.y;
+                (radial - radius * below_apex / height).max(-below_apex).max(below_apex - height)
+            },
+            Shape3D::Polygon3D(poly) => {
+                // flat, so nothing is inside; only points on it count
+                if self.is_on_faces(std::slice::from_ref(poly)) { 0.0 } else { 1.0 }
+            },
+            Shape3D::Polyhedron { faces } => {
+                if !s.aabb().contains_point(*self) {
+                    return 1.0;
+                }
+                if self.is_on_faces(faces) {
+                    return 0.0;
+                }
+                // inside if a ray out of it crosses the surface an odd number
+                // of times, skewed so it's unlikely to run along an edge
+                let direction = Vertex3D { x: 0.4367, y: 0.7681, z: 0.4683 };
+                let crossings = triangles(faces)
+                    .filter_map(|(a, b, c)| ray_triangle(*self, direction, a, b, c))
+                    .filter(|(t, _)| *t > 0.0)
+                    .count();
+                if crossings % 2 == 1 { -1.0 } else { 1.0 }
             },
-            _ => panic!("Unimplemented")
         }
     }
 
-    pub fn is_outside(&self, s: &Shape3D) -> bool {
-        match s {
-            Shape3D::Sphere{ center, radius} => {
-                let x1 = std::primitive::f32::powf(self.x - center.x, 2.0);
-                let y1 = std::primitive::f32::powf(self.y - center.y, 2.0);
-                let z1 = std::primitive::f32::powf(self.z - center.z, 2.0);
-                (x1 + y1 + z1) > (radius * radius)
-            },
-            _ => panic!("Unimplemented")
+    // Whether the point lies in any of the faces, to within a hundredth of a
+    // millimetre.
+    fn is_on_faces(&self, faces: &[Polygon3D]) -> bool {
+        faces.iter().any(|f| {
+            let normal = f.normal();
+            normal != Vertex3D::ZERO && triangles(std::slice::from_ref(f))
+                .filter_map(|(a, b, c)| ray_triangle(*self, normal, a, b, c))
+                .any(|(t, _)| t.abs() <= 1e-5)
+        })
+    }
+
+    pub fn transformed(&self, transform: &Transform) -> Vertex3D {
+        transform.apply(*self)
+    }
+}
+
+// Unit quaternion for orientations.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(tag = "type")]
+pub struct Quaternion {
+    pub w: f32,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Quaternion {
+    pub const IDENTITY: Quaternion = Quaternion { w: 1.0, x: 0.0, y: 0.0, z: 0.0 };
+
+    pub fn from_axis_angle(axis: Vertex3D, angle: f32) -> Quaternion {
+        let (s, c) = (angle / 2.0).sin_cos();
+        let axis = axis.normalize();
+        Quaternion { w: c, x: axis.x * s, y: axis.y * s, z: axis.z * s }
+    }
+
+    // axis times angle, for the short way round
+    pub fn to_rotation_vector(&self) -> Vertex3D {
+        let q = if self.w < 0.0 { self.scaled(-1.0) } else { *self };
+        let v = Vertex3D { x: q.x, y: q.y, z: q.z };
+        let s = v.length();
+        if s < 1e-7 {
+            return v * 2.0;
+        }
+        v * (2.0 * s.atan2(q.w) / s)
+    }
+
+    pub fn conjugate(&self) -> Quaternion {
+        Quaternion { w: self.w, x: -self.x, y: -self.y, z: -self.z }
+    }
+
+    fn scaled(&self, s: f32) -> Quaternion {
+        Quaternion { w: self.w * s, x: self.x * s, y: self.y * s, z: self.z * s }
+    }
+
+    pub fn normalized(&self) -> Quaternion {
+        self.scaled(1.0 / (self.w * self.w + self.x * self.x + self.y * self.y + self.z * self.z).sqrt())
+    }
+
+    pub fn rotate(&self, v: Vertex3D) -> Vertex3D {
+        let u = Vertex3D { x: self.x, y: self.y, z: self.z };
+        let t = u.cross(v) * 2.0;
+        v + t * self.w + u.cross(t)
+    }
+
+    // The shortest turn taking one direction onto another.
+    pub fn from_rotation_arc(from: Vertex3D, to: Vertex3D) -> Quaternion {
+        let (from, to) = (from.normalize(), to.normalize());
+        let d = from.dot(to);
+        if d < -1.0 + 1e-6 {
+            // opposite: half a turn about anything perpendicular
+            let axis = if from.x.abs() < 0.9 { from.cross(Vertex3D { x: 1.0, y: 0.0, z: 0.0 }) } else { from.cross(Vertex3D { x: 0.0, y: 1.0, z: 0.0 }) };
+            return Quaternion::from_axis_angle(axis, PI);
+        }
+        let axis = from.cross(to);
+        Quaternion { w: 1.0 + d, x: axis.x, y: axis.y, z: axis.z }.normalized()
+    }
+
+    pub fn dot(&self, other: Quaternion) -> f32 {
+        self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    // Turns at a steady rate from self at t = 0 to other at t = 1, the short
+    // way round.
+    pub fn slerp(&self, other: Quaternion, t: f32) -> Quaternion {
+        let (other, d) = if self.dot(other) < 0.0 { (other.scaled(-1.0), -self.dot(other)) } else { (other, self.dot(other)) };
+        let mix = |a: f32, b: f32| {
+            Quaternion {
+                w: self.w * a + other.w * b,
+                x: self.x * a + other.x * b,
+                y: self.y * a + other.y * b,
+                z: self.z * a + other.z * b,
+            }.normalized()
+        };
+        if d > 0.9995 {
+            return mix(1.0 - t, t);
+        }
+        let angle = d.acos();
+        mix(((1.0 - t) * angle).sin() / angle.sin(), (t * angle).sin() / angle.sin())
+    }
+
+    // Whether both turn things the same way; q and -q do.
+    pub fn approx_eq(&self, other: Quaternion, epsilon: f32) -> bool {
+        1.0 - self.dot(other).abs() <= epsilon
+    }
+
+    // Turned by a small world-space rotation vector, as when integrating an
+    // angular velocity over a step.
+    pub fn integrated(&self, rotation: Vertex3D) -> Quaternion {
+        let spin = Quaternion { w: 0.0, x: rotation.x, y: rotation.y, z: rotation.z } * *self;
+        let q = spin.scaled(0.5);
+        Quaternion { w: self.w + q.w, x: self.x + q.x, y: self.y + q.y, z: self.z + q.z }.normalized()
+    }
+}
+
+impl std::ops::Mul for Quaternion {
+    type Output = Quaternion;
+
+    // self after other
+    fn mul(self, o: Quaternion) -> Quaternion {
+        Quaternion {
+            w: self.w * o.w - self.x * o.x - self.y * o.y - self.z * o.z,
+            x: self.w * o.x + self.x * o.w + self.y * o.z - self.z * o.y,
+            y: self.w * o.y - self.x * o.z + self.y * o.w + self.z * o.x,
+            z: self.w * o.z + self.x * o.y - self.y * o.x + self.z * o.w,
+        }
+    }
+}
+
+impl Default for Quaternion {
+    fn default() -> Quaternion {
+        Quaternion::IDENTITY
+    }
+}
+
+// Row-major 4x4 matrix acting on column vectors, so a * b applies b first.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(tag = "type")]
+pub struct Mat4 {
+    pub rows: [[f32; 4]; 4],
+}
+
+impl Mat4 {
+    pub const IDENTITY: Mat4 = Mat4 {
+        rows: [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]],
+    };
+
+    pub fn from_translation(offset: Vertex3D) -> Mat4 {
+        let mut m = Mat4::IDENTITY;
+        m.rows[0][3] = offset.x;
+        m.rows[1][3] = offset.y;
+        m.rows[2][3] = offset.z;
+        m
+    }
+
+    pub fn from_scale(scale: Vertex3D) -> Mat4 {
+        let mut m = Mat4::IDENTITY;
+        m.rows[0][0] = scale.x;
+        m.rows[1][1] = scale.y;
+        m.rows[2][2] = scale.z;
+        m
+    }
+
+    pub fn from_rotation(q: Quaternion) -> Mat4 {
+        // columns are where the axes end up
+        let (x, y, z) = (
+            q.rotate(Vertex3D { x: 1.0, y: 0.0, z: 0.0 }),
+            q.rotate(Vertex3D { x: 0.0, y: 1.0, z: 0.0 }),
+            q.rotate(Vertex3D { x: 0.0, y: 0.0, z: 1.0 }),
+        );
+        Mat4 {
+            rows: [[x.x, y.x, z.x, 0.0], [x.y, y.y, z.y, 0.0], [x.z, y.z, z.z, 0.0], [0.0, 0.0, 0.0, 1.0]],
+        }
+    }
+
+    pub fn transpose(&self) -> Mat4 {
+        let mut m = Mat4::IDENTITY;
+        for i in 0 .. 4 {
+            for j in 0 .. 4 {
+                m.rows[i][j] = self.rows[j][i];
+            }
+        }
+        m
+    }
+
+    // Gauss-Jordan elimination with partial pivoting; None if singular.
+    pub fn inverse(&self) -> Option<Mat4> {
+        let mut a = self.rows;
+        let mut inv = Mat4::IDENTITY.rows;
+        for col in 0 .. 4 {
+            let pivot = (col .. 4).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+            if a[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+            let p = a[col][col];
+            for j in 0 .. 4 {
+                a[col][j] /= p;
+                inv[col][j] /= p;
+            }
+            for i in 0 .. 4 {
+                if i != col {
+                    let f = a[i][col];
+                    for j in 0 .. 4 {
+                        a[i][j] -= f * a[col][j];
+                        inv[i][j] -= f * inv[col][j];
+                    }
+                }
+            }
+        }
+        Some(Mat4 { rows: inv })
+    }
+
+    pub fn transform_point(&self, p: Vertex3D) -> Vertex3D {
+        let r = &self.rows;
+        let w = r[3][0] * p.x + r[3][1] * p.y + r[3][2] * p.z + r[3][3];
+        Vertex3D {
+            x: r[0][0] * p.x + r[0][1] * p.y + r[0][2] * p.z + r[0][3],
+            y: r[1][0] * p.x + r[1][1] * p.y + r[1][2] * p.z + r[1][3],
+            z: r[2][0] * p.x + r[2][1] * p.y + r[2][2] * p.z + r[2][3],
+        } / w
+    }
+
+    // Directions ignore the translation.
+    pub fn transform_vector(&self, v: Vertex3D) -> Vertex3D {
+        let r = &self.rows;
+        Vertex3D {
+            x: r[0][0] * v.x + r[0][1] * v.y + r[0][2] * v.z,
+            y: r[1][0] * v.x + r[1][1] * v.y + r[1][2] * v.z,
+            z: r[2][0] * v.x + r[2][1] * v.y + r[2][2] * v.z,
         }
     }
 }
 
+impl std::ops::Mul for Mat4 {
+    type Output = Mat4;
+
+    fn mul(self, o: Mat4) -> Mat4 {
+        let mut m = Mat4 { rows: [[0.0; 4]; 4] };
+        for i in 0 .. 4 {
+            for j in 0 .. 4 {
+                m.rows[i][j] = (0 .. 4).map(|k| self.rows[i][k] * o.rows[k][j]).sum();
+            }
+        }
+        m
+    }
+}
+
+// Scales, then rotates, then translates. The scale is one factor for every
+// axis so that spheres stay spheres and boxes stay boxes.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(tag = "type")]
+pub struct Transform {
+    pub translation: Vertex3D,
+    pub rotation: Quaternion,
+    pub scale: f32,
+}
+
+impl Default for Transform {
+    fn default() -> Transform {
+        Transform::IDENTITY
+    }
+}
+
+impl Transform {
+    pub const IDENTITY: Transform = Transform { translation: Vertex3D::ZERO, rotation: Quaternion::IDENTITY, scale: 1.0 };
+
+    pub fn new(translation: Vertex3D, rotation: Quaternion, scale: f32) -> Transform {
+        Transform { translation, rotation, scale }
+    }
+
+    pub fn from_translation(translation: Vertex3D) -> Transform {
+        Transform { translation, ..Transform::IDENTITY }
+    }
+
+    pub fn from_rotation(rotation: Quaternion) -> Transform {
+        Transform { rotation, ..Transform::IDENTITY }
+    }
+
+    pub fn from_scale(scale: f32) -> Transform {
+        Transform { scale, ..Transform::IDENTITY }
+    }
+
+    pub fn apply(&self, point: Vertex3D) -> Vertex3D {
+        self.translation + self.apply_vector(point)
+    }
+
+    pub fn apply_vector(&self, v: Vertex3D) -> Vertex3D {
+        self.rotation.rotate(v * self.scale)
+    }
+
+    pub fn inverse(&self) -> Transform {
+        let rotation = self.rotation.conjugate();
+        let scale = 1.0 / self.scale;
+        Transform { translation: rotation.rotate(-self.translation) * scale, rotation, scale }
+    }
+
+    pub fn to_matrix(&self) -> Mat4 {
+        Mat4::from_translation(self.translation)
+            * Mat4::from_rotation(self.rotation)
+            * Mat4::from_scale(Vertex3D { x: self.scale, y: self.scale, z: self.scale })
+    }
+}
+
+impl std::ops::Mul for Transform {
+    type Output = Transform;
+
+    // self after other
+    fn mul(self, o: Transform) -> Transform {
+        Transform {
+            translation: self.apply(o.translation),
+            rotation: self.rotation * o.rotation,
+            scale: self.scale * o.scale,
+        }
+    }
+}
+
+// Axis-aligned box from min to max corner. EMPTY has min above max, so it
+// contains and meets nothing and is the starting point for a union.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(tag = "type")]
+pub struct Aabb {
+    pub min: Vertex3D,
+    pub max: Vertex3D,
+}
+
+impl Aabb {
+    pub const EMPTY: Aabb = Aabb {
+        min: Vertex3D { x: f32::INFINITY, y: f32::INFINITY, z: f32::INFINITY },
+        max: Vertex3D { x: f32::NEG_INFINITY, y: f32::NEG_INFINITY, z: f32::NEG_INFINITY },
+    };
+
+    pub fn new(a: Vertex3D, b: Vertex3D) -> Aabb {
+        Aabb { min: a.min(b), max: a.max(b) }
+    }
+
+    pub fn around(center: Vertex3D, half: Vertex3D) -> Aabb {
+        Aabb::new(center - half, center + half)
+    }
+
+    pub fn from_points<'a, I: IntoIterator<Item = &'a Vertex3D>>(points: I) -> Aabb {
+        points.into_iter().fold(Aabb::EMPTY, |b, p| b.including(*p))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    pub fn center(&self) -> Vertex3D {
+        self.min.lerp(self.max, 0.5)
+    }
+
+    pub fn size(&self) -> Vertex3D {
+        if self.is_empty() { Vertex3D::ZERO } else { self.max - self.min }
+    }
+
+    pub fn volume(&self) -> Volume {
+        let size = self.size();
+        Volume::cubic_meters(size.x * size.y * size.z)
+    }
+
+    pub fn including(&self, p: Vertex3D) -> Aabb {
+        Aabb { min: self.min.min(p), max: self.max.max(p) }
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb { min: self.min.min(other.min), max: self.max.max(other.max) }
+    }
+
+    // None when the boxes don't meet; boxes that only touch share a flat box.
+    pub fn intersection(&self, other: &Aabb) -> Option<Aabb> {
+        let overlap = Aabb { min: self.min.max(other.min), max: self.max.min(other.max) };
+        if overlap.is_empty() { None } else { Some(overlap) }
+    }
+
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.intersection(other).is_some()
+    }
+
+    pub fn contains_point(&self, p: Vertex3D) -> bool {
+        p.x >= self.min.x && p.x <= self.max.x
+            && p.y >= self.min.y && p.y <= self.max.y
+            && p.z >= self.min.z && p.z <= self.max.z
+    }
+
+    pub fn contains(&self, other: &Aabb) -> bool {
+        other.is_empty() || (self.contains_point(other.min) && self.contains_point(other.max))
+    }
+
+    // Distance along the ray to where it enters the box, 0 if it starts
+    // inside. The direction need not be normalized; distances are in its
+    // units.
+    pub fn ray_intersect(&self, origin: Vertex3D, direction: Vertex3D, max_distance: f32) -> Option<f32> {
+        let (enter, exit, _) = slabs(origin - self.center(), direction, self.size() / 2.0)?;
+        let enter = enter.max(0.0);
+        if exit < enter || enter > max_distance { None } else { Some(enter) }
+    }
+
+    pub fn expanded(&self, margin: f32) -> Aabb {
+        let m = Vertex3D { x: margin, y: margin, z: margin };
+        Aabb { min: self.min - m, max: self.max + m }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct RayHit {
+    // metres along the ray from its origin
+    pub distance: f32,
+    pub point: Vertex3D,
+    // unit surface normal, facing back along the ray
+    pub normal: Vertex3D,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(tag = "type")]
 pub enum Shape2D {
@@ -96,41 +680,148 @@ pub enum Shape2D {
     },
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 #[serde(tag = "type")]
 pub struct Polygon3D {
     pub vertices: Vec<Vertex3D>,
 }
 
 impl Polygon3D {
-    pub fn surface_area(self) -> f32 {
-        Shape3D::Polygon3D(self).surface_area()
+    // Newell's method: perpendicular to the polygon with length twice its
+    // area, whichever plane it's in, and the best fit for one that isn't
+    // quite flat. Taken about the centroid to keep the sums small.
+    fn newell(&self) -> Vertex3D {
+        let c = self.centroid();
+        let n = self.vertices.len();
+        (0 .. n).map(|i| {
+            let (a, b) = (self.vertices[i] - c, self.vertices[(i + 1) % n] - c);
+            Vertex3D { x: (a.y - b.y) * (a.z + b.z), y: (a.z - b.z) * (a.x + b.x), z: (a.x - b.x) * (a.y + b.y) }
+        }).sum()
+    }
+
+    // Mean of the vertices.
+    pub fn centroid(&self) -> Vertex3D {
+        if self.vertices.is_empty() {
+            return Vertex3D::ZERO;
+        }
+        self.vertices.iter().copied().sum::<Vertex3D>() / self.vertices.len() as f32
+    }
+
+    // Always positive, whichever way round the vertices go.
+    pub fn surface_area(&self) -> Area {
+        Area::square_meters(self.newell().length() / 2.0)
+    }
+
+    // Unit normal, pointing the way from which the vertices run
+    // anticlockwise. Zero if the polygon has no area.
+    pub fn normal(&self) -> Vertex3D {
+        self.newell().normalize()
+    }
+
+    // The plane through the centroid facing along the normal, or none if the
+    // polygon has no area.
+    pub fn plane(&self) -> Option<Plane> {
+        let normal = self.normal();
+        if normal == Vertex3D::ZERO {
+            return None;
+        }
+        Some(Plane { normal, offset: normal.dot(self.centroid()) })
+    }
+
+    // Whether every vertex is within tolerance metres of the plane. Points and
+    // lines lie in a plane whichever way it faces, so count as flat.
+    pub fn is_planar(&self, tolerance: f32) -> bool {
+        match self.plane() {
+            Some(plane) => self.vertices.iter().all(|v| plane.distance_to(*v).abs() <= tolerance),
+            None => true,
+        }
+    }
+
+    // Which way round the vertices run, seen from the side `towards` points
+    // to. None if the polygon has no area or is seen edge on.
+    pub fn winding(&self, towards: Vertex3D) -> Option<Winding> {
+        let facing = self.newell().dot(towards);
+        if facing > 0.0 {
+            Some(Winding::Anticlockwise)
+        } else if facing < 0.0 {
+            Some(Winding::Clockwise)
+        } else {
+            None
+        }
+    }
+
+    pub fn reversed(&self) -> Polygon3D {
+        Polygon3D { vertices: self.vertices.iter().rev().copied().collect() }
+    }
+
+    // The same polygon, reversed if need be so it winds the given way seen
+    // from `towards`.
+    pub fn wound(self, winding: Winding, towards: Vertex3D) -> Polygon3D {
+        match self.winding(towards) {
+            Some(w) if w != winding => self.reversed(),
+            _ => self,
+        }
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Winding {
+    Clockwise,
+    Anticlockwise,
+}
+
+// The points p with normal . p = offset. The normal is a unit vector.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Plane {
+    pub normal: Vertex3D,
+    pub offset: f32,
+}
+
+impl Plane {
+    // Positive on the side the normal points to.
+    pub fn distance_to(&self, point: Vertex3D) -> f32 {
+        self.normal.dot(point) - self.offset
+    }
+
+    pub fn project(&self, point: Vertex3D) -> Vertex3D {
+        point - self.normal * self.distance_to(point)
+    }
+}
+
+// Dimensions and coordinates are in metres. Orientations turn a primitive
+// about its center from the axis-aligned pose the dimensions describe.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 #[serde(tag = "type")]
 pub enum Shape3D {
     Cube {
         center: Vertex3D,
-        side: f32
+        side: f32,
+        #[serde(default)]
+        orientation: Quaternion,
     },
     Cuboid {
         center: Vertex3D,
         width: f32,
         height: f32,
         length: f32,
+        #[serde(default)]
+        orientation: Quaternion,
     },
     Cone {
         center: Vertex3D,
         radius: f32,
-        height: f32
+        height: f32,
+        #[serde(default)]
+        orientation: Quaternion,
     },
     Cylinder {
         center: Vertex3D,
         radius: f32,
-        height: f32
+        height: f32,
+        #[serde(default)]
+        orientation: Quaternion,
     },
+    // looks the same however it's turned, so has no orientation
     Sphere {
         center: Vertex3D,
         radius: f32
@@ -142,68 +833,233 @@ pub enum Shape3D {
 }
 
 impl Shape3D {
-    pub fn diameter(&self) -> f32 {
+    pub fn translated(&self, offset: Vertex3D) -> Shape3D {
+        self.transformed(&Transform::from_translation(offset))
+    }
+
+    pub fn orientation(&self) -> Quaternion {
+        match self {
+            Shape3D::Cube { orientation, .. } | Shape3D::Cuboid { orientation, .. }
+            | Shape3D::Cone { orientation, .. } | Shape3D::Cylinder { orientation, .. } => *orientation,
+            Shape3D::Sphere { .. } | Shape3D::Polygon3D(_) | Shape3D::Polyhedron { .. } => Quaternion::IDENTITY,
+        }
+    }
+
+    // The shape moved, turned and resized as a whole; a primitive turns
+    // about the transform's origin, not its own center.
+    pub fn transformed(&self, transform: &Transform) -> Shape3D {
+        let mut shape = self.clone();
+        match &mut shape {
+            Shape3D::Cube { center, side, orientation } => {
+                *side *= transform.scale;
+                *center = transform.apply(*center);
+                *orientation = transform.rotation * *orientation;
+            },
+            Shape3D::Cuboid { center, width, height, length, orientation } => {
+                *width *= transform.scale;
+                *height *= transform.scale;
+                *length *= transform.scale;
+                *center = transform.apply(*center);
+                *orientation = transform.rotation * *orientation;
+            },
+            Shape3D::Cone { center, radius, height, orientation }
+            | Shape3D::Cylinder { center, radius, height, orientation } => {
+                *radius *= transform.scale;
+                *height *= transform.scale;
+                *center = transform.apply(*center);
+                *orientation = transform.rotation * *orientation;
+            },
+            Shape3D::Sphere { center, radius } => {
+                *radius *= transform.scale;
+                *center = transform.apply(*center);
+            },
+            Shape3D::Polygon3D(poly) => {
+                for v in poly.vertices.iter_mut() {
+                    *v = transform.apply(*v);
+                }
+            },
+            Shape3D::Polyhedron { faces } => {
+                for v in faces.iter_mut().flat_map(|f| f.vertices.iter_mut()) {
+                    *v = transform.apply(*v);
+                }
+            },
+        }
+        shape
+    }
+
+    // Tightest axis-aligned box around the shape, turned or not.
+    pub fn aabb(&self) -> Aabb {
+        match self {
+            Shape3D::Sphere { center, radius } => {
+                Aabb::around(*center, Vertex3D { x: *radius, y: *radius, z: *radius })
+            },
+            Shape3D::Cube { center, side, orientation } => {
+                Aabb::around(*center, box_reach(orientation, Vertex3D { x: *side, y: *side, z: *side } / 2.0))
+            },
+            Shape3D::Cuboid { center, width, height, length, orientation } => {
+                Aabb::around(*center, box_reach(orientation, Vertex3D { x: *width, y: *height, z: *length } / 2.0))
+            },
+            Shape3D::Cylinder { center, radius, height, orientation } => {
+                let axis = orientation.rotate(Vertex3D { x: 0.0, y: 1.0, z: 0.0 });
+                let ends = Aabb::new(*center - axis * (height / 2.0), *center + axis * (height / 2.0));
+                let rim = disc_reach(axis, *radius);
+                Aabb { min: ends.min - rim, max: ends.max + rim }
+            },
+            Shape3D::Cone { center, radius, height, orientation } => {
+                let axis = orientation.rotate(Vertex3D { x: 0.0, y: 1.0, z: 0.0 });
+                let base = *center - axis * (height / 2.0);
+                let rim = disc_reach(axis, *radius);
+                Aabb { min: base - rim, max: base + rim }.including(*center + axis * (height / 2.0))
+            },
+            Shape3D::Polygon3D(poly) => Aabb::from_points(poly.vertices.iter()),
+            Shape3D::Polyhedron { faces } => Aabb::from_points(faces.iter().flat_map(|f| f.vertices.iter())),
+        }
+    }
+
+    // First point where the ray meets the shape within max_distance. A ray
+    // starting inside a solid hits at distance 0. Polygons and polyhedra are
+    // surfaces, tested a triangle at a time and fanned from each face's
+    // first vertex, so faces should be convex.
+    pub fn ray_intersect(&self, origin: Vertex3D, direction: Vertex3D, max_distance: f32) -> Option<RayHit> {
+        let direction = direction.normalize();
+        if direction == Vertex3D::ZERO {
+            return None;
+        }
+        let faces: &[Polygon3D] = match self {
+            Shape3D::Polygon3D(poly) => std::slice::from_ref(poly),
+            Shape3D::Polyhedron { faces } => faces,
+            _ => &[],
+        };
+        let (distance, normal) = match self {
+            Shape3D::Polygon3D(_) | Shape3D::Polyhedron { .. } => {
+                triangles(faces)
+                    .filter_map(|(a, b, c)| ray_triangle(origin, direction, a, b, c))
+                    .filter(|(t, _)| *t >= 0.0)
+                    .min_by(|x, y| x.0.total_cmp(&y.0))?
+            },
+            _ if origin.is_on_or_inside(self) => (0.0, -direction),
+            Shape3D::Sphere { center, radius } => {
+                let offset = origin - *center;
+                let b = offset.dot(direction);
+                // measured from the line rather than as b^2 - c, which loses
+                // the answer when a small sphere is a long way off
+                let disc = radius * radius - (offset - direction * b).length_squared();
+                if disc < 0.0 {
+                    return None;
+                }
+                let t = -b - disc.sqrt();
+                (t, (origin + direction * t - *center) / *radius)
+            },
+            _ => {
+                let orientation = self.orientation();
+                let center = match self {
+                    Shape3D::Cube { center, .. } | Shape3D::Cuboid { center, .. }
+                    | Shape3D::Cylinder { center, .. } | Shape3D::Cone { center, .. } => *center,
+                    _ => unreachable!(),
+                };
+                let d = orientation.conjugate().rotate(direction);
+                // solve from level with the centre, so the numbers are on the
+                // shape's own scale however far off the ray starts
+                let o = orientation.conjugate().rotate(origin - center);
+                let skip = -o.dot(d);
+                let o = o + d * skip;
+                let (t, n) = match self {
+                    Shape3D::Cube { side, .. } => ray_box(o, d, Vertex3D { x: *side, y: *side, z: *side } / 2.0, -skip)?,
+                    Shape3D::Cuboid { width, height, length, .. } => {
+                        ray_box(o, d, Vertex3D { x: *width, y: *height, z: *length } / 2.0, -skip)?
+                    },
+                    Shape3D::Cylinder { radius, height, .. } => ray_cylinder(o, d, *radius, *height, -skip)?,
+                    Shape3D::Cone { radius, height, .. } => ray_cone(o, d, *radius, *height, -skip)?,
+                    _ => unreachable!(),
+                };
+                let t = t + skip;
+                (t, orientation.rotate(n))
+            },
+        };
+        if distance < 0.0 || distance > max_distance {
+            return None;
+        }
+        let normal = normal.normalize();
+        let normal = if normal.dot(direction) > 0.0 { -normal } else { normal };
+        Some(RayHit { distance, point: origin + direction * distance, normal })
+    }
+
+    // A sphere around the whole shape, centred on the primitive's center or
+    // the middle of a polygon's box. Not the smallest possible for cones or
+    // polygons, but never too small.
+    pub fn bounding_sphere(&self) -> Shape3D {
+        let (center, radius) = match self {
+            Shape3D::Sphere { center, radius } => (*center, *radius),
+            Shape3D::Cube { center, side, .. } => (*center, side * 3f32.sqrt() / 2.0),
+            Shape3D::Cuboid { center, width, height, length, .. } => {
+                (*center, Vertex3D { x: *width, y: *height, z: *length }.length() / 2.0)
+            },
+            Shape3D::Cylinder { center, radius, height, .. } | Shape3D::Cone { center, radius, height, .. } => {
+                (*center, Vertex2D { x: *radius, y: height / 2.0 }.length())
+            },
+            Shape3D::Polygon3D(poly) => {
+                let center = Aabb::from_points(poly.vertices.iter()).center();
+                (center, poly.vertices.iter().map(|v| v.distance(center)).fold(0.0, f32::max))
+            },
+            Shape3D::Polyhedron { faces } => {
+                let vertices = || faces.iter().flat_map(|f| f.vertices.iter());
+                let center = Aabb::from_points(vertices()).center();
+                (center, vertices().map(|v| v.distance(center)).fold(0.0, f32::max))
+            },
+        };
+        Shape3D::Sphere { center, radius }
+    }
+
+    pub fn diameter(&self) -> Length {
         match self {
             Shape3D::Sphere { center: _, radius } => {
-                2.0 * radius
+                Length::meters(2.0 * radius)
             },
             _ => {
                 panic!("Unimplemented")
             }
         }
     }
-    pub fn surface_area(&self) -> f32 {
-        match self {
+    pub fn surface_area(&self) -> Area {
+        Area::square_meters(match self {
             Shape3D::Sphere { center:_, radius } => {
                 4.0 * PI * radius.powf(2.0)
             },
 
-            Shape3D::Cylinder { center:_, radius, height } => {
+            Shape3D::Cylinder { center:_, radius, height, .. } => {
                 2.0 * PI * radius * (height + radius)
             },
 
-            Shape3D::Cone { center: _, radius, height } => {
+            Shape3D::Cone { center: _, radius, height, .. } => {
                 let l = (height.powf(2.0) + radius.powf(2.0)).sqrt();
                 PI * radius * (l + radius)
             },
 
-            Shape3D::Cube { center: _, side } => {
+            Shape3D::Cube { center: _, side, .. } => {
                 6.0 * side.powf(2.0)
             },
 
-            Shape3D::Cuboid { center: _, width, height, length } => {
+            Shape3D::Cuboid { center: _, width, height, length, .. } => {
                 2.0 * ((length * width) + (width * height) + (length * height))
             },
 
             Shape3D::Polygon3D(poly) => {
-                let mut area: f32 = 0.0;
-                let mut j = poly.vertices.len() - 1;
-                for i in 0 .. poly.vertices.len() {
-                    let vi = poly.vertices[i];
-                    let vj = poly.vertices[j];
-                    area += (vj.x + vi.x) * (vj.y - vi.y);
-                    j = i;
-                }
-                area / 2.0
+                poly.surface_area().in_square_meters()
             },
 
             Shape3D::Polyhedron { faces } => {
-                let mut area: f32 = 0.0;
-                for f in faces {
-                    area += f.clone().surface_area();
-                }
-                area
+                faces.iter().map(|f| f.surface_area().in_square_meters()).sum()
             },
-        }
+        })
     }
 
-    pub fn volume(&self) -> f32 {
-        match self {
-            Shape3D::Cube { center:_, side } => {
+    pub fn volume(&self) -> Volume {
+        Volume::cubic_meters(match self {
+            Shape3D::Cube { center:_, side, .. } => {
                 side.powf(3.0)
             },
 
-            Shape3D::Cylinder { center:_, radius, height } => {
+            Shape3D::Cylinder { center:_, radius, height, .. } => {
                 PI * radius.powf(2.0) * height
             },
 
@@ -211,25 +1067,717 @@ impl Shape3D {
                 4.0 / 3.0 * PI * radius.powf(3.0)
             },
 
-            Shape3D::Cone { center:_, radius, height } => {
+            Shape3D::Cone { center:_, radius, height, .. } => {
                 1.0 / 3.0 * PI * radius.powf(2.0) * height
             },
 
-            Shape3D::Cuboid { center: _, width, height, length } => {
+            Shape3D::Cuboid { center: _, width, height, length, .. } => {
                 length * width * height
             },
 
             _ => panic!("Unimplemented"),
+        })
+    }
+}
+
+
+// Half extents along the world axes of a box with the given half extents,
+// turned by the orientation.
+fn box_reach(orientation: &Quaternion, half: Vertex3D) -> Vertex3D {
+    let axes = [
+        orientation.rotate(Vertex3D { x: half.x, y: 0.0, z: 0.0 }),
+        orientation.rotate(Vertex3D { x: 0.0, y: half.y, z: 0.0 }),
+        orientation.rotate(Vertex3D { x: 0.0, y: 0.0, z: half.z }),
+    ];
+    axes.iter().fold(Vertex3D::ZERO, |r, a| r + Vertex3D { x: a.x.abs(), y: a.y.abs(), z: a.z.abs() })
+}
+
+// How far a disc of the given radius, facing along the unit axis, reaches
+// from its center along each world axis.
+fn disc_reach(axis: Vertex3D, radius: f32) -> Vertex3D {
+    // radius * sqrt(1 - a^2) for each component a, without the cancellation
+    let reach = |a: f32, b: f32| radius * Vertex2D { x: a, y: b }.length();
+    Vertex3D { x: reach(axis.y, axis.z), y: reach(axis.x, axis.z), z: reach(axis.x, axis.y) }
+}
+
+
+// Nearest of the shapes the ray hits, and which one it was.
+pub fn ray_cast(shapes: &[Shape3D], origin: Vertex3D, direction: Vertex3D, max_distance: f32) -> Option<(usize, RayHit)> {
+    let direction = direction.normalize();
+    let mut nearest: Option<(usize, RayHit)> = None;
+    for (i, shape) in shapes.iter().enumerate() {
+        let limit = nearest.map_or(max_distance, |(_, hit)| hit.distance);
+        // skip anything whose box starts further away than the best so far
+        if shape.aabb().ray_intersect(origin, direction, limit).is_none() {
+            continue;
+        }
+        if let Some(hit) = shape.ray_intersect(origin, direction, limit) {
+            if nearest.is_none_or(|(_, best)| hit.distance < best.distance) {
+                nearest = Some((i, hit));
+            }
+        }
+    }
+    nearest
+}
+
+// Where a ray crosses the slabs of a box centred on the origin: the entry
+// and exit distances and the normal of the face it enters by.
+fn slabs(origin: Vertex3D, direction: Vertex3D, half: Vertex3D) -> Option<(f32, f32, Vertex3D)> {
+    let (o, d, h) = ([origin.x, origin.y, origin.z], [direction.x, direction.y, direction.z], [half.x, half.y, half.z]);
+    let (mut enter, mut exit, mut normal) = (f32::NEG_INFINITY, f32::INFINITY, Vertex3D::ZERO);
+    for i in 0 .. 3 {
+        if d[i] == 0.0 {
+            if o[i].abs() > h[i] {
+                return None;
+            }
+            continue;
+        }
+        let (t1, t2) = ((-h[i] - o[i]) / d[i], (h[i] - o[i]) / d[i]);
+        let (near, far, sign) = if t1 < t2 { (t1, t2, -1.0) } else { (t2, t1, 1.0) };
+        if near > enter {
+            enter = near;
+            let mut n = [0.0; 3];
+            n[i] = sign;
+            normal = Vertex3D { x: n[0], y: n[1], z: n[2] };
         }
+        exit = exit.min(far);
+    }
+    if enter > exit { None } else { Some((enter, exit, normal)) }
+}
+
+// The rest take the ray in the primitive's own frame and return the distance
+// to where it first crosses the surface at or beyond `from`, and the normal
+// there. The ray must start outside the primitive.
+
+fn ray_box(origin: Vertex3D, direction: Vertex3D, half: Vertex3D, from: f32) -> Option<(f32, Vertex3D)> {
+    let (enter, exit, normal) = slabs(origin, direction, half)?;
+    if exit < from { None } else { Some((enter, normal)) }
+}
+
+fn nearest_ahead(candidates: impl Iterator<Item = (f32, Vertex3D)>, from: f32) -> Option<(f32, Vertex3D)> {
+    candidates.filter(|(t, _)| *t >= from && t.is_finite()).min_by(|x, y| x.0.total_cmp(&y.0))
+}
+
+// Roots of a t^2 + b t + c, or the one root when a is zero.
+fn roots(a: f32, b: f32, c: f32) -> Vec<f32> {
+    if a.abs() < 1e-9 {
+        return if b.abs() < 1e-9 { Vec::new() } else { vec![-c / b] };
+    }
+    let disc = b * b - 4.0 * a * c;
+    if disc < 0.0 {
+        return Vec::new();
     }
+    let root = disc.sqrt();
+    vec![(-b - root) / (2.0 * a), (-b + root) / (2.0 * a)]
+}
+
+// axis along y, center halfway up
+fn ray_cylinder(o: Vertex3D, d: Vertex3D, radius: f32, height: f32, from: f32) -> Option<(f32, Vertex3D)> {
+    let at = |t: f32| o + d * t;
+    let side = roots(d.x * d.x + d.z * d.z, 2.0 * (o.x * d.x + o.z * d.z), o.x * o.x + o.z * o.z - radius * radius)
+        .into_iter()
+        .filter(|t| at(*t).y.abs() <= height / 2.0)
+        .map(|t| (t, Vertex3D { x: at(t).x, y: 0.0, z: at(t).z }));
+    nearest_ahead(side.chain(caps(o, d, radius, &[height / 2.0, -height / 2.0])), from)
+}
+
+// apex up at height / 2, base down
+fn ray_cone(o: Vertex3D, d: Vertex3D, radius: f32, height: f32, from: f32) -> Option<(f32, Vertex3D)> {
+    let at = |t: f32| o + d * t;
+    // x^2 + z^2 = (k s)^2, where s is the distance below the apex
+    let k = radius / height;
+    let s0 = height / 2.0 - o.y;
+    let a = d.x * d.x + d.z * d.z - k * k * d.y * d.y;
+    let b = 2.0 * (o.x * d.x + o.z * d.z + k * k * s0 * d.y);
+    let c = o.x * o.x + o.z * o.z - k * k * s0 * s0;
+    let side = roots(a, b, c)
+        .into_iter()
+        .filter(|t| (0.0 ..= height).contains(&(height / 2.0 - at(*t).y)))
+        .map(|t| {
+            let p = at(t);
+            let normal = Vertex3D { x: p.x, y: k * k * (height / 2.0 - p.y), z: p.z };
+            // the apex itself has no slope, so it faces straight up
+            (t, if normal == Vertex3D::ZERO { Vertex3D { x: 0.0, y: 1.0, z: 0.0 } } else { normal })
+        });
+    nearest_ahead(side.chain(caps(o, d, radius, &[-height / 2.0])), from)
+}
+
+// Flat circular ends at the given heights.
+fn caps<'a>(o: Vertex3D, d: Vertex3D, radius: f32, heights: &'a [f32]) -> impl Iterator<Item = (f32, Vertex3D)> + 'a {
+    heights.iter().filter(move |_| d.y != 0.0).filter_map(move |y| {
+        let t = (y - o.y) / d.y;
+        let p = o + d * t;
+        let normal = Vertex3D { x: 0.0, y: y.signum(), z: 0.0 };
+        if p.x * p.x + p.z * p.z <= radius * radius { Some((t, normal)) } else { None }
+    })
+}
+
+// Each face fanned from its first vertex, so faces should be convex.
+fn triangles(faces: &[Polygon3D]) -> impl Iterator<Item = (Vertex3D, Vertex3D, Vertex3D)> + '_ {
+    faces.iter().flat_map(|f| {
+        f.vertices.iter().skip(1).zip(f.vertices.iter().skip(2)).map(move |(b, c)| (f.vertices[0], *b, *c))
+    })
+}
+
+// Möller–Trumbore: distance along the ray to the triangle and the
+// triangle's normal, either way up.
+fn ray_triangle(origin: Vertex3D, direction: Vertex3D, a: Vertex3D, b: Vertex3D, c: Vertex3D) -> Option<(f32, Vertex3D)> {
+    let (e1, e2) = (b - a, c - a);
+    let p = direction.cross(e2);
+    let det = e1.dot(p);
+    // parallel to the triangle, or the triangle has no area
+    if det.abs() <= 1e-7 * e1.length() * e2.length() {
+        return None;
+    }
+    let s = origin - a;
+    let u = s.dot(p) / det;
+    if !(0.0 ..= 1.0).contains(&u) {
+        return None;
+    }
+    let q = s.cross(e1);
+    let v = direction.dot(q) / det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    Some((e2.dot(q) / det, e1.cross(e2)))
 }
 
 
 #[cfg(test)]
 mod tests {
-    // #[test]
-    // fn it_works() {
-    //     let result = 2 + 2;
-    //     assert_eq!(result, 4);
-    // }
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn measurements_carry_units() {
+        let sphere = Shape3D::Sphere { center: Vertex3D::ZERO, radius: 0.5 };
+        assert_eq!(sphere.diameter(), Length::centimeters(100.0));
+        let cube = Shape3D::Cube { center: Vertex3D::ZERO, side: 0.1, orientation: Quaternion::IDENTITY };
+        assert!((cube.volume().in_liters() - 1.0).abs() < 1e-4);
+        assert!((cube.surface_area().in_square_centimeters() - 600.0).abs() < 1e-2);
+        let cuboid = Shape3D::Cuboid { center: Vertex3D::ZERO, width: 2.0, height: 3.0, length: 4.0, orientation: Quaternion::IDENTITY };
+        assert_eq!(cuboid.volume(), Length::meters(2.0) * Length::meters(3.0) * Length::meters(4.0));
+    }
+
+    #[test]
+    fn quaternion_rotation() {
+        let v = |x, y, z| Vertex3D { x, y, z };
+        let quarter = Quaternion::from_axis_angle(v(0.0, 1.0, 0.0), PI / 2.0);
+        let turned = quarter.rotate(v(1.0, 0.0, 0.0));
+        assert!((turned - v(0.0, 0.0, -1.0)).length() < 1e-6);
+        let half = quarter * quarter;
+        assert!((half.rotate(v(1.0, 0.0, 0.0)) - v(-1.0, 0.0, 0.0)).length() < 1e-6);
+        assert!((quarter.conjugate().rotate(turned) - v(1.0, 0.0, 0.0)).length() < 1e-6);
+        let rotation = quarter.to_rotation_vector();
+        assert!((rotation - v(0.0, PI / 2.0, 0.0)).length() < 1e-6);
+        // a hundred small steps make up the same turn
+        let mut q = Quaternion::IDENTITY;
+        for _ in 0 .. 100 {
+            q = q.integrated(v(0.0, PI / 200.0, 0.0));
+        }
+        assert!((q.rotate(v(1.0, 0.0, 0.0)) - turned).length() < 1e-3);
+    }
+
+    #[test]
+    fn primitive_containment() {
+        let v = |x, y, z| Vertex3D { x, y, z };
+        let cuboid = Shape3D::Cuboid { center: v(1.0, 0.0, 0.0), width: 2.0, height: 4.0, length: 6.0, orientation: Quaternion::IDENTITY };
+        assert!(v(1.5, 1.9, -2.9).is_inside(&cuboid));
+        assert!(v(2.0, 0.0, 0.0).is_on(&cuboid));
+        assert!(v(2.1, 0.0, 0.0).is_outside(&cuboid));
+        let cylinder = Shape3D::Cylinder { center: Vertex3D::ZERO, radius: 1.0, height: 2.0, orientation: Quaternion::IDENTITY };
+        assert!(v(0.6, 0.9, 0.6).is_inside(&cylinder));
+        assert!(v(0.8, 0.0, 0.8).is_outside(&cylinder));
+        assert!(v(0.0, 1.0, 0.0).is_on_or_inside(&cylinder));
+        // apex at y = 1, base radius 1 at y = -1
+        let cone = Shape3D::Cone { center: Vertex3D::ZERO, radius: 1.0, height: 2.0, orientation: Quaternion::IDENTITY };
+        assert!(v(0.4, -0.1, 0.0).is_inside(&cone));
+        assert!(v(0.6, 0.0, 0.0).is_outside(&cone));
+        assert!(v(0.0, 1.0, 0.0).is_on(&cone));
+        assert!(v(0.0, -1.1, 0.0).is_outside(&cone));
+        assert!(v(0.49, 0.49, 0.49).is_inside(&Shape3D::Cube { center: Vertex3D::ZERO, side: 1.0, orientation: Quaternion::IDENTITY }));
+    }
+    #[test]
+    fn vector_algebra() {
+        let a = Vertex3D { x: 1.0, y: 2.0, z: 3.0 };
+        let b = Vertex3D { x: -2.0, y: 0.5, z: 4.0 };
+        assert_eq!(a + b, Vertex3D { x: -1.0, y: 2.5, z: 7.0 });
+        assert_eq!(a - b, Vertex3D { x: 3.0, y: 1.5, z: -1.0 });
+        assert_eq!(-a, a * -1.0);
+        assert_eq!(2.0 * a, a + a);
+        assert_eq!(a.dot(b), 11.0);
+        assert_eq!(a.cross(b), Vertex3D { x: 6.5, y: -10.0, z: 4.5 });
+        assert_eq!(Vertex3D { x: 3.0, y: 4.0, z: 0.0 }.distance(Vertex3D::ZERO), 5.0);
+        assert_eq!(a.lerp(b, 0.5), Vertex3D { x: -0.5, y: 1.25, z: 3.5 });
+        assert_eq!(a.min(b), Vertex3D { x: -2.0, y: 0.5, z: 3.0 });
+        assert_eq!(a.max(b), Vertex3D { x: 1.0, y: 2.0, z: 4.0 });
+        assert_eq!(Vertex3D::ZERO.normalize(), Vertex3D::ZERO);
+        assert!(a.approx_eq(Vertex3D { x: 1.0005, y: 2.0, z: 2.9995 }, 1e-3));
+        assert!(!a.approx_eq(b, 1e-3));
+        assert_eq!(a.to_string(), "1, 2, 3");
+        assert_eq!([a, b, a].into_iter().sum::<Vertex3D>(), a + b + a);
+
+        let p = Vertex2D { x: 3.0, y: 4.0 };
+        assert_eq!(p.length(), 5.0);
+        assert_eq!(p.cross(Vertex2D { x: 0.0, y: 1.0 }), 3.0);
+        assert_eq!(p.to_string(), "3, 4");
+    }
+
+    #[test]
+    fn rotated_primitives() {
+        let v = |x, y, z| Vertex3D { x, y, z };
+        // a crate tipped 45 degrees onto an edge
+        let tilt = Quaternion::from_axis_angle(v(0.0, 0.0, 1.0), PI / 4.0);
+        let crate_box = Shape3D::Cuboid { center: Vertex3D::ZERO, width: 2.0, height: 2.0, length: 2.0, orientation: tilt };
+        assert!(v(0.0, 1.3, 0.0).is_inside(&crate_box));
+        assert!(v(0.9, 0.9, 0.0).is_outside(&crate_box));
+        // a cylinder lying down along x
+        let lying = Quaternion::from_rotation_arc(v(0.0, 1.0, 0.0), v(1.0, 0.0, 0.0));
+        let log = Shape3D::Cylinder { center: v(0.0, 0.5, 0.0), radius: 0.5, height: 4.0, orientation: lying };
+        assert!(v(1.9, 0.5, 0.0).is_inside(&log));
+        assert!(v(0.0, 1.2, 0.0).is_outside(&log));
+        assert!(v(0.0, 0.5, 0.45).is_inside(&log));
+        // a cone pointing down
+        let upside_down = Shape3D::Cone { center: Vertex3D::ZERO, radius: 1.0, height: 2.0, orientation: Quaternion::from_axis_angle(v(1.0, 0.0, 0.0), PI) };
+        assert!(v(0.0, -0.99, 0.0).is_inside(&upside_down));
+        assert!(v(0.9, -0.9, 0.0).is_outside(&upside_down));
+        assert!(v(0.9, 0.9, 0.0).is_inside(&upside_down));
+
+        // turning changes neither area nor volume; scaling does
+        let upright = Shape3D::Cuboid { center: Vertex3D::ZERO, width: 1.0, height: 2.0, length: 3.0, orientation: Quaternion::IDENTITY };
+        let turned = upright.transformed(&Transform::new(v(5.0, 0.0, 0.0), Quaternion::from_axis_angle(v(1.0, 1.0, 0.0), 1.0), 1.0));
+        assert_eq!(turned.volume(), upright.volume());
+        assert_eq!(turned.surface_area(), upright.surface_area());
+        let doubled = upright.transformed(&Transform::from_scale(2.0));
+        assert!((doubled.volume().in_cubic_meters() - 48.0).abs() < 1e-4);
+        assert!((doubled.surface_area().in_square_meters() - 88.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn transforms_and_matrices() {
+        let v = |x, y, z| Vertex3D { x, y, z };
+        let t = Transform::new(v(1.0, 2.0, 3.0), Quaternion::from_axis_angle(v(0.0, 1.0, 0.0), PI / 2.0), 2.0);
+        // scale, then turn, then move
+        assert!(t.apply(v(1.0, 0.0, 0.0)).approx_eq(v(1.0, 2.0, 1.0), 1e-6));
+        assert!(t.apply_vector(v(1.0, 0.0, 0.0)).approx_eq(v(0.0, 0.0, -2.0), 1e-6));
+        assert!(t.to_matrix().transform_point(v(1.0, 0.0, 0.0)).approx_eq(v(1.0, 2.0, 1.0), 1e-6));
+        assert!(t.inverse().apply(v(1.0, 2.0, 1.0)).approx_eq(v(1.0, 0.0, 0.0), 1e-6));
+        let inverse = t.to_matrix().inverse().unwrap();
+        assert!(inverse.transform_point(v(1.0, 2.0, 1.0)).approx_eq(v(1.0, 0.0, 0.0), 1e-5));
+        assert!((t.to_matrix() * inverse).transform_vector(v(1.0, 2.0, 3.0)).approx_eq(v(1.0, 2.0, 3.0), 1e-5));
+        assert!(Mat4::from_scale(v(1.0, 0.0, 1.0)).inverse().is_none());
+        assert_eq!(Mat4::from_translation(v(1.0, 2.0, 3.0)).transform_vector(v(1.0, 0.0, 0.0)), v(1.0, 0.0, 0.0));
+        assert_eq!(Mat4::from_rotation(Quaternion::IDENTITY).transpose(), Mat4::IDENTITY);
+
+        let q = Quaternion::from_axis_angle(v(0.0, 0.0, 1.0), PI / 2.0);
+        assert!(Quaternion::IDENTITY.slerp(q, 0.5).approx_eq(Quaternion::from_axis_angle(v(0.0, 0.0, 1.0), PI / 4.0), 1e-6));
+        assert!(Quaternion::from_rotation_arc(v(0.0, 1.0, 0.0), v(0.0, -1.0, 0.0)).rotate(v(0.0, 1.0, 0.0)).approx_eq(v(0.0, -1.0, 0.0), 1e-6));
+
+        // a polygon moves vertex by vertex; a sphere's center turns about the origin
+        let square = Shape3D::Polygon3D(Polygon3D { vertices: vec![v(0.0, 0.0, 0.0), v(1.0, 0.0, 0.0), v(1.0, 1.0, 0.0)] });
+        match square.transformed(&t) {
+            Shape3D::Polygon3D(poly) => assert!(poly.vertices[1].approx_eq(v(1.0, 2.0, 1.0), 1e-6)),
+            _ => panic!("Polygon stopped being a polygon"),
+        }
+        let ball = Shape3D::Sphere { center: v(1.0, 0.0, 0.0), radius: 1.0 }.transformed(&t);
+        assert!(v(1.0, 2.0, 1.0).is_inside(&ball) && !v(1.0, 2.0, 3.5).is_inside(&ball));
+    }
+
+    #[test]
+    fn orientation_is_optional_in_json() {
+        use rocket::serde::json::serde_json;
+        let cube: Shape3D = serde_json::from_str(r#"{"type": "Cube", "center": {"type": "Vertex3D", "x": 0, "y": 1, "z": 0}, "side": 2}"#).unwrap();
+        assert_eq!(cube.orientation(), Quaternion::IDENTITY);
+        let tilted = cube.transformed(&Transform::from_rotation(Quaternion::from_axis_angle(Vertex3D { x: 1.0, y: 0.0, z: 0.0 }, 0.5)));
+        let round_trip: Shape3D = serde_json::from_str(&serde_json::to_string(&tilted).unwrap()).unwrap();
+        assert_eq!(round_trip, tilted);
+    }
+
+    #[test]
+    fn bounding_boxes() {
+        let v = |x, y, z| Vertex3D { x, y, z };
+        let sphere = Shape3D::Sphere { center: v(1.0, 2.0, 3.0), radius: 0.5 };
+        assert_eq!(sphere.aabb(), Aabb { min: v(0.5, 1.5, 2.5), max: v(1.5, 2.5, 3.5) });
+        let cuboid = Shape3D::Cuboid { center: Vertex3D::ZERO, width: 2.0, height: 4.0, length: 6.0, orientation: Quaternion::IDENTITY };
+        assert_eq!(cuboid.aabb(), Aabb { min: v(-1.0, -2.0, -3.0), max: v(1.0, 2.0, 3.0) });
+        // a cube tipped onto an edge is wider
+        let tilted = Shape3D::Cube { center: Vertex3D::ZERO, side: 2.0, orientation: Quaternion::from_axis_angle(v(0.0, 0.0, 1.0), PI / 4.0) };
+        assert!(tilted.aabb().max.approx_eq(v(2f32.sqrt(), 2f32.sqrt(), 1.0), 1e-5));
+        // a log lying along x
+        let lying = Quaternion::from_axis_angle(v(0.0, 0.0, 1.0), PI / 2.0);
+        let log = Shape3D::Cylinder { center: Vertex3D::ZERO, radius: 0.5, height: 4.0, orientation: lying };
+        assert!(log.aabb().max.approx_eq(v(2.0, 0.5, 0.5), 1e-5));
+        let cone = Shape3D::Cone { center: Vertex3D::ZERO, radius: 1.0, height: 2.0, orientation: Quaternion::IDENTITY };
+        assert_eq!(cone.aabb(), Aabb { min: v(-1.0, -1.0, -1.0), max: v(1.0, 1.0, 1.0) });
+        let triangle = Shape3D::Polygon3D(Polygon3D { vertices: vec![v(0.0, 0.0, 0.0), v(2.0, 1.0, 0.0), v(-1.0, 3.0, 0.0)] });
+        assert_eq!(triangle.aabb(), Aabb { min: v(-1.0, 0.0, 0.0), max: v(2.0, 3.0, 0.0) });
+        assert_eq!(Shape3D::Polyhedron { faces: Vec::new() }.aabb(), Aabb::EMPTY);
+
+        match cuboid.bounding_sphere() {
+            Shape3D::Sphere { center, radius } => {
+                assert_eq!(center, Vertex3D::ZERO);
+                assert!((radius - 14f32.sqrt()).abs() < 1e-5);
+            },
+            _ => panic!("Expected a sphere"),
+        }
+        match triangle.bounding_sphere() {
+            Shape3D::Sphere { center, radius } => {
+                assert_eq!(center, v(0.5, 1.5, 0.0));
+                assert!((radius - v(1.5, 1.5, 0.0).length()).abs() < 1e-5);
+            },
+            _ => panic!("Expected a sphere"),
+        }
+    }
+
+    #[test]
+    fn aabb_operations() {
+        let v = |x, y, z| Vertex3D { x, y, z };
+        let a = Aabb::new(v(0.0, 0.0, 0.0), v(2.0, 2.0, 2.0));
+        let b = Aabb::new(v(3.0, 3.0, 3.0), v(1.0, 1.0, 1.0));
+        assert_eq!(b.min, v(1.0, 1.0, 1.0));
+        assert_eq!(a.union(&b), Aabb::new(v(0.0, 0.0, 0.0), v(3.0, 3.0, 3.0)));
+        assert_eq!(a.intersection(&b), Some(Aabb::new(v(1.0, 1.0, 1.0), v(2.0, 2.0, 2.0))));
+        assert!(a.intersects(&b));
+        let far = Aabb::around(v(10.0, 0.0, 0.0), v(1.0, 1.0, 1.0));
+        assert_eq!(a.intersection(&far), None);
+        // touching faces still meet
+        assert!(a.intersects(&Aabb::new(v(2.0, 0.0, 0.0), v(3.0, 1.0, 1.0))));
+        assert!(a.contains_point(v(2.0, 1.0, 0.0)));
+        assert!(!a.contains_point(v(2.1, 1.0, 0.0)));
+        assert!(a.union(&b).contains(&a));
+        assert!(!a.contains(&b));
+        assert!(a.contains(&Aabb::EMPTY));
+        assert_eq!(Aabb::EMPTY.union(&a), a);
+        assert!(!Aabb::EMPTY.intersects(&a));
+        assert_eq!(a.center(), v(1.0, 1.0, 1.0));
+        assert_eq!(a.expanded(0.5).size(), v(3.0, 3.0, 3.0));
+        assert_eq!(a.volume(), Volume::cubic_meters(8.0));
+        assert_eq!(Aabb::EMPTY.volume(), Volume::ZERO);
+    }
+
+    #[test]
+    fn ray_casting() {
+        let v = |x, y, z| Vertex3D { x, y, z };
+        let close = |hit: Option<RayHit>, distance: f32, normal: Vertex3D| {
+            let hit = hit.unwrap();
+            assert!((hit.distance - distance).abs() < 1e-4, "{:?}", hit);
+            assert!(hit.normal.approx_eq(normal, 1e-4), "{:?}", hit);
+        };
+        let sphere = Shape3D::Sphere { center: v(5.0, 0.0, 0.0), radius: 1.0 };
+        close(sphere.ray_intersect(Vertex3D::ZERO, v(2.0, 0.0, 0.0), 10.0), 4.0, v(-1.0, 0.0, 0.0));
+        assert_eq!(sphere.ray_intersect(Vertex3D::ZERO, v(1.0, 0.0, 0.0), 3.5), None);
+        assert_eq!(sphere.ray_intersect(Vertex3D::ZERO, v(-1.0, 0.0, 0.0), 10.0), None);
+        assert_eq!(sphere.ray_intersect(Vertex3D::ZERO, v(0.0, 1.0, 0.0), 10.0), None);
+        assert_eq!(sphere.ray_intersect(Vertex3D::ZERO, Vertex3D::ZERO, 10.0), None);
+        // starting inside hits straight away
+        close(sphere.ray_intersect(v(5.0, 0.5, 0.0), v(0.0, 1.0, 0.0), 10.0), 0.0, v(0.0, -1.0, 0.0));
+
+        // the crate tipped onto an edge is hit on the edge
+        let tilt = Quaternion::from_axis_angle(v(0.0, 0.0, 1.0), PI / 4.0);
+        let crate_box = Shape3D::Cube { center: Vertex3D::ZERO, side: 2.0, orientation: tilt };
+        let hit = crate_box.ray_intersect(v(0.0, 10.0, 0.0), v(0.0, -1.0, 0.0), 20.0).unwrap();
+        assert!((hit.distance - (10.0 - 2.0f32.sqrt())).abs() < 1e-4);
+        assert!(hit.point.approx_eq(v(0.0, 2.0f32.sqrt(), 0.0), 1e-4));
+        close(crate_box.ray_intersect(v(-5.0, 0.5, 0.5), v(1.0, 0.0, 0.0), 20.0), 5.5 - 2.0f32.sqrt(), v(-1.0, 1.0, 0.0).normalize());
+        let cuboid = Shape3D::Cuboid { center: Vertex3D::ZERO, width: 2.0, height: 4.0, length: 6.0, orientation: Quaternion::IDENTITY };
+        close(cuboid.ray_intersect(v(0.0, 0.0, -10.0), v(0.0, 0.0, 1.0), 20.0), 7.0, v(0.0, 0.0, -1.0));
+        assert_eq!(cuboid.ray_intersect(v(1.5, 0.0, -10.0), v(0.0, 0.0, 1.0), 20.0), None);
+
+        // side and end of a log lying along x
+        let lying = Quaternion::from_rotation_arc(v(0.0, 1.0, 0.0), v(1.0, 0.0, 0.0));
+        let log = Shape3D::Cylinder { center: Vertex3D::ZERO, radius: 0.5, height: 4.0, orientation: lying };
+        close(log.ray_intersect(v(1.0, 5.0, 0.0), v(0.0, -1.0, 0.0), 10.0), 4.5, v(0.0, 1.0, 0.0));
+        close(log.ray_intersect(v(-5.0, 0.2, 0.0), v(1.0, 0.0, 0.0), 10.0), 3.0, v(-1.0, 0.0, 0.0));
+        assert_eq!(log.ray_intersect(v(2.5, 5.0, 0.0), v(0.0, -1.0, 0.0), 10.0), None);
+
+        // apex at y = 1, base radius 1 at y = -1
+        let cone = Shape3D::Cone { center: Vertex3D::ZERO, radius: 1.0, height: 2.0, orientation: Quaternion::IDENTITY };
+        close(cone.ray_intersect(v(0.0, 5.0, 0.0), v(0.0, -1.0, 0.0), 10.0), 4.0, v(0.0, 1.0, 0.0));
+        close(cone.ray_intersect(v(0.5, -5.0, 0.0), v(0.0, 1.0, 0.0), 10.0), 4.0, v(0.0, -1.0, 0.0));
+        // halfway up the side is half a unit out, sloping 2 in 1
+        close(cone.ray_intersect(v(5.0, 0.0, 0.0), v(-1.0, 0.0, 0.0), 10.0), 4.5, v(2.0, 1.0, 0.0).normalize());
+        assert_eq!(cone.ray_intersect(v(5.0, 0.9, 0.0), v(0.0, 0.0, 1.0), 10.0), None);
+
+        // polygons are hit from either side, with the normal facing the ray
+        let square = Shape3D::Polygon3D(Polygon3D { vertices: vec![v(-1.0, -1.0, 2.0), v(1.0, -1.0, 2.0), v(1.0, 1.0, 2.0), v(-1.0, 1.0, 2.0)] });
+        close(square.ray_intersect(Vertex3D::ZERO, v(0.0, 0.0, 1.0), 10.0), 2.0, v(0.0, 0.0, -1.0));
+        close(square.ray_intersect(v(0.5, 0.5, 5.0), v(0.0, 0.0, -1.0), 10.0), 3.0, v(0.0, 0.0, 1.0));
+        assert_eq!(square.ray_intersect(v(1.5, 0.0, 0.0), v(0.0, 0.0, 1.0), 10.0), None);
+        assert_eq!(square.ray_intersect(Vertex3D::ZERO, v(1.0, 0.0, 0.0), 10.0), None);
+        let tetrahedron = Shape3D::Polyhedron { faces: vec![
+            Polygon3D { vertices: vec![v(0.0, 0.0, 0.0), v(1.0, 0.0, 0.0), v(0.0, 1.0, 0.0)] },
+            Polygon3D { vertices: vec![v(0.0, 0.0, 0.0), v(0.0, 1.0, 0.0), v(0.0, 0.0, 1.0)] },
+            Polygon3D { vertices: vec![v(0.0, 0.0, 0.0), v(0.0, 0.0, 1.0), v(1.0, 0.0, 0.0)] },
+            Polygon3D { vertices: vec![v(1.0, 0.0, 0.0), v(0.0, 1.0, 0.0), v(0.0, 0.0, 1.0)] },
+        ] };
+        close(tetrahedron.ray_intersect(v(0.2, 0.2, -3.0), v(0.0, 0.0, 1.0), 10.0), 3.0, v(0.0, 0.0, -1.0));
+        close(tetrahedron.ray_intersect(v(2.0, 2.0, 2.0), v(-1.0, -1.0, -1.0), 10.0), (2.0 - 1.0 / 3.0) * 3.0f32.sqrt(), v(1.0, 1.0, 1.0).normalize());
+
+        // the nearest of several, whatever order they come in
+        let shapes = [
+            Shape3D::Sphere { center: v(0.0, 0.0, 20.0), radius: 1.0 },
+            cuboid.translated(v(0.0, 0.0, 10.0)),
+            Shape3D::Sphere { center: v(0.0, 5.0, 5.0), radius: 1.0 },
+        ];
+        let (index, hit) = ray_cast(&shapes, Vertex3D::ZERO, v(0.0, 0.0, 1.0), 100.0).unwrap();
+        assert_eq!(index, 1);
+        assert!((hit.distance - 7.0).abs() < 1e-4);
+        assert_eq!(ray_cast(&shapes, Vertex3D::ZERO, v(0.0, 0.0, 1.0), 5.0), None);
+        assert_eq!(ray_cast(&shapes, Vertex3D::ZERO, v(0.0, 0.0, -1.0), 100.0), None);
+
+        let bounds = Aabb::new(v(1.0, -1.0, -1.0), v(3.0, 1.0, 1.0));
+        assert_eq!(bounds.ray_intersect(Vertex3D::ZERO, v(1.0, 0.0, 0.0), 10.0), Some(1.0));
+        assert_eq!(bounds.ray_intersect(v(2.0, 0.0, 0.0), v(1.0, 0.0, 0.0), 10.0), Some(0.0));
+        assert_eq!(bounds.ray_intersect(Vertex3D::ZERO, v(-1.0, 0.0, 0.0), 10.0), None);
+    }
+
+    #[test]
+    fn polyhedron_containment() {
+        let v = |x, y, z| Vertex3D { x, y, z };
+        let tetrahedron = Shape3D::Polyhedron { faces: vec![
+            Polygon3D { vertices: vec![v(0.0, 0.0, 0.0), v(1.0, 0.0, 0.0), v(0.0, 1.0, 0.0)] },
+            Polygon3D { vertices: vec![v(0.0, 0.0, 0.0), v(0.0, 1.0, 0.0), v(0.0, 0.0, 1.0)] },
+            Polygon3D { vertices: vec![v(0.0, 0.0, 0.0), v(0.0, 0.0, 1.0), v(1.0, 0.0, 0.0)] },
+            Polygon3D { vertices: vec![v(1.0, 0.0, 0.0), v(0.0, 1.0, 0.0), v(0.0, 0.0, 1.0)] },
+        ] };
+        assert!(v(0.2, 0.2, 0.2).is_inside(&tetrahedron));
+        assert!(v(0.1, 0.1, 0.7).is_inside(&tetrahedron));
+        assert!(v(0.3, 0.3, 0.0).is_on(&tetrahedron));
+        assert!(v(0.5, 0.5, 0.5).is_outside(&tetrahedron));
+        assert!(v(-0.1, 0.2, 0.2).is_outside(&tetrahedron));
+        assert!(v(5.0, 5.0, 5.0).is_outside(&tetrahedron));
+
+        // a polygon has no inside, only its surface
+        let square = Shape3D::Polygon3D(Polygon3D { vertices: vec![v(-1.0, -1.0, 2.0), v(1.0, -1.0, 2.0), v(1.0, 1.0, 2.0), v(-1.0, 1.0, 2.0)] });
+        assert!(v(0.5, -0.5, 2.0).is_on(&square));
+        assert!(v(0.5, -0.5, 2.1).is_outside(&square));
+        assert!(v(1.5, 0.0, 2.0).is_outside(&square));
+    }
+
+    #[test]
+    fn polygon_area_and_planes() {
+        let v = |x, y, z| Vertex3D { x, y, z };
+        let square = |vertices: Vec<Vertex3D>| Polygon3D { vertices };
+        // a 2 x 3 rectangle standing up in the xz plane, then the yz plane
+        let wall = square(vec![v(0.0, 5.0, 0.0), v(2.0, 5.0, 0.0), v(2.0, 5.0, 3.0), v(0.0, 5.0, 3.0)]);
+        assert_eq!(wall.surface_area(), Area::square_meters(6.0));
+        assert_eq!(Shape3D::Polygon3D(wall.clone()).surface_area(), Area::square_meters(6.0));
+        let side = square(vec![v(1.0, 0.0, 0.0), v(1.0, 2.0, 0.0), v(1.0, 2.0, 3.0), v(1.0, 0.0, 3.0)]);
+        assert_eq!(side.surface_area(), Area::square_meters(6.0));
+        // clockwise is just as big
+        assert_eq!(wall.reversed().surface_area(), Area::square_meters(6.0));
+
+        assert!(wall.normal().approx_eq(v(0.0, -1.0, 0.0), 1e-6));
+        assert!(wall.reversed().normal().approx_eq(v(0.0, 1.0, 0.0), 1e-6));
+        let plane = wall.plane().unwrap();
+        assert!((plane.distance_to(v(7.0, 3.0, -2.0)) - 2.0).abs() < 1e-5);
+        assert!(plane.project(v(7.0, 3.0, -2.0)).approx_eq(v(7.0, 5.0, -2.0), 1e-5));
+        assert_eq!(wall.centroid(), v(1.0, 5.0, 1.5));
+
+        assert_eq!(wall.winding(v(0.0, -1.0, 0.0)), Some(Winding::Anticlockwise));
+        assert_eq!(wall.winding(v(0.0, 1.0, 0.0)), Some(Winding::Clockwise));
+        assert_eq!(wall.winding(v(1.0, 0.0, 0.0)), None);
+        let flipped = wall.clone().wound(Winding::Anticlockwise, v(0.0, 1.0, 0.0));
+        assert_eq!(flipped, wall.reversed());
+        assert_eq!(flipped.clone().wound(Winding::Anticlockwise, v(0.0, 1.0, 0.0)), flipped);
+
+        assert!(wall.is_planar(1e-6));
+        let bent = square(vec![v(0.0, 0.0, 0.0), v(2.0, 0.0, 0.0), v(2.0, 2.0, 0.1), v(0.0, 2.0, 0.0)]);
+        assert!(!bent.is_planar(1e-3));
+        assert!(bent.is_planar(0.1));
+        let line = square(vec![v(0.0, 0.0, 0.0), v(1.0, 1.0, 1.0), v(2.0, 2.0, 2.0)]);
+        assert_eq!(line.surface_area(), Area::ZERO);
+        assert_eq!(line.normal(), Vertex3D::ZERO);
+        assert_eq!(line.plane(), None);
+        assert!(line.is_planar(0.0));
+        assert_eq!(square(Vec::new()).surface_area(), Area::ZERO);
+
+        // the corner of a unit cube: three right triangles and a sloping
+        // equilateral one
+        let corner = Shape3D::Polyhedron { faces: vec![
+            square(vec![v(0.0, 0.0, 0.0), v(0.0, 1.0, 0.0), v(1.0, 0.0, 0.0)]),
+            square(vec![v(0.0, 0.0, 0.0), v(0.0, 0.0, 1.0), v(0.0, 1.0, 0.0)]),
+            square(vec![v(0.0, 0.0, 0.0), v(1.0, 0.0, 0.0), v(0.0, 0.0, 1.0)]),
+            square(vec![v(1.0, 0.0, 0.0), v(0.0, 1.0, 0.0), v(0.0, 0.0, 1.0)]),
+        ] };
+        let expected = 1.5 + 3.0f32.sqrt() / 2.0;
+        assert!((corner.surface_area().in_square_meters() - expected).abs() < 1e-5);
+    }
+
+    fn vertex3() -> impl Strategy<Value = Vertex3D> {
+        (-100.0f32 .. 100.0, -100.0f32 .. 100.0, -100.0f32 .. 100.0).prop_map(|(x, y, z)| Vertex3D { x, y, z })
+    }
+
+    fn vertex2() -> impl Strategy<Value = Vertex2D> {
+        (-100.0f32 .. 100.0, -100.0f32 .. 100.0).prop_map(|(x, y)| Vertex2D { x, y })
+    }
+
+    proptest! {
+        #[test]
+        fn addition_and_subtraction_undo_each_other(a in vertex3(), b in vertex3()) {
+            prop_assert!((a + b - b).approx_eq(a, 1e-3));
+            prop_assert_eq!(a + b, b + a);
+            prop_assert_eq!(a - b, -(b - a));
+        }
+
+        #[test]
+        fn cross_product_is_perpendicular(a in vertex3(), b in vertex3()) {
+            let c = a.cross(b);
+            let scale = a.length() * b.length() + 1.0;
+            prop_assert!(c.dot(a).abs() <= 1e-4 * scale * a.length());
+            prop_assert!(c.dot(b).abs() <= 1e-4 * scale * b.length());
+            prop_assert_eq!(b.cross(a), -c);
+            // |a x b|^2 + (a . b)^2 = |a|^2 |b|^2
+            let lagrange = c.length_squared() + a.dot(b).powi(2);
+            prop_assert!((lagrange - a.length_squared() * b.length_squared()).abs() <= 1e-4 * scale * scale);
+        }
+
+        #[test]
+        fn normalized_vectors_have_unit_length(a in vertex3(), p in vertex2()) {
+            prop_assume!(a.length() > 1e-3 && p.length() > 1e-3);
+            prop_assert!((a.normalize().length() - 1.0).abs() < 1e-5);
+            prop_assert!((p.normalize().length() - 1.0).abs() < 1e-5);
+            prop_assert!((a.normalize() * a.length()).approx_eq(a, 1e-3));
+        }
+
+        #[test]
+        fn distance_is_a_metric(a in vertex3(), b in vertex3(), c in vertex3()) {
+            prop_assert_eq!(a.distance(b), b.distance(a));
+            prop_assert_eq!(a.distance(a), 0.0);
+            prop_assert!(a.distance(c) <= a.distance(b) + b.distance(c) + 1e-3);
+        }
+
+        #[test]
+        fn lerp_runs_from_one_end_to_the_other(a in vertex3(), b in vertex3(), t in 0.0f32 .. 1.0) {
+            prop_assert_eq!(a.lerp(b, 0.0), a);
+            prop_assert!(a.lerp(b, 1.0).approx_eq(b, 1e-4));
+            let between = a.lerp(b, t);
+            prop_assert!(between.approx_eq(between.max(a.min(b)).min(a.max(b)), 1e-4));
+        }
+
+        #[test]
+        fn vertex2d_matches_vertex3d_in_the_plane(a in vertex2(), b in vertex2()) {
+            let (a3, b3) = (Vertex3D { x: a.x, y: a.y, z: 0.0 }, Vertex3D { x: b.x, y: b.y, z: 0.0 });
+            prop_assert_eq!(a.dot(b), a3.dot(b3));
+            prop_assert_eq!(a.cross(b), a3.cross(b3).z);
+            prop_assert_eq!(a.distance(b), a3.distance(b3));
+            prop_assert_eq!((a + b).x, (a3 + b3).x);
+            prop_assert_eq!(a.lerp(b, 0.25).y, a3.lerp(b3, 0.25).y);
+        }
+
+        #[test]
+        fn containment_predicates_agree(p in vertex3(), center in vertex3(), size in 1.0f32 .. 100.0) {
+            let shapes = [
+                Shape3D::Sphere { center, radius: size },
+                Shape3D::Cube { center, side: size, orientation: Quaternion::IDENTITY },
+                Shape3D::Cuboid { center, width: size, height: size / 2.0, length: size * 2.0, orientation: Quaternion::IDENTITY },
+                Shape3D::Cylinder { center, radius: size, height: size, orientation: Quaternion::IDENTITY },
+                Shape3D::Cone { center, radius: size, height: size, orientation: Quaternion::IDENTITY },
+            ];
+            for shape in shapes.iter() {
+                let states = [p.is_inside(shape), p.is_on(shape), p.is_outside(shape)];
+                prop_assert_eq!(states.iter().filter(|s| **s).count(), 1);
+                prop_assert_eq!(p.is_on_or_inside(shape), !p.is_outside(shape));
+            }
+            prop_assert!(center.is_inside(&shapes[0]));
+            prop_assert_eq!(p.is_inside(&shapes[0]), p.distance(center) < size);
+        }
+        #[test]
+        fn transforms_keep_containment(p in vertex3(), center in vertex3(), size in 1.0f32 .. 50.0,
+                                       axis in vertex3(), angle in -PI .. PI, offset in vertex3(), scale in 0.5f32 .. 2.0) {
+            prop_assume!(axis.length() > 1e-2);
+            let t = Transform::new(offset, Quaternion::from_axis_angle(axis, angle), scale);
+            prop_assert!(t.inverse().apply(t.apply(p)).approx_eq(p, 0.05));
+            prop_assert!(t.to_matrix().transform_point(p).approx_eq(t.apply(p), 0.05));
+            let shapes = [
+                Shape3D::Cuboid { center, width: size, height: size / 2.0, length: size * 2.0, orientation: Quaternion::IDENTITY },
+                Shape3D::Cylinder { center, radius: size, height: size, orientation: Quaternion::IDENTITY },
+                Shape3D::Cone { center, radius: size, height: size, orientation: Quaternion::IDENTITY },
+            ];
+            for shape in shapes.iter() {
+                let moved = shape.transformed(&t);
+                // only points clear of the surface, where rounding can't flip the answer
+                prop_assume!(p.excess(shape).abs() > 0.1);
+                prop_assert_eq!(p.is_inside(shape), t.apply(p).is_inside(&moved));
+                prop_assert!((moved.volume().in_cubic_meters() - shape.volume().in_cubic_meters() * scale.powi(3)).abs() <= 1e-3 * shape.volume().in_cubic_meters() * scale.powi(3));
+            }
+        }
+        #[test]
+        fn rays_stop_at_the_surface(origin in vertex3(), center in vertex3(), size in 0.5f32 .. 20.0, axis in vertex3(), angle in -PI .. PI) {
+            prop_assume!(axis.length() > 1e-2);
+            let orientation = Quaternion::from_axis_angle(axis, angle);
+            let shapes = [
+                Shape3D::Sphere { center, radius: size },
+                Shape3D::Cube { center, side: size, orientation },
+                Shape3D::Cuboid { center, width: size, height: size / 2.0, length: size * 3.0, orientation },
+                Shape3D::Cylinder { center, radius: size / 2.0, height: size, orientation },
+                Shape3D::Cone { center, radius: size, height: size, orientation },
+            ];
+            for shape in shapes.iter() {
+                prop_assume!(origin.excess(shape) > 0.1);
+                // aimed at the centre, every ray hits, and lands on the surface
+                let hit = shape.ray_intersect(origin, center - origin, f32::MAX);
+                prop_assert!(hit.is_some(), "{:?}", shape);
+                let hit = hit.unwrap();
+                prop_assert!(hit.point.excess(shape).abs() < 1e-2, "{:?} {:?}", shape, hit);
+                prop_assert!(hit.distance <= origin.distance(center) + 1e-3);
+                prop_assert!((hit.normal.length() - 1.0).abs() < 1e-3);
+                prop_assert!(hit.normal.dot(center - origin) <= 0.0);
+                // and nothing in front of it is inside
+                prop_assert!(!origin.lerp(hit.point, 0.99).is_inside(shape));
+            }
+        }
+        #[test]
+        fn polygon_area_ignores_pose(sides in 3usize .. 12, radius in 0.5f32 .. 20.0, axis in vertex3(), angle in -PI .. PI, offset in vertex3()) {
+            prop_assume!(axis.length() > 1e-2);
+            let turn = Quaternion::from_axis_angle(axis, angle);
+            // a regular polygon in the xy plane, then turned and moved
+            let flat = Polygon3D { vertices: (0 .. sides).map(|i| {
+                let a = 2.0 * PI * i as f32 / sides as f32;
+                Vertex3D { x: radius * a.cos(), y: radius * a.sin(), z: 0.0 }
+            }).collect() };
+            let posed = Polygon3D { vertices: flat.vertices.iter().map(|v| turn.rotate(*v) + offset).collect() };
+            let area = sides as f32 / 2.0 * radius * radius * (2.0 * PI / sides as f32).sin();
+            for poly in [&flat, &posed, &posed.reversed()] {
+                prop_assert!((poly.surface_area().in_square_meters() - area).abs() <= 1e-3 * area);
+                prop_assert!(poly.is_planar(1e-3 * (1.0 + offset.length())));
+            }
+            let up = turn.rotate(Vertex3D { x: 0.0, y: 0.0, z: 1.0 });
+            prop_assert!(posed.normal().approx_eq(up, 1e-3));
+            prop_assert!(posed.reversed().normal().approx_eq(-posed.normal(), 1e-6));
+            prop_assert_eq!(posed.winding(posed.normal()), Some(Winding::Anticlockwise));
+        }
+        #[test]
+        fn aabb_is_tight_around_turned_shapes(center in vertex3(), size in 0.5f32 .. 20.0, axis in vertex3(), angle in -PI .. PI) {
+            prop_assume!(axis.length() > 1e-2);
+            let orientation = Quaternion::from_axis_angle(axis, angle);
+            let shapes = [
+                Shape3D::Sphere { center, radius: size },
+                Shape3D::Cube { center, side: size, orientation },
+                Shape3D::Cuboid { center, width: size, height: size / 2.0, length: size * 3.0, orientation },
+                Shape3D::Cylinder { center, radius: size / 2.0, height: size, orientation },
+                Shape3D::Cone { center, radius: size, height: size / 3.0, orientation },
+                Shape3D::Polygon3D(Polygon3D { vertices: vec![center, center + axis, center + axis.cross(center) * 0.01] }),
+            ];
+            let directions = [
+                Vertex3D { x: 1.0, y: 0.0, z: 0.0 }, Vertex3D { x: 0.0, y: 1.0, z: 0.0 }, Vertex3D { x: 0.0, y: 0.0, z: 1.0 },
+            ];
+            for shape in shapes.iter() {
+                let bounds = shape.aabb();
+                let tolerance = 1e-3 * (1.0 + bounds.size().length() + center.length());
+                for d in directions {
+                    // the furthest point each way touches the box's face
+                    let (high, low) = (crate::collision::support(shape, d), crate::collision::support(shape, -d));
+                    prop_assert!((high.dot(d) - bounds.max.dot(d)).abs() <= tolerance, "{:?} {:?}", shape, bounds);
+                    prop_assert!((low.dot(d) - bounds.min.dot(d)).abs() <= tolerance, "{:?} {:?}", shape, bounds);
+                }
+                if let Shape3D::Sphere { center: c, radius } = shape.bounding_sphere() {
+                    for corner in [bounds.min, bounds.max] {
+                        let furthest = crate::collision::support(shape, corner - c);
+                        prop_assert!(furthest.distance(c) <= radius + tolerance);
+                    }
+                }
+            }
+        }
+    }
 }
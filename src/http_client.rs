@@ -0,0 +1,178 @@
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::net::TcpStream;
+use std::net::ToSocketAddrs;
+use std::time::Duration;
+use std::time::Instant;
+
+// Just enough HTTP/1.1 for shard-to-shard calls: one request per connection,
+// body read until the peer closes. Chunked responses are not decoded.
+
+// larger responses are refused rather than read into memory
+const MAX_RESPONSE_BYTES: usize = 1024 * 1024;
+
+#[derive(Debug, PartialEq)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.as_str())
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn parse_response(raw: &[u8]) -> io::Result<HttpResponse> {
+    let split = raw.windows(4).position(|w| w == b"\r\n\r\n")
+    .ok_or_else(|| invalid("Response has no header terminator"))?;
+    let head = std::str::from_utf8(&raw[..split])
+    .map_err(|_| invalid("Response headers are not UTF-8"))?;
+    let mut lines = head.split("\r\n");
+
+    let status_line = lines.next().ok_or_else(|| invalid("Empty response"))?;
+    let status = status_line.split(' ').nth(1)
+    .and_then(|code| code.parse::<u16>().ok())
+    .ok_or_else(|| invalid("Malformed status line"))?;
+
+    let headers = lines.filter_map(|line| {
+        let (k, v) = line.split_once(':')?;
+        Some((k.trim().to_string(), v.trim().to_string()))
+    }).collect();
+
+    Ok(HttpResponse {
+        status,
+        headers,
+        body: raw[split + 4 ..].to_vec(),
+    })
+}
+
+// The timeout covers the whole exchange, however slowly the peer trickles
+// its response out.
+pub fn request(method: &str, addr: &str, path: &str, headers: &[(&str, &str)], body: &[u8], timeout: Duration) -> io::Result<HttpResponse> {
+    let deadline = Instant::now() + timeout;
+    let remaining = || {
+        deadline.checked_duration_since(Instant::now())
+        .filter(|left| !left.is_zero())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::TimedOut, format!("No response from {} in time", addr)))
+    };
+    let socket = addr.to_socket_addrs()?.next()
+    .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("No address for {}", addr)))?;
+    let mut stream = TcpStream::connect_timeout(&socket, remaining()?)?;
+    stream.set_write_timeout(Some(remaining()?))?;
+
+    let mut req = format!("{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nContent-Length: {}\r\n", method, path, addr, body.len());
+    for (k, v) in headers {
+        req.push_str(&format!("{}: {}\r\n", k, v));
+    }
+    req.push_str("\r\n");
+    stream.write_all(req.as_bytes())?;
+    stream.write_all(body)?;
+
+    let mut raw = Vec::new();
+    let mut buf = [0u8; 8192];
+    loop {
+        stream.set_read_timeout(Some(remaining()?))?;
+        let n = match stream.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        if raw.len() + n > MAX_RESPONSE_BYTES {
+            return Err(invalid("Response is too large"));
+        }
+        raw.extend_from_slice(&buf[..n]);
+    }
+    parse_response(&raw)
+}
+
+pub fn get(addr: &str, path: &str, headers: &[(&str, &str)], timeout: Duration) -> io::Result<HttpResponse> {
+    request("GET", addr, path, headers, &[], timeout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn parse() {
+        let response = parse_response(b"HTTP/1.1 503 Service Unavailable\r\nContent-Type: text/plain\r\n\r\nnope").unwrap();
+        assert_eq!(response.status, 503);
+        assert_eq!(response.header("content-type"), Some("text/plain"));
+        assert_eq!(response.body, b"nope");
+        assert!(parse_response(b"garbage").is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0u8; 1024];
+            let n = stream.read(&mut buf).unwrap();
+            let req = String::from_utf8_lossy(&buf[..n]).to_string();
+            stream.write_all(b"HTTP/1.1 200 OK\r\n\r\nok").unwrap();
+            req
+        });
+
+        let response = get(&addr, "/healthz", &[("X-Test", "1")], Duration::from_secs(5)).unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, b"ok");
+
+        let req = server.join().unwrap();
+        assert!(req.starts_with("GET /healthz HTTP/1.1\r\n"));
+        assert!(req.contains("X-Test: 1\r\n"));
+    }
+
+    // Accepts one connection, reads the request and hands the stream to `respond`.
+    fn serve<F: FnOnce(&mut TcpStream) + Send + 'static>(respond: F) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0u8; 1024];
+            let _ = stream.read(&mut buf);
+            respond(&mut stream);
+        });
+        addr
+    }
+
+    #[test]
+    fn slow_peers_time_out_overall() {
+        let addr = serve(|stream| {
+            let _ = stream.write_all(b"HTTP/1.1 200 OK\r\n\r\n");
+            for _ in 0 .. 50 {
+                thread::sleep(Duration::from_millis(50));
+                if stream.write_all(b".").is_err() {
+                    return;
+                }
+            }
+        });
+        let start = Instant::now();
+        let error = get(&addr, "/healthz", &[], Duration::from_millis(300)).unwrap_err();
+        assert!(matches!(error.kind(), io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock), "{:?}", error);
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn large_responses_are_refused() {
+        let addr = serve(|stream| {
+            let _ = stream.write_all(b"HTTP/1.1 200 OK\r\n\r\n");
+            let _ = stream.write_all(&vec![b'x'; MAX_RESPONSE_BYTES + 1]);
+        });
+        let error = get(&addr, "/healthz", &[], Duration::from_secs(5)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -1,3 +1,13 @@
+pub mod auth;
+pub mod collision;
 pub mod geometry;
+pub mod http_client;
 pub mod physics;
+pub mod spatial;
+pub mod telemetry;
 pub mod logger_fairing;
+pub mod logging;
+pub mod metrics_fairing;
+pub mod rate_limit_fairing;
+pub mod tracing_fairing;
+pub mod units;
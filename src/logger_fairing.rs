@@ -1,11 +1,244 @@
+use crate::rate_limit_fairing;
 use chrono::{DateTime, Utc};
+use rocket::Build;
 use rocket::Data;
 use rocket::Request;
+use rocket::Response;
+use rocket::Rocket;
+use rocket::fairing;
 use rocket::fairing::Fairing;
 use rocket::fairing::Info;
 use rocket::fairing::Kind;
+use rocket::http::Header;
+use rocket::request::FromRequest;
+use rocket::request::Outcome;
+use rocket::serde::json::serde_json;
+use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::RwLock;
+use std::time::Instant;
+use uuid::Uuid;
+
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+const MAX_REQUEST_ID_LEN: usize = 128;
+const REDACTED: &str = "[REDACTED]";
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    // one JSON object per line
+    Json,
+    // Apache/NCSA combined log format
+    Combined,
+}
+
+// Request and response bodies are never logged. Headers are only logged when
+// listed in `headers`, and query parameters and headers named in the redact
+// lists (case-insensitive) have their values replaced.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct LoggerConfig {
+    pub format: LogFormat,
+    // fraction of ordinary requests to log, 0.0 to 1.0
+    pub sample_ratio: f64,
+    // 4xx responses bypass sampling; 5xx always do, logged as errors
+    pub always_log_errors: bool,
+    // slower requests bypass sampling and are logged as warnings
+    pub slow_threshold_ms: Option<f64>,
+    pub headers: Vec<String>,
+    pub redact_headers: Vec<String>,
+    pub redact_query: Vec<String>,
+}
+
+impl Default for LoggerConfig {
+    fn default() -> LoggerConfig {
+        LoggerConfig {
+            format: LogFormat::Json,
+            sample_ratio: 1.0,
+            always_log_errors: true,
+            slow_threshold_ms: Some(1000.0),
+            headers: Vec::new(),
+            redact_headers: ["Authorization", "Cookie", "Set-Cookie", "X-Api-Key"]
+                .iter().map(|h| h.to_string()).collect(),
+            redact_query: ["token", "access_token", "api_key", "key", "password", "secret"]
+                .iter().map(|q| q.to_string()).collect(),
+        }
+    }
+}
+
+impl LoggerConfig {
+    fn redacts_header(&self, name: &str) -> bool {
+        self.redact_headers.iter().any(|h| h.eq_ignore_ascii_case(name))
+    }
+
+    pub fn redact_uri(&self, path: &str, query: Option<&str>) -> String {
+        let query = match query {
+            Some(query) => query,
+            None => return path.to_string(),
+        };
+        let params: Vec<String> = query.split('&').map(|param| {
+            let key = param.split_once('=').map(|(k, _)| k).unwrap_or(param);
+            if self.redact_query.iter().any(|q| q.eq_ignore_ascii_case(key)) {
+                format!("{}={}", key, REDACTED)
+            } else {
+                param.to_string()
+            }
+        }).collect();
+        format!("{}?{}", path, params.join("&"))
+    }
+
+    // Picks the level to log at, or None when the request is sampled out.
+    pub fn level_for(&self, entry: &AccessLogEntry) -> Option<log::Level> {
+        if entry.status >= 500 {
+            return Some(log::Level::Error);
+        }
+        if let Some(threshold) = self.slow_threshold_ms {
+            if entry.latency_ms >= threshold {
+                return Some(log::Level::Warn);
+            }
+        }
+        if self.always_log_errors && entry.status >= 400 {
+            return Some(log::Level::Info);
+        }
+        if self.sample_ratio >= 1.0 {
+            return Some(log::Level::Info);
+        }
+        if self.sample_ratio <= 0.0 {
+            return None;
+        }
+        // hash the request id so every shard makes the same call for a request
+        let sample = fnv1a(entry.request_id.as_bytes()) as f64 / u64::MAX as f64;
+        if sample < self.sample_ratio {
+            Some(log::Level::Info)
+        } else {
+            None
+        }
+    }
+}
+
+// FNV-1a rather than DefaultHasher, whose keys aren't fixed across processes
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct AccessLogEntry {
+    pub timestamp: DateTime<Utc>,
+    pub request_id: String,
+    pub client: Option<String>,
+    pub method: String,
+    pub uri: String,
+    pub status: u16,
+    pub bytes: Option<usize>,
+    pub latency_ms: f64,
+    pub user_agent: Option<String>,
+    pub referer: Option<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+}
+
+impl AccessLogEntry {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Unable to serialize access log entry")
+    }
+
+    pub fn to_combined(&self) -> String {
+        let dash = |v: &Option<String>| v.clone().unwrap_or_else(|| "-".to_string());
+        format!(
+            "{} - - [{}] \"{} {} HTTP/1.1\" {} {} \"{}\" \"{}\"",
+            dash(&self.client),
+            self.timestamp.format("%d/%b/%Y:%H:%M:%S %z"),
+            self.method,
+            self.uri,
+            self.status,
+            self.bytes.map(|b| b.to_string()).unwrap_or_else(|| "-".to_string()),
+            dash(&self.referer),
+            dash(&self.user_agent),
+        )
+    }
+}
+
+#[derive(Clone)]
+struct RequestLog {
+    id: Option<String>,
+    timestamp: DateTime<Utc>,
+    start: Instant,
+}
+
+// Reuses the caller's id when it looks sane so a request can be followed
+// across shards, otherwise mints a new one.
+fn request_id_for(request: &Request<'_>) -> String {
+    match request.headers().get_one(REQUEST_ID_HEADER) {
+        Some(id) if !id.is_empty()
+            && id.len() <= MAX_REQUEST_ID_LEN
+            && id.bytes().all(|b| b.is_ascii_graphic()) => id.to_string(),
+        _ => Uuid::new_v4().as_simple().to_string(),
+    }
+}
+
+// The id assigned to the current request by the Logger fairing.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    // For forwarding on outgoing calls.
+    pub fn header(&self) -> (&'static str, &str) {
+        (REQUEST_ID_HEADER, &self.0)
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RequestId {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let log = request.local_cache(|| RequestLog {
+            id: Some(request_id_for(request)),
+            timestamp: Utc::now(),
+            start: Instant::now(),
+        });
+        match &log.id {
+            Some(id) => Outcome::Success(RequestId(id.clone())),
+            None => Outcome::Success(RequestId(request_id_for(request))),
+        }
+    }
+}
 
 pub struct Logger {
+    config: RwLock<LoggerConfig>,
+}
+
+impl Logger {
+    pub fn new() -> Logger {
+        Logger::with_config(LoggerConfig::default())
+    }
+
+    pub fn with_config(config: LoggerConfig) -> Logger {
+        Logger { config: RwLock::new(config) }
+    }
+
+    pub fn config(&self) -> LoggerConfig {
+        self.config.read().expect("Unable to get read lock on logger config").clone()
+    }
+}
+
+impl Default for Logger {
+    fn default() -> Logger {
+        Logger::new()
+    }
 }
 
 #[rocket::async_trait]
@@ -13,11 +246,234 @@ impl Fairing for Logger {
     fn info(&self) -> Info {
         Info {
             name: "Request Logger",
-            kind: Kind::Request
+            kind: Kind::Ignite | Kind::Request | Kind::Response
+        }
+    }
+
+    // Reads the `access_log` table, if there is one.
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        if rocket.figment().find_value("access_log").is_ok() {
+            match rocket.figment().extract_inner::<LoggerConfig>("access_log") {
+                Ok(config) => {
+                    *self.config.write().expect("Unable to get write lock on logger config") = config;
+                },
+                Err(e) => {
+                    log::error!("Invalid access_log config: {}", e);
+                    return Err(rocket);
+                },
+            }
         }
+        Ok(rocket)
     }
+
     async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
-        let now: DateTime<Utc> = Utc::now();
-        println!("{}: {}", now, request.to_string());
+        let id = request_id_for(request);
+        request.local_cache(|| RequestLog {
+            id: Some(id),
+            timestamp: Utc::now(),
+            start: Instant::now(),
+        });
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let log = request.local_cache(|| RequestLog {
+            id: None,
+            timestamp: Utc::now(),
+            start: Instant::now(),
+        });
+        let config = self.config();
+        let header = |name: &str| request.headers().get_one(name).map(|value| {
+            if config.redacts_header(name) { REDACTED.to_string() } else { value.to_string() }
+        });
+        let (method, uri) = match rate_limit_fairing::original_request(request) {
+            Some(original) => (original.method, &original.uri),
+            None => (request.method(), request.uri()),
+        };
+        let entry = AccessLogEntry {
+            timestamp: log.timestamp,
+            request_id: log.id.clone().unwrap_or_default(),
+            client: request.client_ip().map(|ip| ip.to_string()),
+            method: method.to_string(),
+            uri: config.redact_uri(uri.path().as_str(), uri.query().map(|q| q.as_str())),
+            status: response.status().code,
+            bytes: response.body().preset_size(),
+            latency_ms: log.start.elapsed().as_secs_f64() * 1000.0,
+            user_agent: header("User-Agent"),
+            referer: header("Referer"),
+            headers: config.headers.iter()
+                .filter_map(|name| Some((name.clone(), header(name)?)))
+                .collect(),
+        };
+
+        if let Some(id) = &log.id {
+            response.set_header(Header::new(REQUEST_ID_HEADER, id.clone()));
+        }
+
+        let level = if let Some(level) = config.level_for(&entry) {
+            level
+        } else {
+            return;
+        };
+        match config.format {
+            LogFormat::Json => log::log!(level, "{}", entry.to_json()),
+            LogFormat::Combined => log::log!(level, "{}", entry.to_combined()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use rocket::get;
+    use rocket::local::blocking::Client;
+    use rocket::routes;
+
+    #[get("/")]
+    fn echo(request_id: RequestId) -> String {
+        request_id.to_string()
+    }
+
+    fn entry() -> AccessLogEntry {
+        AccessLogEntry {
+            timestamp: Utc.with_ymd_and_hms(2022, 9, 1, 12, 30, 5).unwrap(),
+            request_id: "abc".to_string(),
+            client: Some("10.0.0.1".to_string()),
+            method: "GET".to_string(),
+            uri: "/default/abc".to_string(),
+            status: 200,
+            bytes: Some(42),
+            latency_ms: 1.5,
+            user_agent: Some("curl/7.0".to_string()),
+            referer: None,
+            headers: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn json() {
+        let line = entry().to_json();
+        assert!(!line.contains('\n'));
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["timestamp"], "2022-09-01T12:30:05Z");
+        assert_eq!(value["status"], 200);
+        assert_eq!(value["bytes"], 42);
+        assert_eq!(value["latency_ms"], 1.5);
+        assert_eq!(value["client"], "10.0.0.1");
+        assert_eq!(value["user_agent"], "curl/7.0");
+        assert_eq!(value["request_id"], "abc");
+    }
+
+    #[test]
+    fn combined() {
+        assert_eq!(
+            entry().to_combined(),
+            "10.0.0.1 - - [01/Sep/2022:12:30:05 +0000] \"GET /default/abc HTTP/1.1\" 200 42 \"-\" \"curl/7.0\""
+        );
+    }
+
+    #[test]
+    fn config_is_read_from_rocket() {
+        let figment = rocket::Config::figment().merge(("access_log.format", "combined"));
+        let logger = Logger::new();
+        let rocket = rocket::custom(figment);
+        let rocket = rocket::tokio::runtime::Runtime::new().unwrap()
+            .block_on(logger.on_ignite(rocket));
+        assert!(rocket.is_ok());
+        assert_eq!(logger.config().format, LogFormat::Combined);
+    }
+
+    #[test]
+    fn request_id_is_assigned_and_echoed() {
+        let rocket = rocket::build()
+            .attach(Logger::new())
+            .mount("/", routes![echo]);
+        let client = Client::tracked(rocket).expect("valid rocket instance");
+
+        let response = client.get("/").dispatch();
+        let header = response.headers().get_one(REQUEST_ID_HEADER).unwrap().to_string();
+        assert_eq!(header.len(), 32);
+        assert_eq!(response.into_string().unwrap(), header);
+
+        let response = client.get("/")
+            .header(Header::new(REQUEST_ID_HEADER, "upstream-1"))
+            .dispatch();
+        assert_eq!(response.headers().get_one(REQUEST_ID_HEADER), Some("upstream-1"));
+        assert_eq!(response.into_string().unwrap(), "upstream-1");
+
+        // ids that could break log lines are replaced
+        let response = client.get("/")
+            .header(Header::new(REQUEST_ID_HEADER, "bad id"))
+            .dispatch();
+        assert_ne!(response.headers().get_one(REQUEST_ID_HEADER), Some("bad id"));
+    }
+
+    #[test]
+    fn redact_uri() {
+        let config = LoggerConfig::default();
+        assert_eq!(config.redact_uri("/a", None), "/a");
+        assert_eq!(
+            config.redact_uri("/a", Some("x=1&Token=abc&password&y=2")),
+            "/a?x=1&Token=[REDACTED]&password=[REDACTED]&y=2"
+        );
+    }
+
+    #[test]
+    fn sampling_and_levels() {
+        let config = LoggerConfig {
+            sample_ratio: 0.0,
+            slow_threshold_ms: Some(100.0),
+            ..LoggerConfig::default()
+        };
+        let mut e = entry();
+        assert_eq!(config.level_for(&e), None);
+
+        e.status = 404;
+        assert_eq!(config.level_for(&e), Some(log::Level::Info));
+        let quiet = LoggerConfig { always_log_errors: false, ..config.clone() };
+        assert_eq!(quiet.level_for(&e), None);
+
+        e.status = 503;
+        assert_eq!(quiet.level_for(&e), Some(log::Level::Error));
+
+        e.status = 200;
+        e.latency_ms = 250.0;
+        assert_eq!(config.level_for(&e), Some(log::Level::Warn));
+    }
+
+    #[test]
+    fn sample_ratio_is_roughly_honoured() {
+        let config = LoggerConfig { sample_ratio: 0.25, ..LoggerConfig::default() };
+        let mut e = entry();
+        let mut logged = 0;
+        for i in 0..4000 {
+            e.request_id = format!("request-{}", i);
+            if config.level_for(&e).is_some() {
+                logged += 1;
+            }
+        }
+        assert!(logged > 800 && logged < 1200, "logged {}", logged);
+
+        // the same request id always gets the same decision
+        e.request_id = "fixed".to_string();
+        assert_eq!(config.level_for(&e), config.level_for(&e));
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+    }
+
+    #[test]
+    fn config_redactions_are_read_from_rocket() {
+        let figment = rocket::Config::figment()
+            .merge(("access_log.sample_ratio", 0.5))
+            .merge(("access_log.headers", vec!["Authorization", "X-Forwarded-For"]));
+        let logger = Logger::new();
+        let rocket = rocket::tokio::runtime::Runtime::new().unwrap()
+            .block_on(logger.on_ignite(rocket::custom(figment)));
+        assert!(rocket.is_ok());
+        let config = logger.config();
+        assert_eq!(config.sample_ratio, 0.5);
+        assert_eq!(config.headers, vec!["Authorization", "X-Forwarded-For"]);
+        assert!(config.redacts_header("authorization"));
+        assert!(!config.redacts_header("X-Forwarded-For"));
     }
 }
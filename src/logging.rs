@@ -0,0 +1,285 @@
+use chrono::{SecondsFormat, Utc};
+use log::LevelFilter;
+use log::Log;
+use log::Metadata;
+use log::Record;
+use serde::Deserialize;
+use std::fs;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Write;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+use std::sync::RwLock;
+
+static LOGGING: OnceLock<Logging> = OnceLock::new();
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct LogConfig {
+    // default level plus per-module overrides, e.g. "info,bangbang::spatial=debug,rocket=warn"
+    pub filter: String,
+    // log to this file instead of stdout
+    pub file: Option<String>,
+    pub max_file_bytes: u64,
+    // rotated files kept next to the live one
+    pub max_files: usize,
+}
+
+impl Default for LogConfig {
+    fn default() -> LogConfig {
+        LogConfig {
+            filter: "info".to_string(),
+            file: None,
+            max_file_bytes: 10 * 1024 * 1024,
+            max_files: 5,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Filters {
+    default: LevelFilter,
+    // most specific module first
+    modules: Vec<(String, LevelFilter)>,
+}
+
+impl Filters {
+    pub fn level_for(&self, target: &str) -> LevelFilter {
+        for (module, level) in self.modules.iter() {
+            if target == module
+                || (target.starts_with(module.as_str()) && target[module.len() ..].starts_with("::")) {
+                return *level;
+            }
+        }
+        self.default
+    }
+
+    pub fn max_level(&self) -> LevelFilter {
+        self.modules.iter().map(|(_, l)| *l).fold(self.default, Ord::max)
+    }
+}
+
+impl FromStr for Filters {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Filters, String> {
+        let mut filters = Filters { default: LevelFilter::Info, modules: Vec::new() };
+        for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            match directive.split_once('=') {
+                Some((module, level)) => {
+                    let level = LevelFilter::from_str(level.trim())
+                    .map_err(|_| format!("Invalid log level in {}", directive))?;
+                    filters.modules.retain(|(m, _)| m != module.trim());
+                    filters.modules.push((module.trim().to_string(), level));
+                },
+                None => {
+                    filters.default = LevelFilter::from_str(directive)
+                    .map_err(|_| format!("Invalid log level {}", directive))?;
+                },
+            }
+        }
+        filters.modules.sort_by_key(|(module, _)| std::cmp::Reverse(module.len()));
+        Ok(filters)
+    }
+}
+
+// Size-based rotation: when the live file would pass max_bytes it becomes
+// `<path>.1`, `<path>.1` becomes `<path>.2` and so on up to max_files.
+pub struct RotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: usize,
+    file: File,
+    written: u64,
+}
+
+impl RotatingFile {
+    pub fn open(path: &str, max_bytes: u64, max_files: usize) -> io::Result<RotatingFile> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let written = file.metadata()?.len();
+        Ok(RotatingFile {
+            path: PathBuf::from(path),
+            max_bytes,
+            max_files,
+            file,
+            written,
+        })
+    }
+
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.max_files == 0 {
+            self.file = File::create(&self.path)?;
+        } else {
+            let _ = fs::remove_file(self.rotated(self.max_files));
+            for n in (1 .. self.max_files).rev() {
+                if self.rotated(n).exists() {
+                    fs::rename(self.rotated(n), self.rotated(n + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated(1))?;
+            self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        }
+        self.written = 0;
+        Ok(())
+    }
+
+    pub fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let len = line.len() as u64 + 1;
+        if self.written > 0 && self.written + len > self.max_bytes {
+            self.rotate()?;
+        }
+        writeln!(self.file, "{}", line)?;
+        self.written += len;
+        Ok(())
+    }
+}
+
+enum Output {
+    Stdout,
+    File(RotatingFile),
+}
+
+pub struct Logging {
+    filters: RwLock<Filters>,
+    output: Mutex<Output>,
+}
+
+impl Logging {
+    pub fn set_filters(&self, filters: Filters) {
+        log::set_max_level(filters.max_level());
+        *self.filters.write().expect("Unable to get write lock on log filters") = filters;
+    }
+
+    pub fn filters(&self) -> Filters {
+        self.filters.read().expect("Unable to get read lock on log filters").clone()
+    }
+
+    fn set_output(&self, config: &LogConfig) -> io::Result<()> {
+        let output = match &config.file {
+            Some(path) => Output::File(RotatingFile::open(path, config.max_file_bytes, config.max_files)?),
+            None => Output::Stdout,
+        };
+        *self.output.lock().expect("Unable to lock log output") = output;
+        Ok(())
+    }
+}
+
+impl Log for Logging {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.filters.read()
+        .expect("Unable to get read lock on log filters")
+        .level_for(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = format!(
+            "{} {:<5} {}: {}",
+            Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            record.level(),
+            record.target(),
+            record.args()
+        );
+        let mut output = self.output.lock().expect("Unable to lock log output");
+        match &mut *output {
+            Output::Stdout => println!("{}", line),
+            Output::File(file) => {
+                if let Err(e) = file.write_line(&line) {
+                    eprintln!("Unable to write log file: {}", e);
+                }
+            },
+        }
+    }
+
+    fn flush(&self) {
+        if let Output::File(file) = &mut *self.output.lock().expect("Unable to lock log output") {
+            let _ = file.file.flush();
+        }
+    }
+}
+
+// Installs the crate logger. Calling it again reconfigures the installed one.
+pub fn init(config: &LogConfig) -> Result<&'static Logging, String> {
+    let filters: Filters = config.filter.parse()?;
+    if let Some(logging) = LOGGING.get() {
+        logging.set_output(config).map_err(|e| e.to_string())?;
+        logging.set_filters(filters);
+        return Ok(logging);
+    }
+
+    let logging = LOGGING.get_or_init(|| Logging {
+        filters: RwLock::new(filters.clone()),
+        output: Mutex::new(Output::Stdout),
+    });
+    logging.set_output(config).map_err(|e| e.to_string())?;
+    log::set_logger(logging).map_err(|e| e.to_string())?;
+    log::set_max_level(filters.max_level());
+    Ok(logging)
+}
+
+pub fn logger() -> Option<&'static Logging> {
+    LOGGING.get()
+}
+
+// Changes filtering on the installed logger while running.
+pub fn set_filter(spec: &str) -> Result<(), String> {
+    let filters: Filters = spec.parse()?;
+    match LOGGING.get() {
+        Some(logging) => {
+            logging.set_filters(filters);
+            Ok(())
+        },
+        None => Err("Logging has not been initialized".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_filters() {
+        let filters: Filters = "warn,bangbang=debug,bangbang::spatial=trace".parse().unwrap();
+        assert_eq!(filters.level_for("rocket::server"), LevelFilter::Warn);
+        assert_eq!(filters.level_for("bangbang::auth"), LevelFilter::Debug);
+        assert_eq!(filters.level_for("bangbang::spatial"), LevelFilter::Trace);
+        assert_eq!(filters.level_for("bangbangbang"), LevelFilter::Warn);
+        assert_eq!(filters.max_level(), LevelFilter::Trace);
+
+        assert!("loud".parse::<Filters>().is_err());
+        assert!("bangbang=loud".parse::<Filters>().is_err());
+        assert_eq!("".parse::<Filters>().unwrap().level_for("x"), LevelFilter::Info);
+    }
+
+    #[test]
+    fn rotating_file() {
+        let dir = std::env::temp_dir().join(format!("bangbang-log-{}", uuid::Uuid::new_v4().as_simple()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("shard.log");
+        let path = path.to_str().unwrap();
+
+        let mut file = RotatingFile::open(path, 10, 2).unwrap();
+        for line in ["aaaaaaaa", "bbbbbbbb", "cccccccc", "dddddddd"] {
+            file.write_line(line).unwrap();
+        }
+        assert_eq!(fs::read_to_string(path).unwrap(), "dddddddd\n");
+        assert_eq!(fs::read_to_string(format!("{}.1", path)).unwrap(), "cccccccc\n");
+        assert_eq!(fs::read_to_string(format!("{}.2", path)).unwrap(), "bbbbbbbb\n");
+        assert!(!PathBuf::from(format!("{}.3", path)).exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
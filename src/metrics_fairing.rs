@@ -0,0 +1,392 @@
+use crate::rate_limit_fairing;
+use rocket::Build;
+use rocket::Data;
+use rocket::Request;
+use rocket::Response;
+use rocket::Rocket;
+use rocket::State;
+use rocket::fairing;
+use rocket::fairing::Fairing;
+use rocket::fairing::Info;
+use rocket::fairing::Kind;
+use rocket::get;
+use rocket::http::ContentType;
+use rocket::request;
+use rocket::request::FromRequest;
+use rocket::routes;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+pub const LATENCY_BUCKETS: &[f64] = &[0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+type Labels = Vec<(String, String)>;
+type Collector = Box<dyn Fn(&MetricsRegistry) + Send + Sync>;
+
+#[derive(Clone, Debug)]
+struct Histogram {
+    bounds: Vec<f64>,
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &[f64]) -> Histogram {
+        Histogram {
+            bounds: bounds.to_vec(),
+            counts: vec![0; bounds.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        for (bound, count) in self.bounds.iter().zip(self.counts.iter_mut()) {
+            if value <= *bound {
+                *count += 1;
+            }
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+struct Family<T> {
+    help: &'static str,
+    buckets: &'static [f64],
+    series: BTreeMap<Labels, T>,
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn format_labels(labels: &[(String, String)], extra: Option<(&str, &str)>) -> String {
+    let mut parts: Vec<String> = labels.iter()
+    .map(|(k, v)| format!("{}=\"{}\"", k, escape(v)))
+    .collect();
+    if let Some((k, v)) = extra {
+        parts.push(format!("{}=\"{}\"", k, escape(v)));
+    }
+    if parts.is_empty() {
+        return String::new();
+    }
+    format!("{{{}}}", parts.join(","))
+}
+
+fn to_labels(labels: &[(&str, &str)]) -> Labels {
+    labels.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+}
+
+// Counters, gauges and histograms rendered in the Prometheus text format.
+// Families are registered up front with their help text; observations for
+// unregistered names are dropped.
+pub struct MetricsRegistry {
+    counters: Mutex<BTreeMap<&'static str, Family<f64>>>,
+    gauges: Mutex<BTreeMap<&'static str, Family<f64>>>,
+    histograms: Mutex<BTreeMap<&'static str, Family<Histogram>>>,
+    collectors: Mutex<Vec<Collector>>,
+}
+
+impl MetricsRegistry {
+    pub fn new() -> MetricsRegistry {
+        let registry = MetricsRegistry {
+            counters: Mutex::new(BTreeMap::new()),
+            gauges: Mutex::new(BTreeMap::new()),
+            histograms: Mutex::new(BTreeMap::new()),
+            collectors: Mutex::new(Vec::new()),
+        };
+        registry.register_counter("http_requests_total", "Total HTTP requests by route and status.");
+        registry.register_histogram("http_request_duration_seconds", "HTTP request latency by route and status.", LATENCY_BUCKETS);
+        registry
+    }
+
+    pub fn register_counter(&self, name: &'static str, help: &'static str) {
+        self.counters.lock().expect("Unable to lock counters")
+        .entry(name)
+        .or_insert(Family { help, buckets: &[], series: BTreeMap::new() });
+    }
+
+    pub fn register_gauge(&self, name: &'static str, help: &'static str) {
+        self.gauges.lock().expect("Unable to lock gauges")
+        .entry(name)
+        .or_insert(Family { help, buckets: &[], series: BTreeMap::new() });
+    }
+
+    pub fn register_histogram(&self, name: &'static str, help: &'static str, buckets: &'static [f64]) {
+        self.histograms.lock().expect("Unable to lock histograms")
+        .entry(name)
+        .or_insert(Family { help, buckets, series: BTreeMap::new() });
+    }
+
+    // Collectors run before every render, for values cheaper to read than to track.
+    pub fn add_collector<F>(&self, collector: F) where F: Fn(&MetricsRegistry) + Send + Sync + 'static {
+        self.collectors.lock().expect("Unable to lock collectors").push(Box::new(collector));
+    }
+
+    pub fn increment(&self, name: &str, labels: &[(&str, &str)]) {
+        let mut counters = self.counters.lock().expect("Unable to lock counters");
+        if let Some(family) = counters.get_mut(name) {
+            *family.series.entry(to_labels(labels)).or_insert(0.0) += 1.0;
+        }
+    }
+
+    pub fn set_gauge(&self, name: &str, labels: &[(&str, &str)], value: f64) {
+        let mut gauges = self.gauges.lock().expect("Unable to lock gauges");
+        if let Some(family) = gauges.get_mut(name) {
+            family.series.insert(to_labels(labels), value);
+        }
+    }
+
+    pub fn clear_gauge(&self, name: &str) {
+        let mut gauges = self.gauges.lock().expect("Unable to lock gauges");
+        if let Some(family) = gauges.get_mut(name) {
+            family.series.clear();
+        }
+    }
+
+    pub fn observe(&self, name: &str, labels: &[(&str, &str)], value: f64) {
+        let mut histograms = self.histograms.lock().expect("Unable to lock histograms");
+        if let Some(family) = histograms.get_mut(name) {
+            let buckets = family.buckets;
+            family.series.entry(to_labels(labels))
+            .or_insert_with(|| Histogram::new(buckets))
+            .observe(value);
+        }
+    }
+
+    pub fn observe_duration(&self, name: &str, labels: &[(&str, &str)], duration: Duration) {
+        self.observe(name, labels, duration.as_secs_f64());
+    }
+
+    pub fn render(&self) -> String {
+        for collector in self.collectors.lock().expect("Unable to lock collectors").iter() {
+            collector(self);
+        }
+
+        let mut out = String::new();
+        for (kind, families) in [
+            ("counter", &self.counters),
+            ("gauge", &self.gauges),
+        ] {
+            for (name, family) in families.lock().expect("Unable to lock metrics").iter() {
+                writeln!(out, "# HELP {} {}", name, family.help).unwrap();
+                writeln!(out, "# TYPE {} {}", name, kind).unwrap();
+                for (labels, value) in family.series.iter() {
+                    writeln!(out, "{}{} {}", name, format_labels(labels, None), value).unwrap();
+                }
+            }
+        }
+        for (name, family) in self.histograms.lock().expect("Unable to lock histograms").iter() {
+            writeln!(out, "# HELP {} {}", name, family.help).unwrap();
+            writeln!(out, "# TYPE {} histogram", name).unwrap();
+            for (labels, histogram) in family.series.iter() {
+                for (bound, count) in histogram.bounds.iter().zip(histogram.counts.iter()) {
+                    let le = bound.to_string();
+                    writeln!(out, "{}_bucket{} {}", name, format_labels(labels, Some(("le", &le))), count).unwrap();
+                }
+                writeln!(out, "{}_bucket{} {}", name, format_labels(labels, Some(("le", "+Inf"))), histogram.count).unwrap();
+                writeln!(out, "{}_sum{} {}", name, format_labels(labels, None), histogram.sum).unwrap();
+                writeln!(out, "{}_count{} {}", name, format_labels(labels, None), histogram.count).unwrap();
+            }
+        }
+        out
+    }
+}
+
+impl Default for MetricsRegistry {
+    fn default() -> MetricsRegistry {
+        MetricsRegistry::new()
+    }
+}
+
+#[derive(Clone, Copy)]
+struct RequestStart(Option<Instant>);
+
+// Values noted while a request is handled, such as time spent waiting on a
+// lock. The fairing records them into histograms of the same name once the
+// response is ready, so whatever noted them needn't know about the registry.
+#[derive(Default)]
+pub struct Measurements {
+    observed: Mutex<Vec<(&'static str, Labels, f64)>>,
+}
+
+impl Measurements {
+    pub fn of<'r>(request: &'r Request<'_>) -> &'r Measurements {
+        request.local_cache(Measurements::default)
+    }
+
+    pub fn observe(&self, name: &'static str, labels: &[(&str, &str)], value: f64) {
+        self.observed.lock().expect("Unable to lock measurements")
+        .push((name, to_labels(labels), value));
+    }
+
+    pub fn observe_duration(&self, name: &'static str, labels: &[(&str, &str)], duration: Duration) {
+        self.observe(name, labels, duration.as_secs_f64());
+    }
+
+    fn take(&self) -> Vec<(&'static str, Labels, f64)> {
+        std::mem::take(&mut *self.observed.lock().expect("Unable to lock measurements"))
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for &'r Measurements {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        request::Outcome::Success(Measurements::of(request))
+    }
+}
+
+// Records request counts, latency and any Measurements for every response
+// and serves the registry at `/metrics`.
+pub struct Metrics {
+    registry: Arc<MetricsRegistry>,
+}
+
+impl Metrics {
+    pub fn new(registry: Arc<MetricsRegistry>) -> Metrics {
+        Metrics { registry }
+    }
+}
+
+#[get("/metrics")]
+fn metrics(registry: &State<Arc<MetricsRegistry>>) -> (ContentType, String) {
+    (ContentType::new("text", "plain").with_params(("version", "0.0.4")), registry.render())
+}
+
+#[rocket::async_trait]
+impl Fairing for Metrics {
+    fn info(&self) -> Info {
+        Info {
+            name: "Prometheus Metrics",
+            kind: Kind::Ignite | Kind::Request | Kind::Response
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        Ok(rocket
+            .manage(self.registry.clone())
+            .mount("/", routes![metrics]))
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        request.local_cache(|| RequestStart(Some(Instant::now())));
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        for (name, labels, value) in Measurements::of(request).take() {
+            let labels: Vec<(&str, &str)> = labels.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+            self.registry.observe(name, &labels, value);
+        }
+
+        let elapsed = match request.local_cache(|| RequestStart(None)) {
+            RequestStart(Some(start)) => start.elapsed(),
+            RequestStart(None) => return,
+        };
+        let (method, route) = match rate_limit_fairing::original_request(request) {
+            Some(original) => (original.method, original.route.clone()),
+            None => (request.method(), request.route().map(|route| route.uri.to_string())),
+        };
+        let method = method.to_string();
+        let route = route.unwrap_or_else(|| "unmatched".to_string());
+        let status = response.status().code.to_string();
+        let labels = [("method", method.as_str()), ("route", route.as_str()), ("status", status.as_str())];
+
+        self.registry.increment("http_requests_total", &labels);
+        self.registry.observe_duration("http_request_duration_seconds", &labels, elapsed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::http::Status;
+    use rocket::local::blocking::Client;
+
+    #[get("/hello/<name>")]
+    fn hello(name: &str) -> String {
+        format!("hello {}", name)
+    }
+
+    #[test]
+    fn histogram_buckets_are_cumulative() {
+        let mut h = Histogram::new(&[1.0, 5.0]);
+        h.observe(0.5);
+        h.observe(3.0);
+        h.observe(10.0);
+        assert_eq!(h.counts, vec![1, 2]);
+        assert_eq!(h.count, 3);
+        assert_eq!(h.sum, 13.5);
+    }
+
+    #[test]
+    fn render() {
+        let registry = MetricsRegistry::new();
+        registry.register_gauge("objects", "Tracked objects.");
+        registry.set_gauge("objects", &[("world", "a\"b")], 3.0);
+        registry.increment("not_registered", &[]);
+        let out = registry.render();
+        assert!(out.contains("# TYPE objects gauge\n"));
+        assert!(out.contains("objects{world=\"a\\\"b\"} 3\n"));
+        assert!(!out.contains("not_registered"));
+    }
+
+    #[test]
+    fn collectors_run_on_render() {
+        let registry = MetricsRegistry::new();
+        registry.register_gauge("answer", "The answer.");
+        registry.add_collector(|r| r.set_gauge("answer", &[], 42.0));
+        assert!(registry.render().contains("answer 42\n"));
+    }
+
+    #[test]
+    fn fairing_records_requests() {
+        let registry = Arc::new(MetricsRegistry::new());
+        let rocket = rocket::build()
+            .attach(Metrics::new(registry.clone()))
+            .mount("/", routes![hello]);
+        let client = Client::tracked(rocket).expect("valid rocket instance");
+
+        assert_eq!(client.get("/hello/world").dispatch().status(), Status::Ok);
+        assert_eq!(client.get("/nope").dispatch().status(), Status::NotFound);
+
+        let response = client.get("/metrics").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let body = response.into_string().unwrap();
+        assert!(body.contains("http_requests_total{method=\"GET\",route=\"/hello/<name>\",status=\"200\"} 1\n"));
+        assert!(body.contains("http_requests_total{method=\"GET\",route=\"unmatched\",status=\"404\"} 1\n"));
+        assert!(body.contains("http_request_duration_seconds_count{method=\"GET\",route=\"/hello/<name>\",status=\"200\"} 1\n"));
+    }
+
+    #[get("/measured")]
+    fn measured(measurements: &Measurements) -> &'static str {
+        measurements.observe("widgets", &[("kind", "blue")], 3.0);
+        measurements.observe("unregistered", &[], 1.0);
+        "measured"
+    }
+
+    #[test]
+    fn fairing_records_measurements() {
+        let registry = Arc::new(MetricsRegistry::new());
+        registry.register_histogram("widgets", "Widgets per request.", &[1.0, 5.0]);
+        let rocket = rocket::build()
+            .attach(Metrics::new(registry.clone()))
+            .mount("/", routes![measured]);
+        let client = Client::tracked(rocket).expect("valid rocket instance");
+
+        for _ in 0..2 {
+            assert_eq!(client.get("/measured").dispatch().status(), Status::Ok);
+        }
+        let out = registry.render();
+        assert!(out.contains("widgets_bucket{kind=\"blue\",le=\"1\"} 0\n"));
+        assert!(out.contains("widgets_bucket{kind=\"blue\",le=\"5\"} 2\n"));
+        assert!(out.contains("widgets_sum{kind=\"blue\"} 6\n"));
+        assert!(!out.contains("unregistered"));
+    }
+}
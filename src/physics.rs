@@ -1,6 +1,19 @@
-const ACCELERATION_GRAVITY_EARTH: f32 = 9.80664; // m s^-2
+pub mod ballistics;
+pub mod blast;
+pub mod constraint;
+pub mod firing;
+pub mod gravity;
+pub mod orbit;
+pub mod response;
+pub mod world;
 
-pub fn force_from_gravity(mass: f32 /* kg */) -> f32 {
+use crate::units::Acceleration;
+use crate::units::Force;
+use crate::units::Mass;
+
+pub const ACCELERATION_GRAVITY_EARTH: Acceleration = Acceleration::meters_per_second_squared(9.80664);
+
+pub fn force_from_gravity(mass: Mass) -> Force {
     mass * ACCELERATION_GRAVITY_EARTH
 }
 
@@ -0,0 +1,303 @@
+use crate::geometry::Shape3D;
+use crate::geometry::Vertex3D;
+use crate::physics::ACCELERATION_GRAVITY_EARTH;
+use crate::units::Area;
+use crate::units::Mass;
+use crate::units::Time;
+use crate::units::Velocity;
+
+// kg m^-3 at sea level and 15 C
+pub const AIR_DENSITY_SEA_LEVEL: f32 = 1.225;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Projectile {
+    pub position: Vertex3D,
+    pub velocity: Vertex3D,
+    pub mass: Mass,
+    pub drag_coefficient: f32,
+    pub cross_section: Area,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Environment {
+    // kg m^-3
+    pub air_density: f32,
+    // m s^-1, air velocity relative to the ground
+    pub wind: Vertex3D,
+    pub gravity: Vertex3D,
+    // flat ground at this y; None for no ground
+    pub ground: Option<f32>,
+}
+
+impl Default for Environment {
+    fn default() -> Environment {
+        Environment {
+            air_density: AIR_DENSITY_SEA_LEVEL,
+            wind: Vertex3D { x: 0.0, y: 0.0, z: 0.0 },
+            gravity: Vertex3D { x: 0.0, y: -ACCELERATION_GRAVITY_EARTH.in_meters_per_second_squared(), z: 0.0 },
+            ground: Some(0.0),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SimulationConfig {
+    // integration step
+    pub timestep: Time,
+    // spacing of the returned trajectory points
+    pub sample_interval: Time,
+    // give up after this long in flight
+    pub max_time: Time,
+}
+
+impl Default for SimulationConfig {
+    fn default() -> SimulationConfig {
+        SimulationConfig {
+            timestep: Time::seconds(0.001),
+            sample_interval: Time::seconds(0.05),
+            max_time: Time::seconds(120.0),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Impact {
+    // index into the targets passed to simulate
+    Target { index: usize, point: Vertex3D },
+    Ground { point: Vertex3D },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trajectory {
+    // from the muzzle, every sample_interval, ending at the impact point if any
+    pub points: Vec<Vertex3D>,
+    // until impact or until max_time
+    pub time_of_flight: Time,
+    pub impact: Option<Impact>,
+    pub impact_velocity: Vertex3D,
+}
+
+impl Projectile {
+    // Quadratic drag against the air: F = -1/2 rho Cd A |v_air| v_air
+    pub fn acceleration(&self, velocity: Vertex3D, environment: &Environment) -> Vertex3D {
+        let air = velocity - environment.wind;
+        let k = 0.5 * environment.air_density * self.drag_coefficient * self.cross_section.in_square_meters()
+            / self.mass.in_kilograms();
+        environment.gravity + air * (-k * air.length())
+    }
+
+    // Speed at which drag balances gravity, in still air.
+    pub fn terminal_velocity(&self, environment: &Environment) -> Velocity {
+        let g = environment.gravity.length();
+        let drag = environment.air_density * self.drag_coefficient * self.cross_section.in_square_meters();
+        Velocity::meters_per_second((2.0 * self.mass.in_kilograms() * g / drag).sqrt())
+    }
+}
+
+// One RK4 step of position and velocity.
+pub(crate) fn rk4(projectile: &Projectile, environment: &Environment, position: Vertex3D, velocity: Vertex3D, dt: f32) -> (Vertex3D, Vertex3D) {
+    let accel = |v: Vertex3D| projectile.acceleration(v, environment);
+    let k1v = accel(velocity);
+    let k1x = velocity;
+    let k2v = accel(velocity + k1v * (dt / 2.0));
+    let k2x = velocity + k1v * (dt / 2.0);
+    let k3v = accel(velocity + k2v * (dt / 2.0));
+    let k3x = velocity + k2v * (dt / 2.0);
+    let k4v = accel(velocity + k3v * dt);
+    let k4x = velocity + k3v * dt;
+    let sum = |a: Vertex3D, b: Vertex3D, c: Vertex3D, d: Vertex3D| a + b * 2.0 + c * 2.0 + d;
+    (
+        position + sum(k1x, k2x, k3x, k4x) * (dt / 6.0),
+        velocity + sum(k1v, k2v, k3v, k4v) * (dt / 6.0),
+    )
+}
+
+// Fraction of the way from `from` to `to` at which the segment first meets
+// the target, if it does.
+fn first_hit(from: Vertex3D, to: Vertex3D, target: &Shape3D) -> Option<f32> {
+    let length = from.distance(to);
+    target.ray_intersect(from, to - from, length).map(|hit| hit.distance / length)
+}
+
+// Flies the projectile until it hits one of the targets, the ground, or
+// max_time runs out. The projectile is treated as a point; targets are
+// tested against the whole path between steps so fast shots can't tunnel.
+pub fn simulate(projectile: &Projectile, environment: &Environment, targets: &[Shape3D], config: &SimulationConfig) -> Trajectory {
+    let dt = config.timestep.in_seconds();
+    let sample_every = ((config.sample_interval / config.timestep).round() as u64).max(1);
+    let max_steps = (config.max_time / config.timestep).round() as u64;
+
+    let mut position = projectile.position;
+    let mut velocity = projectile.velocity;
+    let mut points = vec![position];
+
+    for step in 1 ..= max_steps {
+        let (next_position, next_velocity) = rk4(projectile, environment, position, velocity, dt);
+
+        let mut hit: Option<(f32, Impact)> = None;
+        for (index, target) in targets.iter().enumerate() {
+            if let Some(t) = first_hit(position, next_position, target) {
+                if hit.is_none_or(|(best, _)| t < best) {
+                    let point = position + (next_position - position) * t;
+                    hit = Some((t, Impact::Target { index, point }));
+                }
+            }
+        }
+        if let Some(ground) = environment.ground {
+            if next_position.y <= ground && position.y > ground {
+                let t = (position.y - ground) / (position.y - next_position.y);
+                if hit.is_none_or(|(best, _)| t < best) {
+                    let mut point = position + (next_position - position) * t;
+                    point.y = ground;
+                    hit = Some((t, Impact::Ground { point }));
+                }
+            }
+        }
+
+        if let Some((t, impact)) = hit {
+            let point = match impact {
+                Impact::Target { point, .. } | Impact::Ground { point } => point,
+            };
+            points.push(point);
+            return Trajectory {
+                points,
+                time_of_flight: Time::seconds((step - 1) as f32 * dt + t * dt),
+                impact: Some(impact),
+                impact_velocity: velocity + (next_velocity - velocity) * t,
+            };
+        }
+
+        position = next_position;
+        velocity = next_velocity;
+        if step % sample_every == 0 {
+            points.push(position);
+        }
+    }
+
+    Trajectory {
+        points,
+        time_of_flight: Time::seconds(max_steps as f32 * dt),
+        impact: None,
+        impact_velocity: velocity,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Quaternion;
+
+    fn v(x: f32, y: f32, z: f32) -> Vertex3D {
+        Vertex3D { x, y, z }
+    }
+
+    // roughly a 9 mm ball
+    fn shot(velocity: Vertex3D) -> Projectile {
+        Projectile {
+            position: v(0.0, 0.0, 0.0),
+            velocity,
+            mass: Mass::grams(8.0),
+            drag_coefficient: 0.47,
+            cross_section: Area::square_centimeters(0.64),
+        }
+    }
+
+    fn vacuum() -> Environment {
+        Environment { air_density: 0.0, ..Environment::default() }
+    }
+
+    fn range(trajectory: &Trajectory) -> f32 {
+        match trajectory.impact {
+            Some(Impact::Ground { point }) => point.x,
+            other => panic!("Expected a ground impact, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn matches_parabola_without_drag() {
+        let (vx, vy) = (30.0, 40.0);
+        let g = ACCELERATION_GRAVITY_EARTH.in_meters_per_second_squared();
+        let t = simulate(&shot(v(vx, vy, 0.0)), &vacuum(), &[], &SimulationConfig::default());
+
+        let flight = 2.0 * vy / g;
+        let time_of_flight = t.time_of_flight.in_seconds();
+        assert!((time_of_flight - flight).abs() < 1e-3, "{} vs {}", time_of_flight, flight);
+        assert!((range(&t) - vx * flight).abs() < 0.05);
+        assert!((t.impact_velocity.y + vy).abs() < 0.01);
+
+        // every sample sits on the parabola, give or take f32 rounding over
+        // thousands of steps
+        for (i, p) in t.points.iter().enumerate().take(t.points.len() - 1) {
+            let time = i as f32 * 0.05;
+            assert!((p.x - vx * time).abs() < 0.05);
+            assert!((p.y - (vy * time - 0.5 * g * time * time)).abs() < 0.05, "sample {} at {:?}", i, p);
+        }
+        let apex = t.points.iter().map(|p| p.y).fold(f32::MIN, f32::max);
+        assert!((apex - vy * vy / (2.0 * g)).abs() < 0.05);
+    }
+
+    #[test]
+    fn drag_and_wind() {
+        let velocity = v(200.0, 100.0, 0.0);
+        let config = SimulationConfig::default();
+        let still = simulate(&shot(velocity), &Environment::default(), &[], &config);
+        let vacuum = simulate(&shot(velocity), &vacuum(), &[], &config);
+        assert!(range(&still) < range(&vacuum) * 0.5);
+        assert!(still.time_of_flight < vacuum.time_of_flight);
+
+        let tail = simulate(&shot(velocity), &Environment { wind: v(10.0, 0.0, 0.0), ..Environment::default() }, &[], &config);
+        let head = simulate(&shot(velocity), &Environment { wind: v(-10.0, 0.0, 0.0), ..Environment::default() }, &[], &config);
+        assert!(range(&tail) > range(&still));
+        assert!(range(&head) < range(&still));
+
+        let cross = simulate(&shot(velocity), &Environment { wind: v(0.0, 0.0, 5.0), ..Environment::default() }, &[], &config);
+        match cross.impact {
+            Some(Impact::Ground { point }) => assert!(point.z > 1.0),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn falls_at_terminal_velocity() {
+        let mut dropped = shot(v(0.0, 0.0, 0.0));
+        dropped.position = v(0.0, 5000.0, 0.0);
+        let environment = Environment::default();
+        let t = simulate(&dropped, &environment, &[], &SimulationConfig::default());
+        let terminal = dropped.terminal_velocity(&environment);
+        let terminal = terminal.in_meters_per_second();
+        assert!((t.impact_velocity.y + terminal).abs() < 0.01 * terminal);
+    }
+
+    #[test]
+    fn hits_the_nearest_target() {
+        let targets = [
+            Shape3D::Cuboid { center: v(60.0, 0.0, 0.0), width: 1.0, height: 100.0, length: 100.0, orientation: Quaternion::IDENTITY },
+            // a thin plate nearer the muzzle than a single step's travel
+            Shape3D::Cuboid { center: v(40.0, 0.0, 0.0), width: 0.01, height: 100.0, length: 100.0, orientation: Quaternion::IDENTITY },
+        ];
+        let config = SimulationConfig { timestep: Time::seconds(0.01), ..SimulationConfig::default() };
+        let t = simulate(&shot(v(800.0, 0.0, 0.0)), &vacuum(), &targets, &config);
+        match t.impact {
+            Some(Impact::Target { index, point }) => {
+                assert_eq!(index, 1);
+                assert!((point.x - 39.995).abs() < 1e-3, "{:?}", point);
+            },
+            other => panic!("{:?}", other),
+        }
+        assert!((t.time_of_flight.in_seconds() - 39.995 / 800.0).abs() < 1e-5);
+        assert!((t.points.last().unwrap().x - 39.995).abs() < 1e-3);
+
+        let missed = simulate(&shot(v(800.0, 50.0, 0.0)), &vacuum(), &[Shape3D::Sphere { center: v(40.0, -5.0, 0.0), radius: 1.0 }], &config);
+        assert!(matches!(missed.impact, Some(Impact::Ground { .. })));
+    }
+
+    #[test]
+    fn gives_up_after_max_time() {
+        let environment = Environment { ground: None, ..vacuum() };
+        let config = SimulationConfig { max_time: Time::seconds(2.0), ..SimulationConfig::default() };
+        let t = simulate(&shot(v(1.0, 0.0, 0.0)), &environment, &[], &config);
+        assert_eq!(t.impact, None);
+        assert!((t.time_of_flight.in_seconds() - 2.0).abs() < 1e-4);
+        assert_eq!(t.points.len(), 41);
+    }
+}
@@ -0,0 +1,255 @@
+use crate::collision;
+use crate::geometry::Shape3D;
+use crate::geometry::Vertex3D;
+use crate::physics::world::BodyId;
+use crate::physics::world::World;
+use crate::units::Length;
+
+// How the impulse dies away between the centre and the edge of the blast.
+#[derive(Clone, Copy, Debug)]
+pub enum Falloff {
+    // full strength at the centre, nothing at the edge
+    Linear,
+    // full strength out to the core radius, then dropping with the square of
+    // the distance
+    InverseSquare { core: Length },
+    // given the distance as a fraction of the radius, 0 to 1, returns the
+    // fraction of the full impulse
+    Custom(fn(f32) -> f32),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Blast {
+    pub center: Vertex3D,
+    pub radius: Length,
+    // N s, received by a body at the centre
+    pub impulse: f32,
+    pub falloff: Falloff,
+    // whether bodies with colliders shield the ones behind them
+    pub occlusion: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BlastHit {
+    pub body: BodyId,
+    // N s, pointing away from the centre
+    pub impulse: Vertex3D,
+    // from the centre to the nearest part of the body
+    pub distance: Length,
+}
+
+impl Blast {
+    pub fn new(center: Vertex3D, radius: Length, impulse: f32) -> Blast {
+        Blast { center, radius, impulse, falloff: Falloff::Linear, occlusion: false }
+    }
+
+    pub fn with_falloff(mut self, falloff: Falloff) -> Blast {
+        self.falloff = falloff;
+        self
+    }
+
+    pub fn with_occlusion(mut self) -> Blast {
+        self.occlusion = true;
+        self
+    }
+
+    pub fn region(&self) -> Shape3D {
+        Shape3D::Sphere { center: self.center, radius: self.radius.in_meters() }
+    }
+
+    // Whether the radius is positive; a blast without reaches nothing.
+    fn has_reach(&self) -> bool {
+        self.radius.in_meters() > 0.0
+    }
+
+    // Size of the impulse at a distance from the centre.
+    pub fn impulse_at(&self, distance: Length) -> f32 {
+        let distance = distance.max(Length::ZERO);
+        if !self.has_reach() || distance > self.radius {
+            return 0.0;
+        }
+        let scale = match self.falloff {
+            Falloff::Linear => 1.0 - distance / self.radius,
+            Falloff::InverseSquare { core } => {
+                if distance <= core { 1.0 } else { (core / distance).powi(2) }
+            },
+            Falloff::Custom(curve) => curve(distance / self.radius).clamp(0.0, 1.0),
+        };
+        self.impulse * scale
+    }
+
+    // Pushes every body the blast reaches straight out from the centre.
+    // Bodies with colliders are reached if any part of them is in range and
+    // the rest if their position is. A body at the centre is thrown upwards.
+    pub fn apply(&self, world: &mut World) -> Vec<BlastHit> {
+        if !self.has_reach() {
+            return Vec::new();
+        }
+        let region = self.region();
+        let shapes: Vec<(BodyId, Shape3D)> = world.bodies()
+            .filter_map(|(id, body)| body.shape().map(|shape| (id, shape)))
+            .collect();
+        // anything the blast goes off inside, such as the bomb itself,
+        // doesn't get in the way
+        let point = Shape3D::Sphere { center: self.center, radius: 0.0 };
+        let obstacles: Vec<&(BodyId, Shape3D)> = shapes.iter()
+            .filter(|(_, shape)| self.occlusion && !collision::intersects(&point, shape))
+            .collect();
+
+        let mut reached = Vec::new();
+        for (id, body) in world.bodies() {
+            let distance = match body.shape() {
+                Some(shape) => match collision::collide(&region, &shape) {
+                    Some(contact) => (self.radius - Length::meters(contact.depth)).max(Length::ZERO),
+                    None => continue,
+                },
+                None => Length::meters((body.position - self.center).length()),
+            };
+            let reach = (body.position - self.center).length();
+            let shielded = obstacles.iter().any(|(other, shape)| {
+                *other != id && shape.ray_intersect(self.center, body.position - self.center, reach).is_some()
+            });
+            if !shielded {
+                reached.push((id, body.position, distance));
+            }
+        }
+
+        let mut hits = Vec::new();
+        for (id, position, distance) in reached {
+            let size = self.impulse_at(distance);
+            // a custom curve can hand back NaN
+            if !(size > 0.0 && size.is_finite()) {
+                continue;
+            }
+            let offset = position - self.center;
+            let direction = if offset.length() > 1e-6 { offset / offset.length() } else { Vertex3D { x: 0.0, y: 1.0, z: 0.0 } };
+            let impulse = direction * size;
+            if let Some(body) = world.body_mut(id) {
+                body.apply_impulse(impulse);
+            }
+            hits.push(BlastHit { body: id, impulse, distance });
+        }
+        hits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Quaternion;
+    use crate::physics::world::Body;
+    use crate::physics::world::WorldConfig;
+    use crate::units::Mass;
+
+    fn v(x: f32, y: f32, z: f32) -> Vertex3D {
+        Vertex3D { x, y, z }
+    }
+
+    fn m(meters: f32) -> Length {
+        Length::meters(meters)
+    }
+
+    fn ball(position: Vertex3D) -> Body {
+        Body::new(Mass::kilograms(2.0), position).with_collider(Shape3D::Sphere { center: v(0.0, 0.0, 0.0), radius: 0.5 })
+    }
+
+    #[test]
+    fn falloff_curves() {
+        let blast = Blast::new(v(0.0, 0.0, 0.0), m(10.0), 100.0);
+        assert_eq!(blast.impulse_at(m(0.0)), 100.0);
+        assert!((blast.impulse_at(m(2.5)) - 75.0).abs() < 1e-4);
+        assert_eq!(blast.impulse_at(m(10.0)), 0.0);
+        assert_eq!(blast.impulse_at(m(12.0)), 0.0);
+
+        let blast = blast.with_falloff(Falloff::InverseSquare { core: m(1.0) });
+        assert_eq!(blast.impulse_at(m(0.5)), 100.0);
+        assert!((blast.impulse_at(m(2.0)) - 25.0).abs() < 1e-4);
+        assert!((blast.impulse_at(m(4.0)) - 6.25).abs() < 1e-4);
+        assert_eq!(blast.impulse_at(m(11.0)), 0.0);
+
+        let blast = blast.with_falloff(Falloff::Custom(|f| if f < 0.5 { 1.0 } else { 0.2 }));
+        assert_eq!(blast.impulse_at(m(4.0)), 100.0);
+        assert!((blast.impulse_at(m(6.0)) - 20.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn pushes_bodies_in_range_outwards() {
+        let mut w = World::new(WorldConfig::default());
+        let near = w.add_body(ball(v(2.0, 0.0, 0.0)));
+        let far = w.add_body(ball(v(0.0, 0.0, -6.0)));
+        let outside = w.add_body(ball(v(0.0, 20.0, 0.0)));
+        // no collider, so only its position counts
+        let point = w.add_body(Body::new(Mass::kilograms(1.0), v(0.0, -3.0, 0.0)));
+
+        let hits = Blast::new(v(0.0, 0.0, 0.0), m(10.0), 100.0).apply(&mut w);
+        assert_eq!(hits.len(), 3);
+        let hit = |id| hits.iter().find(|h| h.body == id).copied();
+        assert!(hit(outside).is_none());
+
+        // measured to the surface of the ball, not its centre
+        let near_hit = hit(near).unwrap();
+        assert!((near_hit.distance.in_meters() - 1.5).abs() < 1e-4);
+        assert!((near_hit.impulse.x - 85.0).abs() < 1e-3);
+        assert!(near_hit.impulse.y.abs() < 1e-6 && near_hit.impulse.z.abs() < 1e-6);
+        assert!((w.body(near).unwrap().velocity.x - 42.5).abs() < 1e-3);
+
+        let far_hit = hit(far).unwrap();
+        assert!((far_hit.impulse.z + 45.0).abs() < 1e-3);
+
+        let point_hit = hit(point).unwrap();
+        assert!((point_hit.distance.in_meters() - 3.0).abs() < 1e-4);
+        assert!((w.body(point).unwrap().velocity.y + 70.0).abs() < 1e-3);
+        assert_eq!(w.body(outside).unwrap().velocity, v(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn degenerate_blasts_push_nothing() {
+        let mut w = World::new(WorldConfig::default());
+        let at_center = w.add_body(Body::new(Mass::kilograms(1.0), v(0.0, 0.0, 0.0)));
+        let ball = w.add_body(ball(v(0.0, 0.0, 0.0)));
+        for radius in [0.0, -1.0] {
+            let blast = Blast::new(v(0.0, 0.0, 0.0), m(radius), 100.0);
+            assert_eq!(blast.impulse_at(m(0.0)), 0.0);
+            assert!(blast.apply(&mut w).is_empty());
+        }
+        let hits = Blast::new(v(0.0, 0.0, 0.0), m(10.0), 100.0)
+            .with_falloff(Falloff::Custom(|_| f32::NAN))
+            .apply(&mut w);
+        assert!(hits.is_empty());
+        for id in [at_center, ball] {
+            assert_eq!(w.body(id).unwrap().velocity, v(0.0, 0.0, 0.0));
+        }
+    }
+
+    #[test]
+    fn walls_shield_bodies_behind_them() {
+        let scene = || {
+            let mut w = World::new(WorldConfig::default());
+            let wall = w.add_body(Body::fixed(v(3.0, 0.0, 0.0))
+                .with_collider(Shape3D::Cuboid { center: v(0.0, 0.0, 0.0), width: 0.5, height: 4.0, length: 4.0, orientation: Quaternion::IDENTITY }));
+            let hidden = w.add_body(ball(v(6.0, 0.0, 0.0)));
+            let open = w.add_body(ball(v(-6.0, 0.0, 0.0)));
+            // the bomb itself doesn't hide anything
+            let bomb = w.add_body(Body::new(Mass::kilograms(1.0), v(0.0, 0.0, 0.0))
+                .with_collider(Shape3D::Sphere { center: v(0.0, 0.0, 0.0), radius: 0.2 }));
+            (w, wall, hidden, open, bomb)
+        };
+
+        let blast = Blast::new(v(0.0, 0.0, 0.0), m(10.0), 100.0);
+        let (mut w, _, hidden, _, _) = scene();
+        assert!(blast.apply(&mut w).iter().any(|h| h.body == hidden));
+
+        let (mut w, wall, hidden, open, bomb) = scene();
+        let hits = blast.with_occlusion().apply(&mut w);
+        let ids: Vec<BodyId> = hits.iter().map(|h| h.body).collect();
+        assert!(!ids.contains(&hidden));
+        assert!(ids.contains(&open));
+        assert!(ids.contains(&wall));
+        assert!(ids.contains(&bomb));
+        assert_eq!(w.body(hidden).unwrap().velocity, v(0.0, 0.0, 0.0));
+        // static bodies are reported for damage but don't move
+        assert_eq!(w.body(wall).unwrap().velocity, v(0.0, 0.0, 0.0));
+        // the bomb sat at the centre, so it goes straight up
+        assert!(w.body(bomb).unwrap().velocity.y > 0.0);
+    }
+}
@@ -0,0 +1,444 @@
+use crate::geometry::Quaternion;
+use crate::geometry::Vertex3D;
+use crate::physics::world::Body;
+use crate::physics::world::BodyId;
+
+pub type ConstraintId = usize;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Joint {
+    // keeps the body centres a fixed distance apart, like a rigid rod
+    Distance { length: f32 },
+    // pulls the body centres towards the rest length; stiffness is N m^-1
+    // and damping N s m^-1
+    Spring { rest_length: f32, stiffness: f32, damping: f32 },
+    // pins the bodies together at a world-space point, free to turn
+    BallSocket { pivot: Vertex3D },
+    // as BallSocket, but only turning about the world-space axis
+    Hinge { pivot: Vertex3D, axis: Vertex3D },
+    // welds the bodies together as they are
+    Fixed,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Constraint {
+    pub a: BodyId,
+    pub b: BodyId,
+    pub joint: Joint,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ConstraintConfig {
+    pub velocity_iterations: u32,
+    pub position_iterations: u32,
+    // fraction of the remaining position error removed by each iteration
+    pub correction: f32,
+}
+
+impl Default for ConstraintConfig {
+    fn default() -> ConstraintConfig {
+        ConstraintConfig { velocity_iterations: 10, position_iterations: 10, correction: 0.8 }
+    }
+}
+
+// A constraint tied to its bodies as they were when it was added. Anchors
+// and axes are kept in each body's own frame so they turn with it.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Attached {
+    pub(crate) constraint: Constraint,
+    anchor_a: Vertex3D,
+    anchor_b: Vertex3D,
+    axis_a: Vertex3D,
+    axis_b: Vertex3D,
+    // b's orientation relative to a's
+    rest: Quaternion,
+    // impulse applied along each row last step, reapplied to start the next
+    // so long chains don't have to converge from nothing every time
+    impulses: [f32; 6],
+}
+
+// One scalar equation of a joint. An impulse of size lambda pushes b along
+// `linear` and turns it about `angular_b`, and does the opposite to a.
+struct Row {
+    linear: Vertex3D,
+    angular_a: Vertex3D,
+    angular_b: Vertex3D,
+    // how far the bodies are from satisfying the row
+    error: f32,
+}
+
+const AXES: [Vertex3D; 3] = [
+    Vertex3D { x: 1.0, y: 0.0, z: 0.0 },
+    Vertex3D { x: 0.0, y: 1.0, z: 0.0 },
+    Vertex3D { x: 0.0, y: 0.0, z: 1.0 },
+];
+
+fn perpendicular(v: Vertex3D) -> Vertex3D {
+    let other = if v.x.abs() < 0.9 { AXES[0] } else { AXES[1] };
+    v.cross(other).normalize()
+}
+
+impl Row {
+    fn effective_mass(&self, a: &Body, b: &Body) -> f32 {
+        (a.inverse_mass() + b.inverse_mass()) * self.linear.dot(self.linear)
+            + a.inverse_inertia() * self.angular_a.dot(self.angular_a)
+            + b.inverse_inertia() * self.angular_b.dot(self.angular_b)
+    }
+
+    fn speed(&self, a: &Body, b: &Body) -> f32 {
+        self.linear.dot(b.velocity - a.velocity)
+            + self.angular_b.dot(b.angular_velocity)
+            - self.angular_a.dot(a.angular_velocity)
+    }
+
+    fn apply_impulse(&self, a: &mut Body, b: &mut Body, lambda: f32) {
+        a.velocity -= self.linear * (lambda * a.inverse_mass());
+        a.angular_velocity -= self.angular_a * (lambda * a.inverse_inertia());
+        b.velocity += self.linear * (lambda * b.inverse_mass());
+        b.angular_velocity += self.angular_b * (lambda * b.inverse_inertia());
+    }
+
+    fn apply_displacement(&self, a: &mut Body, b: &mut Body, lambda: f32) {
+        a.position -= self.linear * (lambda * a.inverse_mass());
+        a.orientation = a.orientation.integrated(self.angular_a * (-lambda * a.inverse_inertia()));
+        b.position += self.linear * (lambda * b.inverse_mass());
+        b.orientation = b.orientation.integrated(self.angular_b * (lambda * b.inverse_inertia()));
+    }
+}
+
+impl Attached {
+    pub(crate) fn new(constraint: Constraint, a: &Body, b: &Body) -> Attached {
+        let local = |body: &Body, point: Vertex3D| body.orientation.conjugate().rotate(point - body.position);
+        let pivot = match constraint.joint {
+            Joint::BallSocket { pivot } | Joint::Hinge { pivot, .. } => pivot,
+            Joint::Fixed => (a.position + b.position) * 0.5,
+            Joint::Distance { .. } | Joint::Spring { .. } => Vertex3D::ZERO,
+        };
+        let axis = match constraint.joint {
+            Joint::Hinge { axis, .. } => axis.normalize(),
+            _ => AXES[1],
+        };
+        Attached {
+            constraint,
+            anchor_a: local(a, pivot),
+            anchor_b: local(b, pivot),
+            axis_a: a.orientation.conjugate().rotate(axis),
+            axis_b: b.orientation.conjugate().rotate(axis),
+            rest: a.orientation.conjugate() * b.orientation,
+            impulses: [0.0; 6],
+        }
+    }
+
+    fn rows(&self, a: &Body, b: &Body) -> Vec<Row> {
+        let mut rows = Vec::new();
+        match self.constraint.joint {
+            Joint::Spring { .. } => {},
+            Joint::Distance { length: target } => {
+                let d = b.position - a.position;
+                let distance = d.length();
+                if distance > 1e-6 {
+                    let n = d / distance;
+                    rows.push(Row { linear: n, angular_a: Vertex3D::ZERO, angular_b: Vertex3D::ZERO, error: distance - target });
+                }
+            },
+            Joint::BallSocket { .. } | Joint::Hinge { .. } | Joint::Fixed => {
+                let (arm_a, arm_b) = (a.orientation.rotate(self.anchor_a), b.orientation.rotate(self.anchor_b));
+                let gap = b.position + arm_b - (a.position + arm_a);
+                for n in AXES {
+                    rows.push(Row { linear: n, angular_a: arm_a.cross(n), angular_b: arm_b.cross(n), error: gap.dot(n) });
+                }
+            },
+        }
+        match self.constraint.joint {
+            Joint::Hinge { .. } => {
+                // the two bodies' copies of the axis stay lined up
+                let (axis_a, axis_b) = (a.orientation.rotate(self.axis_a), b.orientation.rotate(self.axis_b));
+                let misalignment = axis_a.cross(axis_b);
+                let t1 = perpendicular(axis_a);
+                for t in [t1, axis_a.cross(t1)] {
+                    rows.push(Row { linear: Vertex3D::ZERO, angular_a: t, angular_b: t, error: misalignment.dot(t) });
+                }
+            },
+            Joint::Fixed => {
+                let target = a.orientation * self.rest;
+                let twist = (b.orientation * target.conjugate()).to_rotation_vector();
+                for n in AXES {
+                    rows.push(Row { linear: Vertex3D::ZERO, angular_a: n, angular_b: n, error: twist.dot(n) });
+                }
+            },
+            _ => {},
+        }
+        rows
+    }
+
+    pub(crate) fn error(&self, a: &Body, b: &Body) -> f32 {
+        self.rows(a, b).iter().map(|r| r.error * r.error).sum::<f32>().sqrt()
+    }
+
+    // Springs work through forces, ahead of integration; the rest are solved
+    // as constraints.
+    pub(crate) fn apply_spring(&self, a: &mut Body, b: &mut Body) {
+        if let Joint::Spring { rest_length, stiffness, damping } = self.constraint.joint {
+            let d = b.position - a.position;
+            let distance = d.length();
+            if distance <= 1e-6 {
+                return;
+            }
+            let n = d / distance;
+            let pull = stiffness * (distance - rest_length) + damping * (b.velocity - a.velocity).dot(n);
+            a.apply_force(n * pull);
+            b.apply_force(n * -pull);
+        }
+    }
+
+    pub(crate) fn warm_start(&self, a: &mut Body, b: &mut Body) {
+        for (row, lambda) in self.rows(a, b).iter().zip(self.impulses) {
+            row.apply_impulse(a, b, lambda);
+        }
+    }
+
+    // Removes relative velocity along each row.
+    pub(crate) fn solve_velocity(&mut self, a: &mut Body, b: &mut Body) {
+        for (row, total) in self.rows(a, b).iter().zip(self.impulses.iter_mut()) {
+            let mass = row.effective_mass(a, b);
+            if mass > 0.0 {
+                let lambda = -row.speed(a, b) / mass;
+                row.apply_impulse(a, b, lambda);
+                *total += lambda;
+            }
+        }
+    }
+
+    // Moves the bodies directly to close up what drift is left, which keeps
+    // long chains taut without the energy a velocity bias would add.
+    pub(crate) fn solve_position(&self, a: &mut Body, b: &mut Body, correction: f32) {
+        for row in self.rows(a, b) {
+            let mass = row.effective_mass(a, b);
+            if mass > 0.0 {
+                row.apply_displacement(a, b, -correction * row.error / mass);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::ACCELERATION_GRAVITY_EARTH;
+    use crate::physics::world::World;
+    use crate::physics::world::WorldConfig;
+    use crate::units::Mass;
+    use crate::units::Time;
+    use std::f32::consts::PI;
+
+    fn v(x: f32, y: f32, z: f32) -> Vertex3D {
+        Vertex3D { x, y, z }
+    }
+
+    fn kg(mass: f32) -> Mass {
+        Mass::kilograms(mass)
+    }
+
+    fn world(gravity: bool) -> World {
+        let mut config = WorldConfig { timestep: Time::seconds(1.0 / 240.0), ..WorldConfig::default() };
+        if !gravity {
+            config.gravity = Vertex3D::ZERO;
+        }
+        World::new(config)
+    }
+
+    fn join(world: &mut World, a: BodyId, b: BodyId, joint: Joint) -> ConstraintId {
+        world.add_constraint(Constraint { a, b, joint }).unwrap()
+    }
+
+    fn worst_error(world: &World) -> f32 {
+        world.constraints().map(|(id, _)| world.constraint_error(id).unwrap()).fold(0.0, f32::max)
+    }
+
+    fn energy(world: &World) -> f32 {
+        let g = ACCELERATION_GRAVITY_EARTH.in_meters_per_second_squared();
+        world.bodies()
+            .filter(|(_, b)| !b.is_static())
+            .map(|(_, b)| b.kinetic_energy().in_joules() + b.mass.in_kilograms() * g * b.position.y)
+            .sum()
+    }
+
+    #[test]
+    fn pendulum_keeps_its_length_and_period() {
+        let mut w = world(true);
+        let length = 2.0;
+        let angle = 5f32.to_radians();
+        let pivot = w.add_body(Body::fixed(Vertex3D::ZERO));
+        let bob = w.add_body(Body::new(kg(1.0), v(length * angle.sin(), -length * angle.cos(), 0.0)));
+        join(&mut w, pivot, bob, Joint::Distance { length });
+
+        // time three swings by the bob's crossings of the vertical
+        let mut crossings = Vec::new();
+        let mut last = w.body(bob).unwrap().position.x;
+        while crossings.len() < 7 {
+            w.step();
+            let body = w.body(bob).unwrap();
+            assert!((body.position.length() - length).abs() < 1e-3);
+            if last.signum() != body.position.x.signum() {
+                crossings.push(w.time().in_seconds());
+            }
+            last = body.position.x;
+        }
+        let period = (crossings[6] - crossings[0]) / 3.0;
+        let expected = 2.0 * PI * (length / ACCELERATION_GRAVITY_EARTH.in_meters_per_second_squared()).sqrt();
+        assert!((period - expected).abs() < 0.01 * expected, "{} vs {}", period, expected);
+    }
+
+    #[test]
+    fn spring_oscillates_and_damps() {
+        let (mass, stiffness) = (2.0, 50.0);
+        let spring = |damping: f32| {
+            let mut w = world(false);
+            let anchor = w.add_body(Body::fixed(Vertex3D::ZERO));
+            let bob = w.add_body(Body::new(kg(mass), v(1.1, 0.0, 0.0)));
+            join(&mut w, anchor, bob, Joint::Spring { rest_length: 1.0, stiffness, damping });
+            (w, bob)
+        };
+
+        let (mut w, bob) = spring(0.0);
+        let period = 2.0 * PI * (mass / stiffness).sqrt();
+        w.run_for(Time::seconds(period / 2.0));
+        assert!((w.body(bob).unwrap().position.x - 0.9).abs() < 0.005);
+        w.run_for(Time::seconds(period / 2.0));
+        assert!((w.body(bob).unwrap().position.x - 1.1).abs() < 0.005);
+
+        // critically damped: straight back to rest without overshooting
+        let (mut w, bob) = spring(2.0 * (stiffness * mass).sqrt());
+        for _ in 0 .. 480 {
+            w.step();
+            assert!(w.body(bob).unwrap().position.x >= 1.0 - 1e-4);
+        }
+        assert!((w.body(bob).unwrap().position.x - 1.0).abs() < 1e-3);
+    }
+
+    // Links joined end to end from a pin at the origin, laid out along
+    // `direction`. Returns the world and the id of the last link.
+    fn chain(links: usize, link_length: f32, end_mass: f32, direction: Vertex3D, joint: fn(Vertex3D, f32) -> Joint) -> (World, BodyId) {
+        let mut w = World::new(WorldConfig::default());
+        let mut previous = w.add_body(Body::fixed(Vertex3D::ZERO));
+        for i in 0 .. links {
+            let mass = if i + 1 == links { end_mass } else { 1.0 };
+            let center = direction * ((i as f32 + 0.5) * link_length);
+            let link = w.add_body(Body::new(kg(mass), center).with_inertia(mass * link_length * link_length / 12.0));
+            join(&mut w, previous, link, joint(direction * (i as f32 * link_length), link_length));
+            previous = link;
+        }
+        (w, previous)
+    }
+
+    fn ball_socket(pivot: Vertex3D, _: f32) -> Joint {
+        Joint::BallSocket { pivot }
+    }
+
+    #[test]
+    fn stiff_chain_stays_stable_when_dropped() {
+        // twenty links falling from horizontal whip the tip through at over
+        // a link length per step
+        let (mut w, end) = chain(20, 0.25, 1.0, v(1.0, 0.0, 0.0), ball_socket);
+        let start = energy(&w);
+        let (mut worst, mut total) = (0.0f32, 0.0f32);
+        for _ in 0 .. 600 {
+            w.step();
+            let error = worst_error(&w);
+            worst = worst.max(error);
+            total += error;
+            assert!(energy(&w) <= start + 0.5, "chain gained energy");
+        }
+        assert!(worst < 0.1 * 0.25, "joints opened by {}", worst);
+        assert!(total / 600.0 < 0.03 * 0.25, "joints open by {} on average", total / 600.0);
+        let tip = w.body(end).unwrap().position;
+        assert!(tip.x.is_finite() && tip.y.is_finite() && tip.z.is_finite());
+        assert!(tip.length() < 20.0 * 0.25 + 0.1);
+    }
+
+    #[test]
+    fn hanging_chain_holds_its_length() {
+        let (mut w, end) = chain(20, 0.25, 1.0, v(0.0, -1.0, 0.0), ball_socket);
+        w.body_mut(end).unwrap().velocity = v(3.0, 0.0, 0.0);
+        for _ in 0 .. 600 {
+            w.step();
+            assert!(worst_error(&w) < 0.03 * 0.25, "joints opened by {}", worst_error(&w));
+        }
+    }
+
+    #[test]
+    fn chain_with_a_heavy_end_settles() {
+        // fifty times the mass of each link is hard going for an iterative
+        // solver; it stretches while settling but comes back and stays put
+        let (mut w, end) = chain(12, 0.3, 50.0, v(0.0, -1.0, 0.0), ball_socket);
+        let mut worst = 0.0f32;
+        for _ in 0 .. 120 {
+            w.step();
+            worst = worst.max(worst_error(&w));
+        }
+        assert!(worst < 0.1 * 0.3, "joints opened by {}", worst);
+        for _ in 0 .. 240 {
+            w.step();
+        }
+        for _ in 0 .. 240 {
+            w.step();
+            assert!(worst_error(&w) < 0.01 * 0.3, "joints opened by {}", worst_error(&w));
+        }
+        let tip = w.body(end).unwrap();
+        assert!(tip.velocity.length() < 0.05);
+        assert!((tip.position.y + 11.5 * 0.3).abs() < 0.02 * 0.3 * 12.0, "{:?}", tip.position);
+    }
+
+    #[test]
+    fn rope_of_distance_joints() {
+        let (mut w, end) = chain(30, 0.1, 1.0, v(1.0, 0.0, 0.0), |_, length| Joint::Distance { length });
+        w.run_for(Time::seconds(5.0));
+        let ends: Vec<Vertex3D> = w.bodies().map(|(_, b)| b.position).collect();
+        for pair in ends.windows(2).skip(1) {
+            let stretch = (pair[1] - pair[0]).length() - 0.1;
+            assert!(stretch.abs() < 0.005, "link off by {}", stretch);
+        }
+        assert!(w.body(end).unwrap().position.y < -1.0);
+    }
+
+    #[test]
+    fn hinge_turns_only_about_its_axis() {
+        let mut w = world(false);
+        let frame = w.add_body(Body::fixed(Vertex3D::ZERO));
+        let door = w.add_body(Body::new(kg(10.0), v(0.5, 0.0, 0.0)));
+        join(&mut w, frame, door, Joint::Hinge { pivot: Vertex3D::ZERO, axis: v(0.0, 1.0, 0.0) });
+        for _ in 0 .. 240 {
+            w.apply_force(door, v(0.0, 0.0, -5.0));
+            w.apply_torque(door, v(3.0, 0.0, 0.0));
+            w.step();
+        }
+        let body = w.body(door).unwrap();
+        assert!(body.angular_velocity.y > 0.5, "{:?}", body.angular_velocity);
+        assert!(body.angular_velocity.x.abs() < 1e-3 && body.angular_velocity.z.abs() < 1e-3, "{:?}", body.angular_velocity);
+        // still hanging from the hinge, half a metre out
+        assert!((body.position.length() - 0.5).abs() < 1e-3);
+        assert!(body.position.y.abs() < 1e-3);
+        let edge = body.position + body.orientation.rotate(v(-0.5, 0.0, 0.0));
+        assert!(edge.length() < 1e-3);
+    }
+
+    #[test]
+    fn fixed_joint_moves_as_one() {
+        let mut w = world(false);
+        let a = w.add_body(Body::new(kg(1.0), v(0.0, 0.0, 0.0)));
+        let b = w.add_body(Body::new(kg(3.0), v(1.0, 0.0, 0.0)));
+        join(&mut w, a, b, Joint::Fixed);
+        w.body_mut(a).unwrap().apply_impulse_at(v(0.0, 0.0, 2.0), v(0.0, 0.5, 0.0));
+        w.run_for(Time::seconds(2.0));
+        let (a, b) = (w.body(a).unwrap(), w.body(b).unwrap());
+        let offset = a.orientation.conjugate().rotate(b.position - a.position);
+        assert!((offset - v(1.0, 0.0, 0.0)).length() < 1e-3, "{:?}", offset);
+        let twist = (a.orientation.conjugate() * b.orientation).to_rotation_vector();
+        assert!(twist.length() < 1e-3);
+        assert!(a.angular_velocity.length() > 0.1);
+        assert!((a.angular_velocity - b.angular_velocity).length() < 1e-3);
+        // the pair's momentum is the impulse
+        let momentum = a.velocity + b.velocity * 3.0;
+        assert!((momentum - v(0.0, 0.0, 2.0)).length() < 1e-3, "{:?}", momentum);
+    }
+}
@@ -0,0 +1,498 @@
+use crate::geometry::Vertex3D;
+use crate::physics::ballistics;
+use crate::physics::ballistics::Environment;
+use crate::physics::ballistics::Projectile;
+use crate::units::Acceleration;
+use crate::units::Length;
+use crate::units::Time;
+use crate::units::Velocity;
+use std::f32::consts::FRAC_PI_2;
+
+// Angles are in radians. Elevation is above the horizontal; azimuth is
+// measured in the horizontal plane from +x towards +z. Gravity is assumed
+// to point along -y.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Launch {
+    pub elevation: f32,
+    pub azimuth: f32,
+    pub velocity: Vertex3D,
+    pub time_of_flight: Time,
+    // where the shot meets the target, which differs from the target's
+    // current position when leading a moving one
+    pub intercept: Vertex3D,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FiringSolution {
+    // low and high are the same launch when the target is at maximum range
+    Reachable { low: Launch, high: Launch },
+    Unreachable,
+}
+
+impl FiringSolution {
+    pub fn low(&self) -> Option<Launch> {
+        match self {
+            FiringSolution::Reachable { low, .. } => Some(*low),
+            FiringSolution::Unreachable => None,
+        }
+    }
+
+    pub fn high(&self) -> Option<Launch> {
+        match self {
+            FiringSolution::Reachable { high, .. } => Some(*high),
+            FiringSolution::Unreachable => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SolverConfig {
+    pub timestep: Time,
+    pub max_time: Time,
+    // spacing of the elevations tried before bisecting, radians
+    pub scan_step: f32,
+    pub bisection_iterations: u32,
+    // for moving targets and crosswind correction
+    pub max_iterations: u32,
+    pub tolerance: Length,
+}
+
+impl Default for SolverConfig {
+    fn default() -> SolverConfig {
+        SolverConfig {
+            timestep: Time::seconds(0.005),
+            max_time: Time::seconds(120.0),
+            scan_step: 1f32.to_radians(),
+            bisection_iterations: 32,
+            max_iterations: 20,
+            tolerance: Length::meters(0.01),
+        }
+    }
+}
+
+pub fn launch_velocity(speed: Velocity, elevation: f32, azimuth: f32) -> Vertex3D {
+    let speed = speed.in_meters_per_second();
+    Vertex3D {
+        x: speed * elevation.cos() * azimuth.cos(),
+        y: speed * elevation.sin(),
+        z: speed * elevation.cos() * azimuth.sin(),
+    }
+}
+
+fn launch(speed: Velocity, elevation: f32, azimuth: f32, time_of_flight: f32, intercept: Vertex3D) -> Launch {
+    Launch {
+        elevation,
+        azimuth,
+        velocity: launch_velocity(speed, elevation, azimuth),
+        time_of_flight: Time::seconds(time_of_flight),
+        intercept,
+    }
+}
+
+fn horizontal(launcher: Vertex3D, target: Vertex3D) -> (f32, f32, f32) {
+    let (dx, dz) = (target.x - launcher.x, target.z - launcher.z);
+    ((dx * dx + dz * dz).sqrt(), target.y - launcher.y, dz.atan2(dx))
+}
+
+// Drag-free solution: tan(theta) = (v^2 +- sqrt(v^4 - g(g d^2 + 2 h v^2))) / g d
+pub fn solve_vacuum(launcher: Vertex3D, speed: Velocity, target: Vertex3D, gravity: Acceleration) -> FiringSolution {
+    let (d, h, azimuth) = horizontal(launcher, target);
+    let gravity = gravity.in_meters_per_second_squared();
+    let v = speed.in_meters_per_second();
+    let v2 = v * v;
+
+    if d < 1e-6 {
+        // straight up or down
+        if h > 0.0 {
+            let discriminant = v2 - 2.0 * gravity * h;
+            if discriminant < 0.0 {
+                return FiringSolution::Unreachable;
+            }
+            let up = launch(speed, FRAC_PI_2, 0.0, (v - discriminant.sqrt()) / gravity, target);
+            return FiringSolution::Reachable { low: up, high: up };
+        }
+        let down = (v2 - 2.0 * gravity * h).sqrt();
+        return FiringSolution::Reachable {
+            low: launch(speed, -FRAC_PI_2, 0.0, (down - v) / gravity, target),
+            high: launch(speed, FRAC_PI_2, 0.0, (v + down) / gravity, target),
+        };
+    }
+
+    let discriminant = v2 * v2 - gravity * (gravity * d * d + 2.0 * h * v2);
+    // allow for rounding right at maximum range
+    if discriminant < -1e-5 * v2 * v2 {
+        return FiringSolution::Unreachable;
+    }
+    let root = discriminant.max(0.0).sqrt();
+    let solution = |tan: f32| {
+        let elevation = tan.atan();
+        launch(speed, elevation, azimuth, d / (v * elevation.cos()), target)
+    };
+    FiringSolution::Reachable {
+        low: solution((v2 - root) / (gravity * d)),
+        high: solution((v2 + root) / (gravity * d)),
+    }
+}
+
+struct Crossing {
+    height: f32,
+    lateral: f32,
+    time: f32,
+}
+
+// Flies a shot and reports where it passes `distance` along the bearing to
+// the target, or None if it drops below `floor` first or never gets that far.
+// Lateral is the drift off that bearing.
+#[allow(clippy::too_many_arguments)]
+fn crossing(projectile: &Projectile, environment: &Environment, launcher: Vertex3D, speed: Velocity, elevation: f32, azimuth: f32, bearing: f32, distance: f32, floor: f32, config: &SolverConfig) -> Option<Crossing> {
+    let forward = Vertex3D { x: bearing.cos(), y: 0.0, z: bearing.sin() };
+    let side = Vertex3D { x: -bearing.sin(), y: 0.0, z: bearing.cos() };
+    let dt = config.timestep.in_seconds();
+    let mut position = launcher;
+    let mut velocity = launch_velocity(speed, elevation, azimuth);
+    let mut along = 0.0;
+    let steps = (config.max_time / config.timestep).round() as u32;
+    for step in 0 .. steps {
+        let (next_position, next_velocity) = ballistics::rk4(projectile, environment, position, velocity, dt);
+        let next_along = (next_position - launcher).dot(forward);
+        if next_along >= distance {
+            let t = (distance - along) / (next_along - along);
+            let point = position + (next_position - position) * t;
+            return Some(Crossing {
+                height: point.y - launcher.y,
+                lateral: (point - launcher).dot(side),
+                time: (step as f32 + t) * dt,
+            });
+        }
+        if next_velocity.y < 0.0 && next_position.y - launcher.y < floor {
+            return None;
+        }
+        position = next_position;
+        velocity = next_velocity;
+        along = next_along;
+    }
+    None
+}
+
+// Flies a shot straight up or down and reports when it first passes height
+// `h` going up (`rising`) or down, or None if it never does. Drift from the
+// wind is ignored.
+#[allow(clippy::too_many_arguments)]
+fn vertical_crossing(projectile: &Projectile, environment: &Environment, launcher: Vertex3D, speed: Velocity, elevation: f32, h: f32, rising: bool, config: &SolverConfig) -> Option<f32> {
+    let dt = config.timestep.in_seconds();
+    let mut position = launcher;
+    let mut velocity = launch_velocity(speed, elevation, 0.0);
+    let steps = (config.max_time / config.timestep).round() as u32;
+    for step in 0 .. steps {
+        let (next_position, next_velocity) = ballistics::rk4(projectile, environment, position, velocity, dt);
+        let (y, next_y) = (position.y - launcher.y, next_position.y - launcher.y);
+        let crossed = if rising { y < h && next_y >= h } else { y >= h && next_y < h };
+        if crossed {
+            return Some((step as f32 + (h - y) / (next_y - y)) * dt);
+        }
+        // topped out below the target
+        if rising && next_velocity.y <= 0.0 {
+            return None;
+        }
+        position = next_position;
+        velocity = next_velocity;
+    }
+    None
+}
+
+fn bisect<F: Fn(f32) -> f32>(f: &F, mut low: f32, mut high: f32, iterations: u32) -> f32 {
+    let low_sign = f(low) > 0.0;
+    for _ in 0 .. iterations {
+        let mid = (low + high) / 2.0;
+        if (f(mid) > 0.0) == low_sign {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    (low + high) / 2.0
+}
+
+// Elevations either side of the lowest and highest shots that pass through
+// the target's height, found by scanning for the first and last sign
+// changes of the miss.
+fn scan_elevations<F: Fn(f32) -> f32>(miss: &F, config: &SolverConfig) -> Option<[(f32, f32); 2]> {
+    let limit = FRAC_PI_2 - config.scan_step / 2.0;
+    let count = (2.0 * limit / config.scan_step).floor() as i32;
+    let elevations: Vec<f32> = (0 ..= count).map(|i| -limit + i as f32 * config.scan_step).collect();
+    let misses: Vec<f32> = elevations.iter().map(|e| miss(*e)).collect();
+
+    let brackets: Vec<usize> = (0 .. elevations.len() - 1)
+        .filter(|&i| (misses[i] > 0.0) != (misses[i + 1] > 0.0))
+        .collect();
+    let first = *brackets.first()?;
+    let last = *brackets.last()?;
+    Some([(elevations[first], elevations[first + 1]), (elevations[last], elevations[last + 1])])
+}
+
+// Solution with the drag model, by shooting: the projectile supplies mass
+// and drag, its own position and velocity are ignored. Crosswind drift is
+// taken out by iterating on the azimuth for each of the two elevations.
+pub fn solve_drag(launcher: Vertex3D, speed: Velocity, target: Vertex3D, projectile: &Projectile, environment: &Environment, config: &SolverConfig) -> FiringSolution {
+    let (d, h, bearing) = horizontal(launcher, target);
+    if d < 1e-6 {
+        // straight up or down: there's no bearing to scan elevations along
+        let vertical = |elevation: f32, rising: bool| {
+            vertical_crossing(projectile, environment, launcher, speed, elevation, h, rising, config)
+                .map(|time| launch(speed, elevation, 0.0, time, target))
+        };
+        let (low, high) = if h > 0.0 {
+            (vertical(FRAC_PI_2, true), vertical(FRAC_PI_2, true))
+        } else {
+            (vertical(-FRAC_PI_2, false), vertical(FRAC_PI_2, false))
+        };
+        return match (low, high) {
+            (Some(low), Some(high)) => FiringSolution::Reachable { low, high },
+            _ => FiringSolution::Unreachable,
+        };
+    }
+
+    let miss = |elevation: f32, azimuth: f32| match crossing(projectile, environment, launcher, speed, elevation, azimuth, bearing, d, h, config) {
+        Some(c) => c.height - h,
+        None => f32::NEG_INFINITY,
+    };
+    let changes_sign = |at: &dyn Fn(f32) -> f32, (low, high): (f32, f32)| (at(low) > 0.0) != (at(high) > 0.0);
+    let limit = FRAC_PI_2 - config.scan_step / 2.0;
+    let brackets = match scan_elevations(&|elevation| miss(elevation, bearing), config) {
+        Some(brackets) => brackets,
+        None => return FiringSolution::Unreachable,
+    };
+
+    let mut solutions = Vec::new();
+    for (branch, mut bracket) in brackets.into_iter().enumerate() {
+        let mut azimuth = bearing;
+        let mut solved = None;
+        for _ in 0 .. config.max_iterations {
+            let at = |elevation: f32| miss(elevation, azimuth);
+            // correcting the azimuth only nudges the root, so it's usually
+            // still within a scan step of the last one; rescan when it isn't
+            if !changes_sign(&at, bracket) {
+                bracket = match scan_elevations(&at, config) {
+                    Some(brackets) => brackets[branch],
+                    None => return FiringSolution::Unreachable,
+                };
+            }
+            let elevation = bisect(&at, bracket.0, bracket.1, config.bisection_iterations);
+            let c = match crossing(projectile, environment, launcher, speed, elevation, azimuth, bearing, d, h, config) {
+                Some(c) => c,
+                None => return FiringSolution::Unreachable,
+            };
+            solved = Some(launch(speed, elevation, azimuth, c.time, target));
+            if c.lateral.abs() <= config.tolerance.in_meters() {
+                break;
+            }
+            azimuth -= (c.lateral / d).atan();
+            bracket = ((elevation - config.scan_step).max(-limit), (elevation + config.scan_step).min(limit));
+        }
+        match solved {
+            Some(s) => solutions.push(s),
+            None => return FiringSolution::Unreachable,
+        }
+    }
+    FiringSolution::Reachable { low: solutions[0], high: solutions[1] }
+}
+
+// Leads a target moving at constant velocity: aims at where the target will
+// be after the time of flight, iterating until that time settles. `solve`
+// is solve_vacuum or solve_drag with everything but the target fixed.
+pub fn solve_moving<F: Fn(Vertex3D) -> FiringSolution>(target: Vertex3D, target_velocity: Vertex3D, config: &SolverConfig, solve: F) -> FiringSolution {
+    let lead = |pick: fn(&FiringSolution) -> Option<Launch>| -> Option<Launch> {
+        let mut shot = pick(&solve(target))?;
+        for _ in 0 .. config.max_iterations {
+            let intercept = target + target_velocity * shot.time_of_flight.in_seconds();
+            let next = pick(&solve(intercept))?;
+            let moved = (next.intercept - shot.intercept).length();
+            shot = next;
+            if moved <= config.tolerance.in_meters() {
+                return Some(shot);
+            }
+        }
+        None
+    };
+    match (lead(FiringSolution::low), lead(FiringSolution::high)) {
+        (Some(low), Some(high)) => FiringSolution::Reachable { low, high },
+        _ => FiringSolution::Unreachable,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::ACCELERATION_GRAVITY_EARTH;
+    use crate::physics::ballistics::Impact;
+    use crate::physics::ballistics::SimulationConfig;
+    use crate::physics::ballistics::simulate;
+    use crate::units::Area;
+    use crate::units::Mass;
+
+    const G: Acceleration = ACCELERATION_GRAVITY_EARTH;
+
+    fn v(x: f32, y: f32, z: f32) -> Vertex3D {
+        Vertex3D { x, y, z }
+    }
+
+    fn mps(speed: f32) -> Velocity {
+        Velocity::meters_per_second(speed)
+    }
+
+    fn shell() -> Projectile {
+        Projectile {
+            position: v(0.0, 0.0, 0.0),
+            velocity: v(0.0, 0.0, 0.0),
+            mass: Mass::kilograms(5.0),
+            drag_coefficient: 0.3,
+            cross_section: Area::square_meters(0.008),
+        }
+    }
+
+    // where a launch actually lands when flown through the simulator
+    fn closest_approach(launcher: Vertex3D, launch: &Launch, target: Vertex3D, environment: &Environment) -> f32 {
+        let projectile = Projectile { position: launcher, velocity: launch.velocity, ..shell() };
+        let environment = Environment { ground: None, ..*environment };
+        let config = SimulationConfig {
+            timestep: Time::seconds(0.001),
+            sample_interval: Time::seconds(0.001),
+            max_time: launch.time_of_flight * 1.5,
+        };
+        let t = simulate(&projectile, &environment, &[], &config);
+        t.points.iter().map(|p| (*p - target).length()).fold(f32::MAX, f32::min)
+    }
+
+    #[test]
+    fn vacuum_angles() {
+        // 45 degrees is the only way to reach maximum range
+        let speed = mps(100.0);
+        let max_range = 100.0 * 100.0 / G.in_meters_per_second_squared();
+        match solve_vacuum(v(0.0, 0.0, 0.0), speed, v(max_range, 0.0, 0.0), G) {
+            FiringSolution::Reachable { low, high } => {
+                assert!((low.elevation.to_degrees() - 45.0).abs() < 0.1);
+                assert!((high.elevation.to_degrees() - 45.0).abs() < 0.1);
+            },
+            FiringSolution::Unreachable => panic!("Should reach maximum range"),
+        }
+        assert_eq!(solve_vacuum(v(0.0, 0.0, 0.0), speed, v(max_range + 10.0, 0.0, 0.0), G), FiringSolution::Unreachable);
+
+        let launcher = v(5.0, 2.0, -3.0);
+        let target = v(505.0, 40.0, 297.0);
+        let solution = solve_vacuum(launcher, mps(120.0), target, G);
+        let (low, high) = (solution.low().unwrap(), solution.high().unwrap());
+        assert!(low.elevation < high.elevation);
+        assert!((low.azimuth - 300f32.atan2(500.0)).abs() < 1e-5);
+        for launch in [low, high] {
+            let miss = closest_approach(launcher, &launch, target, &Environment { air_density: 0.0, ..Environment::default() });
+            assert!(miss < 0.5, "missed by {}", miss);
+        }
+        // complementary angles on level ground
+        let level = solve_vacuum(v(0.0, 0.0, 0.0), mps(100.0), v(700.0, 0.0, 0.0), G);
+        assert!((level.low().unwrap().elevation + level.high().unwrap().elevation - FRAC_PI_2).abs() < 1e-4);
+    }
+
+    #[test]
+    fn straight_up() {
+        let solution = solve_vacuum(v(0.0, 0.0, 0.0), mps(20.0), v(0.0, 10.0, 0.0), G);
+        assert_eq!(solution.low().unwrap().elevation, FRAC_PI_2);
+        assert_eq!(solve_vacuum(v(0.0, 0.0, 0.0), mps(10.0), v(0.0, 10.0, 0.0), G), FiringSolution::Unreachable);
+    }
+
+    #[test]
+    fn drag_straight_up_and_down() {
+        let environment = Environment::default();
+        let config = SolverConfig::default();
+        let speed = mps(60.0);
+        let up = v(0.0, 100.0, 0.0);
+        let launch = solve_drag(v(0.0, 0.0, 0.0), speed, up, &shell(), &environment, &config).low().unwrap();
+        assert_eq!(launch.elevation, FRAC_PI_2);
+        // slowed by the air on the way
+        assert!(launch.time_of_flight > solve_vacuum(v(0.0, 0.0, 0.0), speed, up, G).low().unwrap().time_of_flight);
+
+        // high enough in a vacuum, not through air
+        let out_of_reach = v(0.0, 180.0, 0.0);
+        assert!(matches!(solve_vacuum(v(0.0, 0.0, 0.0), speed, out_of_reach, G), FiringSolution::Reachable { .. }));
+        assert_eq!(solve_drag(v(0.0, 0.0, 0.0), speed, out_of_reach, &shell(), &environment, &config), FiringSolution::Unreachable);
+
+        // below: straight down, or up and back again
+        let down = v(0.0, -50.0, 0.0);
+        let solution = solve_drag(v(0.0, 0.0, 0.0), speed, down, &shell(), &environment, &config);
+        let (low, high) = (solution.low().unwrap(), solution.high().unwrap());
+        assert_eq!((low.elevation, high.elevation), (-FRAC_PI_2, FRAC_PI_2));
+        assert!(low.time_of_flight < high.time_of_flight);
+        for (launch, target) in [(launch, up), (low, down), (high, down)] {
+            let miss = closest_approach(v(0.0, 0.0, 0.0), &launch, target, &environment);
+            assert!(miss < 0.5, "missed by {}", miss);
+        }
+    }
+
+    #[test]
+    fn drag_solver_matches_vacuum_without_air() {
+        let vacuum = Environment { air_density: 0.0, ..Environment::default() };
+        let target = v(400.0, 25.0, 100.0);
+        let exact = solve_vacuum(v(0.0, 0.0, 0.0), mps(90.0), target, G);
+        let numeric = solve_drag(v(0.0, 0.0, 0.0), mps(90.0), target, &shell(), &vacuum, &SolverConfig::default());
+        let degrees = |l: Option<Launch>| l.unwrap().elevation.to_degrees();
+        assert!((degrees(exact.low()) - degrees(numeric.low())).abs() < 0.05);
+        assert!((degrees(exact.high()) - degrees(numeric.high())).abs() < 0.05);
+        assert!((exact.low().unwrap().time_of_flight - numeric.low().unwrap().time_of_flight).abs() < Time::seconds(0.01));
+    }
+
+    #[test]
+    fn drag_needs_a_steeper_low_shot_and_still_hits() {
+        let environment = Environment { wind: v(0.0, 0.0, 6.0), ..Environment::default() };
+        let target = v(600.0, 0.0, 0.0);
+        let speed = mps(110.0);
+        let with_drag = solve_drag(v(0.0, 0.0, 0.0), speed, target, &shell(), &environment, &SolverConfig::default());
+        let without = solve_vacuum(v(0.0, 0.0, 0.0), speed, target, G);
+        let low = with_drag.low().unwrap();
+        assert!(low.elevation > without.low().unwrap().elevation);
+        // aims upwind
+        assert!(low.azimuth < 0.0);
+        for launch in [low, with_drag.high().unwrap()] {
+            let miss = closest_approach(v(0.0, 0.0, 0.0), &launch, target, &environment);
+            assert!(miss < 0.5, "missed by {}", miss);
+        }
+
+        // reachable in a vacuum, not through air
+        let far = v(1200.0, 0.0, 0.0);
+        assert!(matches!(solve_vacuum(v(0.0, 0.0, 0.0), speed, far, G), FiringSolution::Reachable { .. }));
+        assert_eq!(solve_drag(v(0.0, 0.0, 0.0), speed, far, &shell(), &environment, &SolverConfig::default()), FiringSolution::Unreachable);
+    }
+
+    #[test]
+    fn leads_moving_targets() {
+        let launcher = v(0.0, 0.0, 0.0);
+        let target = v(300.0, 0.0, 0.0);
+        let target_velocity = v(0.0, 0.0, 15.0);
+        let config = SolverConfig::default();
+        let solution = solve_moving(target, target_velocity, &config, |aim| solve_vacuum(launcher, mps(80.0), aim, G));
+        for launch in [solution.low().unwrap(), solution.high().unwrap()] {
+            let will_be = target + target_velocity * launch.time_of_flight.in_seconds();
+            assert!((will_be - launch.intercept).length() < 0.05);
+            let miss = closest_approach(launcher, &launch, will_be, &Environment { air_density: 0.0, ..Environment::default() });
+            assert!(miss < 0.5, "missed by {}", miss);
+        }
+
+        // too fast to catch
+        let fleeing = solve_moving(target, v(100.0, 0.0, 0.0), &config, |aim| solve_vacuum(launcher, mps(80.0), aim, G));
+        assert_eq!(fleeing, FiringSolution::Unreachable);
+    }
+
+    #[test]
+    fn drag_solution_lands_where_simulate_says() {
+        let environment = Environment::default();
+        let target = v(350.0, 0.0, 0.0);
+        let launch = solve_drag(v(0.0, 0.0, 0.0), mps(100.0), target, &shell(), &environment, &SolverConfig::default())
+            .low().unwrap();
+        let projectile = Projectile { velocity: launch.velocity, ..shell() };
+        let flown = simulate(&projectile, &environment, &[], &SimulationConfig::default());
+        match flown.impact {
+            Some(Impact::Ground { point }) => assert!((point.x - 350.0).abs() < 0.5, "{:?}", point),
+            other => panic!("{:?}", other),
+        }
+        assert!((flown.time_of_flight - launch.time_of_flight).abs() < Time::seconds(0.05));
+    }
+}
@@ -0,0 +1,419 @@
+use crate::geometry::Shape3D;
+use crate::geometry::Vertex3D;
+use crate::units::Energy;
+use crate::units::Length;
+use crate::units::Mass;
+
+// m^3 kg^-1 s^-2
+pub const GRAVITATIONAL_CONSTANT: f32 = 6.674e-11;
+
+// A fixed attractor, such as a planet the level is built around.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PointMass {
+    pub position: Vertex3D,
+    pub mass: Mass,
+}
+
+// Replaces the world's uniform gravity for anything inside the region.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GravityZone {
+    pub region: Shape3D,
+    pub gravity: Vertex3D,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GravityField {
+    // whether bodies attract each other
+    pub mutual: bool,
+    // usually scaled up from GRAVITATIONAL_CONSTANT, which is far too weak
+    // to notice between game-sized masses
+    pub constant: f32,
+    // added in quadrature to every distance so close passes stay finite
+    pub softening: Length,
+    // Barnes-Hut opening angle: a cell is treated as one mass when its size
+    // over its distance is below this. 0 computes every pair exactly.
+    pub theta: f32,
+    // bodies needed before the tree is worth building
+    pub barnes_hut_threshold: usize,
+    pub point_masses: Vec<PointMass>,
+    // the first zone containing a body wins
+    pub zones: Vec<GravityZone>,
+}
+
+impl Default for GravityField {
+    fn default() -> GravityField {
+        GravityField {
+            mutual: false,
+            constant: GRAVITATIONAL_CONSTANT,
+            softening: Length::ZERO,
+            theta: 0.5,
+            barnes_hut_threshold: 64,
+            point_masses: Vec::new(),
+            zones: Vec::new(),
+        }
+    }
+}
+
+impl GravityField {
+    // Acceleration towards a mass at `to` from `from`.
+    fn pull(&self, from: Vertex3D, to: Vertex3D, mass: f32) -> Vertex3D {
+        let r = to - from;
+        let d2 = r.dot(r) + self.softening.in_meters().powi(2);
+        if d2 == 0.0 {
+            return Vertex3D::ZERO;
+        }
+        r * (self.constant * mass / (d2 * d2.sqrt()))
+    }
+
+    // The uniform part: the zone's gravity, or `background` outside every zone.
+    pub fn uniform_at(&self, position: Vertex3D, background: Vertex3D) -> Vertex3D {
+        self.zones.iter()
+            .find(|z| position.is_on_or_inside(&z.region))
+            .map_or(background, |z| z.gravity)
+    }
+
+    pub fn point_masses_at(&self, position: Vertex3D) -> Vertex3D {
+        self.point_masses.iter()
+            .fold(Vertex3D::ZERO, |a, p| a + self.pull(position, p.position, p.mass.in_kilograms()))
+    }
+
+    // Acceleration of each body due to all the others, exact below the
+    // Barnes-Hut threshold and approximated above it.
+    pub fn mutual_accelerations(&self, bodies: &[(Vertex3D, Mass)]) -> Vec<Vertex3D> {
+        if bodies.is_empty() {
+            return Vec::new();
+        }
+        if self.theta > 0.0 && bodies.len() >= self.barnes_hut_threshold {
+            let tree = Octree::build(bodies);
+            (0 .. bodies.len()).map(|i| tree.acceleration(self, bodies, i)).collect()
+        } else {
+            self.direct(bodies)
+        }
+    }
+
+    // Every pair once, applying equal and opposite pulls so momentum is
+    // conserved to rounding.
+    fn direct(&self, bodies: &[(Vertex3D, Mass)]) -> Vec<Vertex3D> {
+        let mut accelerations = vec![Vertex3D::ZERO; bodies.len()];
+        for (i, (a, mass_a)) in bodies.iter().enumerate() {
+            for (j, (b, mass_b)) in bodies.iter().enumerate().skip(i + 1) {
+                let unit = self.pull(*a, *b, 1.0);
+                accelerations[i] += unit * mass_b.in_kilograms();
+                accelerations[j] -= unit * mass_a.in_kilograms();
+            }
+        }
+        accelerations
+    }
+
+    // Gravitational potential energy of the bodies among themselves and in
+    // the point masses' fields. Uniform fields are left out; they have no
+    // single zero to measure from once zones are involved.
+    pub fn potential_energy(&self, bodies: &[(Vertex3D, Mass)]) -> Energy {
+        let softening = self.softening.in_meters().powi(2);
+        let pair = |a: Vertex3D, b: Vertex3D, m: f32| {
+            let r = a - b;
+            let d = (r.dot(r) + softening).sqrt();
+            if d == 0.0 { 0.0 } else { -self.constant * m / d }
+        };
+        let mut energy = 0.0;
+        for (i, (a, mass_a)) in bodies.iter().enumerate() {
+            let mass_a = mass_a.in_kilograms();
+            if self.mutual {
+                for (b, mass_b) in bodies.iter().skip(i + 1) {
+                    energy += pair(*a, *b, mass_a * mass_b.in_kilograms());
+                }
+            }
+            for p in self.point_masses.iter() {
+                energy += pair(*a, p.position, mass_a * p.mass.in_kilograms());
+            }
+        }
+        Energy::joules(energy)
+    }
+}
+
+// below this a cell stops splitting and keeps all its bodies, which stops
+// coincident bodies recursing forever
+const MIN_HALF_SIZE: f32 = 1e-4;
+
+struct Cell {
+    center: Vertex3D,
+    half: f32,
+    mass: f32,
+    // sum of mass * position, for the centre of mass
+    moment: Vertex3D,
+    bodies: Vec<usize>,
+    // index of the first of eight consecutive children
+    children: Option<usize>,
+}
+
+impl Cell {
+    fn new(center: Vertex3D, half: f32) -> Cell {
+        Cell { center, half, mass: 0.0, moment: Vertex3D::ZERO, bodies: Vec::new(), children: None }
+    }
+
+    fn octant(&self, p: Vertex3D) -> usize {
+        (p.x >= self.center.x) as usize | ((p.y >= self.center.y) as usize) << 1 | ((p.z >= self.center.z) as usize) << 2
+    }
+
+    fn contains(&self, p: Vertex3D) -> bool {
+        (p.x - self.center.x).abs() <= self.half
+            && (p.y - self.center.y).abs() <= self.half
+            && (p.z - self.center.z).abs() <= self.half
+    }
+}
+
+struct Octree {
+    cells: Vec<Cell>,
+}
+
+impl Octree {
+    fn build(bodies: &[(Vertex3D, Mass)]) -> Octree {
+        let (mut low, mut high) = (bodies[0].0, bodies[0].0);
+        for (p, _) in bodies.iter() {
+            low = Vertex3D { x: low.x.min(p.x), y: low.y.min(p.y), z: low.z.min(p.z) };
+            high = Vertex3D { x: high.x.max(p.x), y: high.y.max(p.y), z: high.z.max(p.z) };
+        }
+        let half = ((high.x - low.x).max(high.y - low.y).max(high.z - low.z) / 2.0).max(MIN_HALF_SIZE) * 1.001;
+        let mut tree = Octree { cells: vec![Cell::new((low + high) * 0.5, half)] };
+        for (i, (p, m)) in bodies.iter().enumerate() {
+            tree.insert(bodies, i, *p, m.in_kilograms());
+        }
+        tree
+    }
+
+    fn insert(&mut self, bodies: &[(Vertex3D, Mass)], index: usize, position: Vertex3D, mass: f32) {
+        let mut cell = 0;
+        loop {
+            let c = &mut self.cells[cell];
+            c.mass += mass;
+            c.moment += position * mass;
+            if let Some(first) = c.children {
+                cell = first + c.octant(position);
+                continue;
+            }
+            if c.bodies.is_empty() || c.half <= MIN_HALF_SIZE {
+                c.bodies.push(index);
+                return;
+            }
+            // split, pushing the resident body down a level
+            let (center, quarter) = (c.center, c.half / 2.0);
+            let resident = c.bodies.pop().unwrap();
+            let first = self.cells.len();
+            self.cells[cell].children = Some(first);
+            for octant in 0 .. 8 {
+                let offset = |bit: usize| if octant & bit == 0 { -quarter } else { quarter };
+                let child = center + Vertex3D { x: offset(1), y: offset(2), z: offset(4) };
+                self.cells.push(Cell::new(child, quarter));
+            }
+            let (p, m) = bodies[resident];
+            let m = m.in_kilograms();
+            let octant = self.cells[cell].octant(p);
+            let child = &mut self.cells[first + octant];
+            child.mass += m;
+            child.moment += p * m;
+            child.bodies.push(resident);
+            cell = first + self.cells[cell].octant(position);
+        }
+    }
+
+    fn acceleration(&self, field: &GravityField, bodies: &[(Vertex3D, Mass)], index: usize) -> Vertex3D {
+        let position = bodies[index].0;
+        let mut total = Vertex3D::ZERO;
+        let mut stack = vec![0];
+        while let Some(cell) = stack.pop() {
+            let c = &self.cells[cell];
+            if c.mass == 0.0 {
+                continue;
+            }
+            match c.children {
+                None => {
+                    for &other in c.bodies.iter().filter(|&&other| other != index) {
+                        let (p, m) = bodies[other];
+                        total += field.pull(position, p, m.in_kilograms());
+                    }
+                },
+                Some(first) => {
+                    let com = c.moment / c.mass;
+                    let r = com - position;
+                    let distance = r.dot(r).sqrt();
+                    // a cell holding the body itself is always opened
+                    if !c.contains(position) && 2.0 * c.half < field.theta * distance {
+                        total += field.pull(position, com, c.mass);
+                    } else {
+                        stack.extend(first .. first + 8);
+                    }
+                },
+            }
+        }
+        total
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Quaternion;
+    use crate::geometry::Polygon3D;
+    use crate::physics::world::Body;
+    use crate::physics::world::Rng;
+    use crate::physics::world::World;
+    use crate::physics::world::WorldConfig;
+    use crate::units::Time;
+
+    fn v(x: f32, y: f32, z: f32) -> Vertex3D {
+        Vertex3D { x, y, z }
+    }
+
+    fn space(field: GravityField) -> World {
+        World::new(WorldConfig { timestep: Time::seconds(0.001), gravity: Vertex3D::ZERO, field, ..WorldConfig::default() })
+    }
+
+    fn state(world: &World) -> Vec<(Vertex3D, Mass)> {
+        world.bodies().map(|(_, b)| (b.position, b.mass)).collect()
+    }
+
+    fn energy(world: &World) -> f32 {
+        let kinetic: Energy = world.bodies().map(|(_, b)| b.kinetic_energy()).sum();
+        (kinetic + world.config().field.potential_energy(&state(world))).in_joules()
+    }
+
+    fn momentum(world: &World) -> Vertex3D {
+        world.bodies().fold(Vertex3D::ZERO, |p, (_, b)| p + b.velocity * b.mass.in_kilograms())
+    }
+
+    #[test]
+    fn inverse_square() {
+        let field = GravityField { constant: 1.0, ..GravityField::default() };
+        let pull = |d: f32| field.pull(Vertex3D::ZERO, v(d, 0.0, 0.0), 8.0).length();
+        assert!((pull(2.0) - 2.0).abs() < 1e-6);
+        assert!((pull(4.0) - 0.5).abs() < 1e-6);
+        let earth = GravityField {
+            point_masses: vec![PointMass { position: Vertex3D::ZERO, mass: Mass::kilograms(5.972e24) }],
+            ..GravityField::default()
+        };
+        let surface = earth.point_masses_at(v(0.0, 6.371e6, 0.0)).length();
+        assert!((surface - 9.82).abs() < 0.01, "{}", surface);
+    }
+
+    #[test]
+    fn zones_replace_uniform_gravity() {
+        let background = v(0.0, -9.8, 0.0);
+        let field = GravityField {
+            zones: vec![
+                GravityZone { region: Shape3D::Cuboid { center: v(0.0, 50.0, 0.0), width: 20.0, height: 20.0, length: 20.0, orientation: Quaternion::IDENTITY }, gravity: v(0.0, -1.6, 0.0) },
+                GravityZone { region: Shape3D::Sphere { center: v(0.0, 50.0, 0.0), radius: 100.0 }, gravity: Vertex3D::ZERO },
+            ],
+            ..GravityField::default()
+        };
+        assert_eq!(field.uniform_at(v(5.0, 45.0, 0.0), background), v(0.0, -1.6, 0.0));
+        assert_eq!(field.uniform_at(v(50.0, 45.0, 0.0), background), Vertex3D::ZERO);
+        assert_eq!(field.uniform_at(v(0.0, -100.0, 0.0), background), background);
+
+        let mut world = World::new(WorldConfig { timestep: Time::seconds(0.01), field, ..WorldConfig::default() });
+        let inside = world.add_body(Body::new(Mass::kilograms(1.0), v(0.0, 55.0, 0.0)));
+        let outside = world.add_body(Body::new(Mass::kilograms(1.0), v(0.0, -100.0, 0.0)));
+        world.run_for(Time::seconds(1.0));
+        assert!((world.body(inside).unwrap().velocity.y + 1.6).abs() < 1e-4);
+        assert!((world.body(outside).unwrap().velocity.y + 9.8).abs() < 0.01);
+    }
+
+    #[test]
+    fn polyhedron_zones() {
+        let background = v(0.0, -9.8, 0.0);
+        // a wedge sloping down to +x
+        let wedge = Shape3D::Polyhedron { faces: vec![
+            Polygon3D { vertices: vec![v(0.0, 0.0, 0.0), v(0.0, 0.0, 2.0), v(2.0, 0.0, 2.0), v(2.0, 0.0, 0.0)] },
+            Polygon3D { vertices: vec![v(0.0, 0.0, 0.0), v(0.0, 2.0, 0.0), v(0.0, 2.0, 2.0), v(0.0, 0.0, 2.0)] },
+            Polygon3D { vertices: vec![v(0.0, 2.0, 0.0), v(2.0, 0.0, 0.0), v(2.0, 0.0, 2.0), v(0.0, 2.0, 2.0)] },
+            Polygon3D { vertices: vec![v(0.0, 0.0, 0.0), v(2.0, 0.0, 0.0), v(0.0, 2.0, 0.0)] },
+            Polygon3D { vertices: vec![v(0.0, 0.0, 2.0), v(0.0, 2.0, 2.0), v(2.0, 0.0, 2.0)] },
+        ] };
+        let field = GravityField {
+            zones: vec![GravityZone { region: wedge, gravity: Vertex3D::ZERO }],
+            ..GravityField::default()
+        };
+        assert_eq!(field.uniform_at(v(0.5, 0.5, 1.0), background), Vertex3D::ZERO);
+        assert_eq!(field.uniform_at(v(1.5, 1.5, 1.0), background), background);
+        assert_eq!(field.uniform_at(v(0.5, -0.5, 1.0), background), background);
+    }
+
+    #[test]
+    fn circular_orbit_around_a_point_mass() {
+        // G M = 1000, r = 10 gives v = 10 and a period of 2 pi
+        let field = GravityField {
+            constant: 1.0,
+            point_masses: vec![PointMass { position: Vertex3D::ZERO, mass: Mass::kilograms(1000.0) }],
+            ..GravityField::default()
+        };
+        let mut world = space(field);
+        let id = world.add_body(Body::new(Mass::kilograms(1.0), v(10.0, 0.0, 0.0)).with_velocity(v(0.0, 0.0, 10.0)));
+        let start = energy(&world);
+        for _ in 0 .. 10 {
+            world.run_for(Time::seconds(std::f32::consts::PI / 5.0));
+            let radius = world.body(id).unwrap().position.length();
+            assert!((radius - 10.0).abs() < 0.05, "drifted to {}", radius);
+        }
+        assert!(((energy(&world) - start) / start).abs() < 1e-3);
+        assert!((world.body(id).unwrap().position - v(10.0, 0.0, 0.0)).length() < 0.5);
+    }
+
+    #[test]
+    fn binary_conserves_energy_and_momentum() {
+        let field = GravityField { mutual: true, constant: 1.0, ..GravityField::default() };
+        let mut world = space(field);
+        // unequal pair on a bound, eccentric orbit about a drifting centre of mass
+        world.add_body(Body::new(Mass::kilograms(300.0), v(-2.0, 0.0, 0.0)).with_velocity(v(0.5, 1.0, -2.0)));
+        world.add_body(Body::new(Mass::kilograms(100.0), v(6.0, 0.0, 0.0)).with_velocity(v(0.5, 0.0, 6.0)));
+        let (start_energy, start_momentum) = (energy(&world), momentum(&world));
+        assert!(start_energy < 0.0);
+        for _ in 0 .. 20 {
+            world.run_for(Time::seconds(1.0));
+            let drift = (momentum(&world) - start_momentum).length();
+            assert!(drift < 1e-3 * start_momentum.length(), "momentum drifted by {}", drift);
+            let error = ((energy(&world) - start_energy) / start_energy).abs();
+            assert!(error < 5e-3, "energy off by {}", error);
+        }
+    }
+
+    #[test]
+    fn cluster_conserves_energy_and_momentum() {
+        let field = GravityField { mutual: true, constant: 1.0, softening: Length::meters(0.5), ..GravityField::default() };
+        let mut world = space(field);
+        let mut rng = Rng::new(7);
+        for _ in 0 .. 12 {
+            let position = v(rng.range(-10.0, 10.0), rng.range(-10.0, 10.0), rng.range(-10.0, 10.0));
+            let velocity = v(rng.range(-1.0, 1.0), rng.range(-1.0, 1.0), rng.range(-1.0, 1.0));
+            world.add_body(Body::new(Mass::kilograms(rng.range(1.0, 20.0)), position).with_velocity(velocity));
+        }
+        let (start_energy, start_momentum) = (energy(&world), momentum(&world));
+        world.run_for(Time::seconds(10.0));
+        assert!((momentum(&world) - start_momentum).length() < 1e-3);
+        let error = ((energy(&world) - start_energy) / start_energy).abs();
+        assert!(error < 1e-2, "energy off by {}", error);
+    }
+
+    #[test]
+    fn barnes_hut_approximates_direct_sum() {
+        let mut rng = Rng::new(3);
+        let bodies: Vec<(Vertex3D, Mass)> = (0 .. 500)
+            .map(|_| (v(rng.range(-100.0, 100.0), rng.range(-100.0, 100.0), rng.range(-100.0, 100.0)), Mass::kilograms(rng.range(1.0, 10.0))))
+            .collect();
+        let exact = GravityField { mutual: true, constant: 1.0, softening: Length::meters(1.0), theta: 0.0, ..GravityField::default() };
+        let approximate = GravityField { theta: 0.5, barnes_hut_threshold: 100, ..exact.clone() };
+        let (a, b) = (exact.mutual_accelerations(&bodies), approximate.mutual_accelerations(&bodies));
+        let error: f32 = a.iter().zip(b.iter()).map(|(a, b)| (*a - *b).length() / a.length()).sum::<f32>() / a.len() as f32;
+        assert!(error < 0.01, "mean relative error {}", error);
+
+        // coincident bodies stop splitting instead of recursing forever
+        let stacked = vec![(v(1.0, 1.0, 1.0), Mass::kilograms(1.0)); 100];
+        let pulls = approximate.mutual_accelerations(&stacked);
+        assert!(pulls.iter().all(|a| a.length() < 1e-6));
+
+        // nothing to build a tree from
+        let eager = GravityField { barnes_hut_threshold: 0, ..approximate };
+        assert!(eager.mutual_accelerations(&[]).is_empty());
+        let mut world = space(eager);
+        world.add_body(Body::fixed(v(1.0, 2.0, 3.0)));
+        world.step();
+    }
+}
@@ -0,0 +1,330 @@
+use crate::geometry::Vertex3D;
+use crate::physics::gravity::GRAVITATIONAL_CONSTANT;
+use crate::units::Length;
+use crate::units::Mass;
+use crate::units::Time;
+use std::f32::consts::PI;
+
+// Orbits are around a body at the origin. With y up, the reference plane is
+// x-z and the pole is +y; longitudes are measured from +x towards -z, so an
+// orbit that runs anticlockwise seen from above has zero inclination.
+// `mu` is the standard gravitational parameter G M of the central body,
+// m^3 s^-2.
+
+const EPSILON: f32 = 1e-6;
+
+// Game coordinates to the usual right-handed z-up frame and back.
+fn to_frame(v: Vertex3D) -> Vertex3D {
+    Vertex3D { x: v.x, y: -v.z, z: v.y }
+}
+
+fn from_frame(v: Vertex3D) -> Vertex3D {
+    Vertex3D { x: v.x, y: v.z, z: -v.y }
+}
+
+pub fn gravitational_parameter(mass: Mass) -> f32 {
+    GRAVITATIONAL_CONSTANT * mass.in_kilograms()
+}
+
+// Angles are in radians. Parabolic orbits, with an eccentricity of exactly
+// one, have no semi-major axis and can't be represented.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OrbitalElements {
+    // negative for hyperbolic orbits
+    pub semi_major_axis: Length,
+    pub eccentricity: f32,
+    pub inclination: f32,
+    // zero for equatorial orbits, where there is no node
+    pub longitude_of_ascending_node: f32,
+    // zero for circular orbits, where there is no periapsis
+    pub argument_of_periapsis: f32,
+    // in (-pi, pi]; measured from the node, or from +x when equatorial too,
+    // on circular orbits
+    pub true_anomaly: f32,
+}
+
+fn wrap(angle: f32) -> f32 {
+    let wrapped = angle.rem_euclid(2.0 * PI);
+    if wrapped > PI { wrapped - 2.0 * PI } else { wrapped }
+}
+
+// signed angle from a to b about the axis
+fn angle_about(axis: Vertex3D, a: Vertex3D, b: Vertex3D) -> f32 {
+    axis.dot(a.cross(b)).atan2(a.dot(b))
+}
+
+// Eccentric anomaly for a mean anomaly on an ellipse, by Newton's method on
+// M = E - e sin E.
+pub fn solve_kepler(mean_anomaly: f32, eccentricity: f32) -> f32 {
+    let (m, e) = (wrap(mean_anomaly) as f64, eccentricity as f64);
+    let mut anomaly = if e < 0.8 { m } else { std::f64::consts::PI.copysign(m) };
+    for _ in 0 .. 50 {
+        let step = (anomaly - e * anomaly.sin() - m) / (1.0 - e * anomaly.cos());
+        anomaly -= step;
+        if step.abs() < 1e-12 {
+            break;
+        }
+    }
+    anomaly as f32
+}
+
+// Hyperbolic anomaly for a mean anomaly, from M = e sinh H - H.
+fn solve_kepler_hyperbolic(mean_anomaly: f32, eccentricity: f32) -> f32 {
+    let (m, e) = (mean_anomaly as f64, eccentricity as f64);
+    let mut anomaly = (m / e).asinh();
+    for _ in 0 .. 50 {
+        let step = (e * anomaly.sinh() - anomaly - m) / (e * anomaly.cosh() - 1.0);
+        anomaly -= step;
+        if step.abs() < 1e-12 {
+            break;
+        }
+    }
+    anomaly as f32
+}
+
+impl OrbitalElements {
+    pub fn from_state(position: Vertex3D, velocity: Vertex3D, mu: f32) -> OrbitalElements {
+        let (r, v) = (to_frame(position), to_frame(velocity));
+        let distance = r.length();
+        let h = r.cross(v);
+        let h_hat = h / h.length();
+        let energy = v.dot(v) / 2.0 - mu / distance;
+        let e_vec = (r * (v.dot(v) - mu / distance) - v * r.dot(v)) / mu;
+        let eccentricity = e_vec.length();
+
+        // the ascending node lies along K x h
+        let node = Vertex3D { x: -h.y, y: h.x, z: 0.0 };
+        let equatorial = node.length() <= EPSILON * h.length();
+        let node_hat = if equatorial { Vertex3D { x: 1.0, y: 0.0, z: 0.0 } } else { node / node.length() };
+        let circular = eccentricity <= EPSILON;
+        let periapsis_hat = if circular { node_hat } else { e_vec / eccentricity };
+
+        OrbitalElements {
+            semi_major_axis: Length::meters(-mu / (2.0 * energy)),
+            eccentricity,
+            inclination: h_hat.z.clamp(-1.0, 1.0).acos(),
+            longitude_of_ascending_node: if equatorial { 0.0 } else { node.y.atan2(node.x).rem_euclid(2.0 * PI) },
+            argument_of_periapsis: if circular { 0.0 } else { angle_about(h_hat, node_hat, periapsis_hat).rem_euclid(2.0 * PI) },
+            true_anomaly: angle_about(h_hat, periapsis_hat, r),
+        }
+    }
+
+    pub fn to_state(&self, mu: f32) -> (Vertex3D, Vertex3D) {
+        let (e, nu) = (self.eccentricity, self.true_anomaly);
+        let p = self.semi_latus_rectum();
+        let r = p / (1.0 + e * nu.cos());
+        let (so, co) = self.longitude_of_ascending_node.sin_cos();
+        let (sw, cw) = self.argument_of_periapsis.sin_cos();
+        let (si, ci) = self.inclination.sin_cos();
+        // the perifocal axes: towards periapsis, and 90 degrees on in the direction of motion
+        let towards = Vertex3D { x: co * cw - so * sw * ci, y: so * cw + co * sw * ci, z: sw * si };
+        let along = Vertex3D { x: -co * sw - so * cw * ci, y: -so * sw + co * cw * ci, z: cw * si };
+        let position = towards * (r * nu.cos()) + along * (r * nu.sin());
+        let speed = (mu / p).sqrt();
+        let velocity = towards * (-speed * nu.sin()) + along * (speed * (e + nu.cos()));
+        (from_frame(position), from_frame(velocity))
+    }
+
+    fn semi_latus_rectum(&self) -> f32 {
+        self.semi_major_axis.in_meters() * (1.0 - self.eccentricity * self.eccentricity)
+    }
+
+    pub fn is_bound(&self) -> bool {
+        self.eccentricity < 1.0
+    }
+
+    pub fn periapsis(&self) -> Length {
+        Length::meters(self.semi_latus_rectum() / (1.0 + self.eccentricity))
+    }
+
+    // None when the orbit escapes
+    pub fn apoapsis(&self) -> Option<Length> {
+        self.is_bound().then(|| self.semi_major_axis * (1.0 + self.eccentricity))
+    }
+
+    pub fn period(&self, mu: f32) -> Option<Time> {
+        self.is_bound().then(|| Time::seconds(2.0 * PI / self.mean_motion(mu)))
+    }
+
+    // radians per second
+    pub fn mean_motion(&self, mu: f32) -> f32 {
+        (mu / self.semi_major_axis.in_meters().abs().powi(3)).sqrt()
+    }
+
+    pub fn mean_anomaly(&self) -> f32 {
+        let (e, nu) = (self.eccentricity, self.true_anomaly);
+        if self.is_bound() {
+            let anomaly = ((1.0 - e * e).sqrt() * nu.sin()).atan2(e + nu.cos());
+            anomaly - e * anomaly.sin()
+        } else {
+            let anomaly = 2.0 * (((e - 1.0) / (e + 1.0)).sqrt() * (nu / 2.0).tan()).atanh();
+            e * anomaly.sinh() - anomaly
+        }
+    }
+
+    // The same orbit `elapsed` later, which may be negative.
+    pub fn propagate(&self, elapsed: Time, mu: f32) -> OrbitalElements {
+        let e = self.eccentricity;
+        let mean_anomaly = self.mean_anomaly() + self.mean_motion(mu) * elapsed.in_seconds();
+        let true_anomaly = if self.is_bound() {
+            let anomaly = solve_kepler(mean_anomaly, e);
+            ((1.0 - e * e).sqrt() * anomaly.sin()).atan2(anomaly.cos() - e)
+        } else {
+            let anomaly = solve_kepler_hyperbolic(mean_anomaly, e);
+            2.0 * (((e + 1.0) / (e - 1.0)).sqrt() * (anomaly / 2.0).tanh()).atan()
+        };
+        OrbitalElements { true_anomaly, ..*self }
+    }
+}
+
+// Position and velocity `elapsed` after the given state, following the
+// conic exactly rather than integrating.
+pub fn propagate(position: Vertex3D, velocity: Vertex3D, elapsed: Time, mu: f32) -> (Vertex3D, Vertex3D) {
+    OrbitalElements::from_state(position, velocity, mu).propagate(elapsed, mu).to_state(mu)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Earth, m^3 s^-2
+    const MU: f32 = 3.986004e14;
+
+    fn v(x: f32, y: f32, z: f32) -> Vertex3D {
+        Vertex3D { x, y, z }
+    }
+
+    // textbook vectors are z-up; these are km and km/s in that frame
+    fn textbook(x: f32, y: f32, z: f32) -> Vertex3D {
+        from_frame(v(x * 1000.0, y * 1000.0, z * 1000.0))
+    }
+
+    fn degrees(angle: f32) -> f32 {
+        angle.to_degrees()
+    }
+
+    fn close(a: Vertex3D, b: Vertex3D, tolerance: f32) -> bool {
+        (a - b).length() <= tolerance
+    }
+
+    // A fine fixed-step RK4 in f64 to check propagation against.
+    fn integrate(position: Vertex3D, velocity: Vertex3D, seconds: f64, mu: f64) -> Vertex3D {
+        let accel = |p: [f64; 3]| {
+            let r = (p[0] * p[0] + p[1] * p[1] + p[2] * p[2]).sqrt();
+            [-mu * p[0] / r.powi(3), -mu * p[1] / r.powi(3), -mu * p[2] / r.powi(3)]
+        };
+        let shift = |a: [f64; 3], b: [f64; 3], s: f64| [a[0] + b[0] * s, a[1] + b[1] * s, a[2] + b[2] * s];
+        let mut p = [position.x as f64, position.y as f64, position.z as f64];
+        let mut u = [velocity.x as f64, velocity.y as f64, velocity.z as f64];
+        let dt = 0.5f64.copysign(seconds);
+        for _ in 0 .. (seconds / dt).round() as u64 {
+            let (k1x, k1v) = (u, accel(p));
+            let (k2x, k2v) = (shift(u, k1v, dt / 2.0), accel(shift(p, k1x, dt / 2.0)));
+            let (k3x, k3v) = (shift(u, k2v, dt / 2.0), accel(shift(p, k2x, dt / 2.0)));
+            let (k4x, k4v) = (shift(u, k3v, dt), accel(shift(p, k3x, dt)));
+            for i in 0 .. 3 {
+                p[i] += dt / 6.0 * (k1x[i] + 2.0 * k2x[i] + 2.0 * k3x[i] + k4x[i]);
+                u[i] += dt / 6.0 * (k1v[i] + 2.0 * k2v[i] + 2.0 * k3v[i] + k4v[i]);
+            }
+        }
+        v(p[0] as f32, p[1] as f32, p[2] as f32)
+    }
+
+    #[test]
+    fn circular() {
+        let radius = 7.0e6;
+        let speed = (MU / radius).sqrt();
+        // anticlockwise from above: +x towards -z
+        let elements = OrbitalElements::from_state(v(radius, 0.0, 0.0), v(0.0, 0.0, -speed), MU);
+        assert!(elements.eccentricity < 1e-5);
+        assert!(elements.inclination.abs() < 1e-5);
+        assert!(((elements.semi_major_axis - Length::meters(radius)) / Length::meters(radius)).abs() < 1e-5);
+        let period = elements.period(MU).unwrap().in_seconds();
+        assert!((period - 5828.5).abs() < 1.0, "{}", period);
+        assert!((elements.periapsis() / elements.apoapsis().unwrap() - 1.0).abs() < 1e-4);
+
+        let (quarter, velocity) = elements.propagate(Time::seconds(period / 4.0), MU).to_state(MU);
+        assert!(close(quarter, v(0.0, 0.0, -radius), 50.0), "{:?}", quarter);
+        assert!(close(velocity, v(-speed, 0.0, 0.0), 0.05), "{:?}", velocity);
+        let (around, _) = elements.propagate(Time::seconds(10.0 * period), MU).to_state(MU);
+        assert!(close(around, v(radius, 0.0, 0.0), 100.0), "{:?}", around);
+
+        // the other way round is inclined by 180 degrees
+        let retrograde = OrbitalElements::from_state(v(radius, 0.0, 0.0), v(0.0, 0.0, speed), MU);
+        assert!((degrees(retrograde.inclination) - 180.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn elliptical_reference() {
+        // Curtis, Orbital Mechanics for Engineering Students, example 4.3
+        let position = textbook(-6045.0, -3490.0, 2500.0);
+        let velocity = textbook(-3.457, 6.618, 2.533);
+        let elements = OrbitalElements::from_state(position, velocity, MU);
+        assert!((elements.eccentricity - 0.1712).abs() < 1e-3);
+        assert!((elements.semi_major_axis.in_kilometers() - 8788.0).abs() < 2.0);
+        assert!((degrees(elements.inclination) - 153.2).abs() < 0.05);
+        assert!((degrees(elements.longitude_of_ascending_node) - 255.3).abs() < 0.05);
+        assert!((degrees(elements.argument_of_periapsis) - 20.07).abs() < 0.05);
+        assert!((degrees(elements.true_anomaly) - 28.45).abs() < 0.05);
+        assert!((elements.periapsis().in_kilometers() - 7284.0).abs() < 2.0);
+        assert!((elements.apoapsis().unwrap().in_kilometers() - 10292.0).abs() < 2.0);
+        assert!((elements.period(MU).unwrap().in_seconds() - 8198.0).abs() < 5.0);
+
+        let (p, u) = elements.to_state(MU);
+        assert!(close(p, position, 5.0), "{:?}", p);
+        assert!(close(u, velocity, 0.01), "{:?}", u);
+    }
+
+    #[test]
+    fn propagation_matches_integration() {
+        let position = textbook(-6045.0, -3490.0, 2500.0);
+        let velocity = textbook(-3.457, 6.618, 2.533);
+        for seconds in [600.0, 3000.0, 7000.0] {
+            let (kepler, _) = propagate(position, velocity, Time::seconds(seconds), MU);
+            let numeric = integrate(position, velocity, seconds as f64, MU as f64);
+            assert!(close(kepler, numeric, 100.0), "after {} s {:?} vs {:?}", seconds, kepler, numeric);
+        }
+        // and back again
+        let (ahead, ahead_velocity) = propagate(position, velocity, Time::seconds(3000.0), MU);
+        let (back, _) = propagate(ahead, ahead_velocity, Time::seconds(-3000.0), MU);
+        assert!(close(back, position, 100.0));
+    }
+
+    #[test]
+    fn hyperbolic() {
+        let periapsis = 7.0e6;
+        let speed = 1.2 * (2.0 * MU / periapsis).sqrt();
+        let position = v(0.0, 0.0, periapsis);
+        let velocity = v(0.0, speed, 0.0);
+        let elements = OrbitalElements::from_state(position, velocity, MU);
+        // e = r v^2 / mu - 1 and a = r / (1 - e) at periapsis
+        assert!((elements.eccentricity - 1.88).abs() < 1e-4);
+        assert!((elements.semi_major_axis.in_kilometers() + 7954.5).abs() < 1.0);
+        assert!((degrees(elements.inclination) - 90.0).abs() < 1e-3);
+        assert!(elements.true_anomaly.abs() < 1e-3);
+        assert!(!elements.is_bound());
+        assert_eq!(elements.period(MU), None);
+        assert_eq!(elements.apoapsis(), None);
+        assert!((elements.periapsis().in_meters() - periapsis).abs() < 10.0);
+
+        for seconds in [-2000.0, 1500.0, 4000.0] {
+            let (kepler, _) = propagate(position, velocity, Time::seconds(seconds), MU);
+            let numeric = integrate(position, velocity, seconds as f64, MU as f64);
+            assert!(close(kepler, numeric, 100.0), "after {} s {:?} vs {:?}", seconds, kepler, numeric);
+        }
+        // heading out past the asymptote's limit
+        let later = elements.propagate(Time::seconds(1.0e5), MU);
+        let limit = (-1.0 / elements.eccentricity).acos();
+        assert!(later.true_anomaly > 0.0 && later.true_anomaly < limit);
+    }
+
+    #[test]
+    fn kepler_equation() {
+        for e in [0.0f32, 0.3, 0.9, 0.99] {
+            for m in [-3.0f32, -0.5, 0.1, 1.0, 3.1] {
+                let anomaly = solve_kepler(m, e);
+                assert!((anomaly - e * anomaly.sin() - m).abs() < 1e-5, "e={} m={}", e, m);
+            }
+        }
+    }
+}
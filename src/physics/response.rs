@@ -0,0 +1,245 @@
+use crate::collision::Contact;
+use crate::geometry::Vertex3D;
+use crate::physics::world::Body;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Material {
+    // 0 absorbs the impact, 1 bounces back with the same speed
+    pub restitution: f32,
+    pub static_friction: f32,
+    pub dynamic_friction: f32,
+}
+
+impl Default for Material {
+    fn default() -> Material {
+        Material { restitution: 0.2, static_friction: 0.6, dynamic_friction: 0.4 }
+    }
+}
+
+impl Material {
+    // The bouncier surface wins; friction is the geometric mean.
+    pub fn combine(&self, other: &Material) -> Material {
+        Material {
+            restitution: self.restitution.max(other.restitution),
+            static_friction: (self.static_friction * other.static_friction).sqrt(),
+            dynamic_friction: (self.dynamic_friction * other.dynamic_friction).sqrt(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ResponseConfig {
+    // closing speeds below this are treated as resting contact and don't bounce
+    pub restitution_threshold: f32,
+    // fraction of the penetration removed each step
+    pub correction: f32,
+    // penetration allowed before correcting, which stops resting bodies jittering
+    pub slop: f32,
+}
+
+impl Default for ResponseConfig {
+    fn default() -> ResponseConfig {
+        ResponseConfig { restitution_threshold: 0.5, correction: 0.8, slop: 0.005 }
+    }
+}
+
+// Applies the normal and friction impulses for a contact whose normal points
+// from a to b. Returns the normal impulse.
+pub fn resolve_velocity(a: &mut Body, b: &mut Body, contact: &Contact, config: &ResponseConfig) -> f32 {
+    let inverse_mass = a.inverse_mass() + b.inverse_mass();
+    if inverse_mass == 0.0 {
+        return 0.0;
+    }
+    let n = contact.normal;
+    let relative = b.velocity - a.velocity;
+    let closing = relative.dot(n);
+    if closing > 0.0 {
+        return 0.0;
+    }
+
+    let material = a.material.combine(&b.material);
+    let restitution = if -closing < config.restitution_threshold { 0.0 } else { material.restitution };
+    let j = -(1.0 + restitution) * closing / inverse_mass;
+    a.apply_impulse(n * -j);
+    b.apply_impulse(n * j);
+
+    // Coulomb friction against the sliding velocity left after the normal impulse
+    let relative = b.velocity - a.velocity;
+    let sliding = relative - n * relative.dot(n);
+    let speed = sliding.length();
+    if speed > 1e-6 {
+        let tangent = sliding / speed;
+        let stop = speed / inverse_mass;
+        let friction = if stop <= j * material.static_friction {
+            stop
+        } else {
+            j * material.dynamic_friction
+        };
+        a.apply_impulse(tangent * friction);
+        b.apply_impulse(tangent * -friction);
+    }
+    j
+}
+
+// Pushes the bodies apart in proportion to their inverse masses.
+pub fn correct_position(a: &mut Body, b: &mut Body, contact: &Contact, config: &ResponseConfig) {
+    let inverse_mass = a.inverse_mass() + b.inverse_mass();
+    if inverse_mass == 0.0 {
+        return;
+    }
+    let amount = (contact.depth - config.slop).max(0.0) / inverse_mass * config.correction;
+    let correction: Vertex3D = contact.normal * amount;
+    a.position -= correction * a.inverse_mass();
+    b.position += correction * b.inverse_mass();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Quaternion;
+    use crate::geometry::Shape3D;
+    use crate::physics::ACCELERATION_GRAVITY_EARTH;
+    use crate::physics::world::BodyId;
+    use crate::physics::world::World;
+    use crate::physics::world::WorldConfig;
+    use crate::units::Energy;
+    use crate::units::Mass;
+    use crate::units::Time;
+
+    const RADIUS: f32 = 0.5;
+
+    fn v(x: f32, y: f32, z: f32) -> Vertex3D {
+        Vertex3D { x, y, z }
+    }
+
+    // a large static slab whose top face is y = 0
+    fn ground(world: &mut World, material: Material) -> BodyId {
+        world.add_body(Body::fixed(v(0.0, -1.0, 0.0))
+            .with_collider(Shape3D::Cuboid { center: Vertex3D::ZERO, width: 200.0, height: 2.0, length: 200.0, orientation: Quaternion::IDENTITY })
+            .with_material(material))
+    }
+
+    fn ball(world: &mut World, height: f32, material: Material) -> BodyId {
+        world.add_body(Body::new(Mass::kilograms(1.0), v(0.0, height + RADIUS, 0.0))
+            .with_collider(Shape3D::Sphere { center: Vertex3D::ZERO, radius: RADIUS })
+            .with_material(material))
+    }
+
+    fn bouncy(restitution: f32) -> Material {
+        Material { restitution, ..Material::default() }
+    }
+
+    // highest point of the ball's bottom after its first bounce
+    fn rebound(restitution: f32, drop: f32) -> f32 {
+        let mut world = World::new(WorldConfig { timestep: Time::seconds(1.0 / 240.0), ..WorldConfig::default() });
+        ground(&mut world, bouncy(restitution));
+        let id = ball(&mut world, drop, bouncy(restitution));
+        let mut bounced = false;
+        let mut highest = 0.0f32;
+        for _ in 0 .. 240 * 5 {
+            world.step();
+            let body = world.body(id).unwrap();
+            if !world.contacts().is_empty() {
+                if bounced && body.velocity.y <= 0.0 {
+                    break;
+                }
+                bounced = true;
+            } else if bounced {
+                highest = highest.max(body.position.y - RADIUS);
+            }
+        }
+        highest
+    }
+
+    #[test]
+    fn combine() {
+        let a = Material { restitution: 0.1, static_friction: 0.9, dynamic_friction: 0.4 };
+        let b = Material { restitution: 0.7, static_friction: 0.1, dynamic_friction: 0.1 };
+        let m = a.combine(&b);
+        assert_eq!(m.restitution, 0.7);
+        assert!((m.static_friction - 0.3).abs() < 1e-6);
+        assert!((m.dynamic_friction - 0.2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn elastic_bounce_keeps_its_energy() {
+        let height = rebound(1.0, 5.0);
+        assert!((height - 5.0).abs() < 0.15, "rebounded to {}", height);
+    }
+
+    #[test]
+    fn rebound_height_scales_with_restitution_squared() {
+        for e in [0.3f32, 0.5, 0.8] {
+            let height = rebound(e, 5.0);
+            assert!((height - 5.0 * e * e).abs() < 0.15, "e={} rebounded to {}", e, height);
+        }
+    }
+
+    #[test]
+    fn inelastic_ball_comes_to_rest_without_sinking() {
+        let mut world = World::new(WorldConfig::default());
+        ground(&mut world, bouncy(0.0));
+        let id = ball(&mut world, 2.0, bouncy(0.0));
+        world.run_for(Time::seconds(3.0));
+        let body = world.body(id).unwrap();
+        assert!(body.velocity.y.abs() < 1e-3);
+        assert!((body.position.y - RADIUS).abs() < 0.01, "resting at {}", body.position.y);
+        assert!(body.kinetic_energy() < Energy::joules(1e-5));
+    }
+
+    #[test]
+    fn sliding_stops_after_friction_distance() {
+        let material = Material { restitution: 0.0, static_friction: 0.5, dynamic_friction: 0.5 };
+        let mut world = World::new(WorldConfig { timestep: Time::seconds(1.0 / 240.0), ..WorldConfig::default() });
+        ground(&mut world, material);
+        let id = world.add_body(Body::new(Mass::kilograms(2.0), v(0.0, 0.5, 0.0))
+            .with_velocity(v(4.0, 0.0, 0.0))
+            .with_collider(Shape3D::Cube { center: Vertex3D::ZERO, side: 1.0, orientation: Quaternion::IDENTITY })
+            .with_material(material));
+        world.run_for(Time::seconds(3.0));
+        let body = world.body(id).unwrap();
+        // v^2 / (2 mu g)
+        let expected = 16.0 / (2.0 * 0.5 * ACCELERATION_GRAVITY_EARTH.in_meters_per_second_squared());
+        assert!(body.velocity.x.abs() < 1e-3);
+        assert!((body.position.x - expected).abs() < 0.05, "slid {} not {}", body.position.x, expected);
+    }
+
+    #[test]
+    fn static_friction_holds_against_small_pushes() {
+        let material = Material { restitution: 0.0, static_friction: 0.5, dynamic_friction: 0.3 };
+        let mut world = World::new(WorldConfig::default());
+        ground(&mut world, material);
+        let id = world.add_body(Body::new(Mass::kilograms(1.0), v(0.0, 0.5, 0.0))
+            .with_collider(Shape3D::Cube { center: Vertex3D::ZERO, side: 1.0, orientation: Quaternion::IDENTITY })
+            .with_material(material));
+        let weight = ACCELERATION_GRAVITY_EARTH.in_meters_per_second_squared();
+        for _ in 0 .. 120 {
+            world.apply_force(id, v(0.4 * weight, 0.0, 0.0));
+            world.step();
+        }
+        assert!(world.body(id).unwrap().position.x.abs() < 1e-4);
+        for _ in 0 .. 120 {
+            world.apply_force(id, v(0.6 * weight, 0.0, 0.0));
+            world.step();
+        }
+        assert!(world.body(id).unwrap().position.x > 0.05);
+    }
+
+    #[test]
+    fn equal_masses_exchange_velocity() {
+        let mut world = World::new(WorldConfig { gravity: Vertex3D::ZERO, ..WorldConfig::default() });
+        let material = bouncy(1.0);
+        let a = world.add_body(Body::new(Mass::kilograms(1.0), v(0.0, 0.0, 0.0))
+            .with_velocity(v(2.0, 0.0, 0.0))
+            .with_collider(Shape3D::Sphere { center: Vertex3D::ZERO, radius: RADIUS })
+            .with_material(material));
+        let b = world.add_body(Body::new(Mass::kilograms(1.0), v(1.5, 0.0, 0.0))
+            .with_collider(Shape3D::Sphere { center: Vertex3D::ZERO, radius: RADIUS })
+            .with_material(material));
+        let momentum = |w: &World| w.body(a).unwrap().velocity.x + w.body(b).unwrap().velocity.x;
+        world.run_for(Time::seconds(1.0));
+        assert!((momentum(&world) - 2.0).abs() < 1e-4);
+        assert!(world.body(a).unwrap().velocity.x.abs() < 1e-4);
+        assert!((world.body(b).unwrap().velocity.x - 2.0).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,642 @@
+use crate::collision;
+use crate::collision::Contact;
+use crate::geometry::Aabb;
+use crate::geometry::Quaternion;
+use crate::geometry::Shape3D;
+use crate::geometry::Transform;
+use crate::geometry::Vertex3D;
+use crate::physics::ACCELERATION_GRAVITY_EARTH;
+use crate::physics::constraint::Attached;
+use crate::physics::constraint::Constraint;
+use crate::physics::constraint::ConstraintConfig;
+use crate::physics::constraint::ConstraintId;
+use crate::physics::gravity::GravityField;
+use crate::physics::response;
+use crate::physics::response::Material;
+use crate::physics::response::ResponseConfig;
+use crate::units::Energy;
+use crate::units::Mass;
+use crate::units::Time;
+
+pub type BodyId = usize;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Body {
+    // zero or infinite mass makes the body static
+    pub mass: Mass,
+    pub position: Vertex3D,
+    pub velocity: Vertex3D,
+    pub orientation: Quaternion,
+    // rad s^-1, world frame
+    pub angular_velocity: Vertex3D,
+    // kg m^2, the same about every axis
+    pub inertia: f32,
+    // relative to position; bodies without one don't collide
+    pub collider: Option<Shape3D>,
+    pub material: Material,
+    force: Vertex3D,
+    torque: Vertex3D,
+}
+
+impl Body {
+    pub fn new(mass: Mass, position: Vertex3D) -> Body {
+        Body {
+            mass,
+            position,
+            velocity: Vertex3D::ZERO,
+            orientation: Quaternion::IDENTITY,
+            angular_velocity: Vertex3D::ZERO,
+            // a solid sphere a metre across: 2/5 * m * 0.5^2
+            inertia: 0.1 * mass.in_kilograms(),
+            collider: None,
+            material: Material::default(),
+            force: Vertex3D::ZERO,
+            torque: Vertex3D::ZERO,
+        }
+    }
+
+    pub fn fixed(position: Vertex3D) -> Body {
+        Body::new(Mass::kilograms(f32::INFINITY), position)
+    }
+
+    pub fn with_velocity(mut self, velocity: Vertex3D) -> Body {
+        self.velocity = velocity;
+        self
+    }
+
+    pub fn with_angular_velocity(mut self, angular_velocity: Vertex3D) -> Body {
+        self.angular_velocity = angular_velocity;
+        self
+    }
+
+    pub fn with_inertia(mut self, inertia: f32) -> Body {
+        self.inertia = inertia;
+        self
+    }
+
+    pub fn with_collider(mut self, collider: Shape3D) -> Body {
+        self.collider = Some(collider);
+        self
+    }
+
+    pub fn with_material(mut self, material: Material) -> Body {
+        self.material = material;
+        self
+    }
+
+    // The collider turned and moved with the body.
+    pub fn shape(&self) -> Option<Shape3D> {
+        self.collider.as_ref().map(|c| c.transformed(&Transform::new(self.position, self.orientation, 1.0)))
+    }
+
+    pub fn is_static(&self) -> bool {
+        !(self.mass > Mass::ZERO && self.mass.is_finite())
+    }
+
+    pub fn inverse_mass(&self) -> f32 {
+        if self.is_static() { 0.0 } else { 1.0 / self.mass.in_kilograms() }
+    }
+
+    pub fn inverse_inertia(&self) -> f32 {
+        if self.is_static() || !(self.inertia > 0.0 && self.inertia.is_finite()) { 0.0 } else { 1.0 / self.inertia }
+    }
+
+    // Force accumulated since the last step.
+    pub fn force(&self) -> Vertex3D {
+        self.force
+    }
+
+    pub fn torque(&self) -> Vertex3D {
+        self.torque
+    }
+
+    pub fn apply_force(&mut self, force: Vertex3D) {
+        self.force += force;
+    }
+
+    pub fn apply_torque(&mut self, torque: Vertex3D) {
+        self.torque += torque;
+    }
+
+    // Instantaneous change in momentum, N s.
+    pub fn apply_impulse(&mut self, impulse: Vertex3D) {
+        self.velocity += impulse * self.inverse_mass();
+    }
+
+    // An impulse through a world-space point, which also spins the body.
+    pub fn apply_impulse_at(&mut self, impulse: Vertex3D, point: Vertex3D) {
+        self.apply_impulse(impulse);
+        let arm = point - self.position;
+        self.angular_velocity += arm.cross(impulse) * self.inverse_inertia();
+    }
+
+    pub fn kinetic_energy(&self) -> Energy {
+        if self.is_static() {
+            return Energy::ZERO;
+        }
+        let (v, w) = (self.velocity, self.angular_velocity);
+        Energy::joules(0.5 * self.mass.in_kilograms() * v.dot(v) + 0.5 * self.inertia * w.dot(w))
+    }
+}
+
+// xorshift64*: small, fast and the same on every platform, which is all the
+// simulation needs for reproducible runs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        // zero is a fixed point of xorshift
+        Rng { state: if seed == 0 { 0x9E37_79B9_7F4A_7C15 } else { seed } }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    // uniform in [0, 1)
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    pub fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct WorldConfig {
+    pub timestep: Time,
+    // uniform, everywhere outside the field's zones
+    pub gravity: Vertex3D,
+    pub field: GravityField,
+    pub seed: u64,
+    // cap on steps taken by one call to advance, so a long stall doesn't
+    // turn into a spiral of catch-up work
+    pub max_steps_per_advance: u32,
+    pub response: ResponseConfig,
+    pub constraints: ConstraintConfig,
+}
+
+impl Default for WorldConfig {
+    fn default() -> WorldConfig {
+        WorldConfig {
+            timestep: Time::seconds(1.0 / 60.0),
+            gravity: Vertex3D { x: 0.0, y: -ACCELERATION_GRAVITY_EARTH.in_meters_per_second_squared(), z: 0.0 },
+            field: GravityField::default(),
+            seed: 0,
+            max_steps_per_advance: 8,
+            response: ResponseConfig::default(),
+            constraints: ConstraintConfig::default(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BodyContact {
+    pub a: BodyId,
+    pub b: BodyId,
+    // normal points from a to b
+    pub contact: Contact,
+    pub impulse: f32,
+}
+
+// Bodies are integrated with semi-implicit Euler at a fixed timestep, in
+// insertion order, so the same seed and inputs always give the same state.
+pub struct World {
+    config: WorldConfig,
+    bodies: Vec<Option<Body>>,
+    contacts: Vec<BodyContact>,
+    constraints: Vec<Option<Attached>>,
+    rng: Rng,
+    paused: bool,
+    steps: u64,
+    accumulator: Time,
+}
+
+impl World {
+    pub fn new(config: WorldConfig) -> World {
+        World {
+            rng: Rng::new(config.seed),
+            config,
+            bodies: Vec::new(),
+            contacts: Vec::new(),
+            constraints: Vec::new(),
+            paused: false,
+            steps: 0,
+            accumulator: Time::ZERO,
+        }
+    }
+
+    pub fn config(&self) -> &WorldConfig {
+        &self.config
+    }
+
+    pub fn set_gravity(&mut self, gravity: Vertex3D) {
+        self.config.gravity = gravity;
+    }
+
+    pub fn set_gravity_field(&mut self, field: GravityField) {
+        self.config.field = field;
+    }
+
+    pub fn rng(&mut self) -> &mut Rng {
+        &mut self.rng
+    }
+
+    pub fn add_body(&mut self, body: Body) -> BodyId {
+        self.bodies.push(Some(body));
+        self.bodies.len() - 1
+    }
+
+    // Ids are never reused.
+    pub fn remove_body(&mut self, id: BodyId) -> Option<Body> {
+        self.bodies.get_mut(id).and_then(|b| b.take())
+    }
+
+    pub fn body(&self, id: BodyId) -> Option<&Body> {
+        self.bodies.get(id).and_then(|b| b.as_ref())
+    }
+
+    pub fn body_mut(&mut self, id: BodyId) -> Option<&mut Body> {
+        self.bodies.get_mut(id).and_then(|b| b.as_mut())
+    }
+
+    pub fn bodies(&self) -> impl Iterator<Item = (BodyId, &Body)> {
+        self.bodies.iter().enumerate().filter_map(|(id, b)| Some((id, b.as_ref()?)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.bodies().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn apply_force(&mut self, id: BodyId, force: Vertex3D) {
+        if let Some(body) = self.body_mut(id) {
+            body.apply_force(force);
+        }
+    }
+
+    pub fn apply_torque(&mut self, id: BodyId, torque: Vertex3D) {
+        if let Some(body) = self.body_mut(id) {
+            body.apply_torque(torque);
+        }
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    // multiplied out from the step count in f64 rather than summed, so long runs don't drift; Time is f32
+    pub fn time(&self) -> Time {
+        Time::seconds((self.steps as f64 * self.config.timestep.in_seconds() as f64) as f32)
+    }
+
+    // None if either body is missing. The joint is fixed to the bodies as
+    // they are now, so add it once they are in place.
+    pub fn add_constraint(&mut self, constraint: Constraint) -> Option<ConstraintId> {
+        if constraint.a == constraint.b {
+            return None;
+        }
+        let attached = Attached::new(constraint, self.body(constraint.a)?, self.body(constraint.b)?);
+        self.constraints.push(Some(attached));
+        Some(self.constraints.len() - 1)
+    }
+
+    // Ids are never reused.
+    pub fn remove_constraint(&mut self, id: ConstraintId) -> Option<Constraint> {
+        self.constraints.get_mut(id).and_then(|c| c.take()).map(|c| c.constraint)
+    }
+
+    pub fn constraint(&self, id: ConstraintId) -> Option<&Constraint> {
+        self.constraints.get(id).and_then(|c| c.as_ref()).map(|c| &c.constraint)
+    }
+
+    // How far the joint has drifted from holding: the size of its position
+    // and angle errors together.
+    pub fn constraint_error(&self, id: ConstraintId) -> Option<f32> {
+        let attached = self.constraints.get(id)?.as_ref()?;
+        Some(attached.error(self.body(attached.constraint.a)?, self.body(attached.constraint.b)?))
+    }
+
+    pub fn constraints(&self) -> impl Iterator<Item = (ConstraintId, &Constraint)> {
+        self.constraints.iter().enumerate().filter_map(|(id, c)| Some((id, &c.as_ref()?.constraint)))
+    }
+
+    // Runs `solve` on every constraint whose bodies are both still there.
+    fn each_constraint<F: FnMut(&mut Attached, &mut Body, &mut Body)>(&mut self, mut solve: F) {
+        let mut constraints = std::mem::take(&mut self.constraints);
+        for attached in constraints.iter_mut().flatten() {
+            if let Some((a, b)) = self.pair_mut(attached.constraint.a, attached.constraint.b) {
+                solve(attached, a, b);
+            }
+        }
+        self.constraints = constraints;
+    }
+
+    // Contacts found during the last step.
+    pub fn contacts(&self) -> &[BodyContact] {
+        &self.contacts
+    }
+
+    // Both bodies of a pair, mutably.
+    fn pair_mut(&mut self, a: BodyId, b: BodyId) -> Option<(&mut Body, &mut Body)> {
+        if a == b {
+            return None;
+        }
+        let (low, high) = (a.min(b), a.max(b));
+        let (head, tail) = self.bodies.split_at_mut(high);
+        let (first, second) = (head[low].as_mut()?, tail[0].as_mut()?);
+        if a < b { Some((first, second)) } else { Some((second, first)) }
+    }
+
+    // Sort and sweep along x on the bodies' bounding boxes, then the exact
+    // test on pairs whose boxes meet. Pairs are checked in body order so the
+    // contacts come out the same as testing every pair.
+    fn find_contacts(&self) -> Vec<BodyContact> {
+        let shapes: Vec<(BodyId, Shape3D, Aabb, bool)> = self.bodies()
+            .filter_map(|(id, body)| {
+                let shape = body.shape()?;
+                // grown so shapes that only touch still pair up
+                let bounds = shape.aabb().expanded(collision::TOUCHING);
+                Some((id, shape, bounds, body.is_static()))
+            })
+            .collect();
+        let mut order: Vec<usize> = (0 .. shapes.len()).collect();
+        order.sort_by(|&i, &j| shapes[i].2.min.x.total_cmp(&shapes[j].2.min.x));
+
+        let mut pairs = Vec::new();
+        for (n, &i) in order.iter().enumerate() {
+            let (_, _, bounds_i, static_i) = &shapes[i];
+            for &j in order[n + 1 ..].iter() {
+                let (_, _, bounds_j, static_j) = &shapes[j];
+                if bounds_j.min.x > bounds_i.max.x {
+                    break;
+                }
+                if !(*static_i && *static_j) && bounds_i.intersects(bounds_j) {
+                    pairs.push((i.min(j), i.max(j)));
+                }
+            }
+        }
+        pairs.sort();
+
+        let mut contacts = Vec::new();
+        for (i, j) in pairs {
+            let ((a, shape_a, ..), (b, shape_b, ..)) = (&shapes[i], &shapes[j]);
+            if let Some(contact) = collision::collide(shape_a, shape_b) {
+                contacts.push(BodyContact { a: *a, b: *b, contact, impulse: 0.0 });
+            }
+        }
+        contacts
+    }
+
+    // Advances exactly one timestep, even while paused. Velocities are
+    // updated first, then joint and contact impulses applied, then positions
+    // moved, so resting bodies stay put. Joints finish by pulling any drift
+    // out of the new positions.
+    pub fn step(&mut self) {
+        self.each_constraint(|c, a, b| c.apply_spring(a, b));
+
+        let dt = self.config.timestep.in_seconds();
+        let gravity = self.config.gravity;
+        let field = &self.config.field;
+        // static bodies neither pull nor get pulled; use point masses for fixed attractors
+        let mut mutual = if field.mutual {
+            let dynamic: Vec<(Vertex3D, Mass)> = self.bodies.iter().flatten()
+                .filter(|b| !b.is_static())
+                .map(|b| (b.position, b.mass))
+                .collect();
+            field.mutual_accelerations(&dynamic)
+        } else {
+            Vec::new()
+        }.into_iter();
+        for body in self.bodies.iter_mut().flatten() {
+            if !body.is_static() {
+                let mut acceleration = field.uniform_at(body.position, gravity) + field.point_masses_at(body.position);
+                if let Some(pull) = mutual.next() {
+                    acceleration += pull;
+                }
+                acceleration += body.force * body.inverse_mass();
+                body.velocity += acceleration * dt;
+                body.angular_velocity += body.torque * (body.inverse_inertia() * dt);
+            }
+            body.force = Vertex3D::ZERO;
+            body.torque = Vertex3D::ZERO;
+        }
+
+        let joints = self.config.constraints;
+        self.each_constraint(|c, a, b| c.warm_start(a, b));
+        for _ in 0 .. joints.velocity_iterations {
+            self.each_constraint(|c, a, b| c.solve_velocity(a, b));
+        }
+
+        let mut contacts = self.find_contacts();
+        let config = self.config.response;
+        for c in contacts.iter_mut() {
+            if let Some((a, b)) = self.pair_mut(c.a, c.b) {
+                c.impulse = response::resolve_velocity(a, b, &c.contact, &config);
+            }
+        }
+
+        for body in self.bodies.iter_mut().flatten() {
+            if !body.is_static() {
+                body.position += body.velocity * dt;
+                body.orientation = body.orientation.integrated(body.angular_velocity * dt);
+            }
+        }
+
+        for c in contacts.iter() {
+            if let Some((a, b)) = self.pair_mut(c.a, c.b) {
+                response::correct_position(a, b, &c.contact, &config);
+            }
+        }
+        for _ in 0 .. joints.position_iterations {
+            self.each_constraint(|c, a, b| c.solve_position(a, b, joints.correction));
+        }
+        self.contacts = contacts;
+        self.steps += 1;
+    }
+
+    // Steps for `duration` of simulated time, rounded to whole steps, even
+    // while paused. Returns the number of steps taken.
+    pub fn run_for(&mut self, duration: Time) -> u64 {
+        let steps = (duration / self.config.timestep).round().max(0.0) as u64;
+        for _ in 0 .. steps {
+            self.step();
+        }
+        steps
+    }
+
+    // Feeds in wall-clock time. Leftover time is carried to the next call,
+    // and nothing happens while paused. Returns the number of steps taken.
+    pub fn advance(&mut self, elapsed: Time) -> u32 {
+        if self.paused {
+            return 0;
+        }
+        self.accumulator += elapsed;
+        let mut steps = 0;
+        while self.accumulator >= self.config.timestep && steps < self.config.max_steps_per_advance {
+            self.step();
+            self.accumulator -= self.config.timestep;
+            steps += 1;
+        }
+        if steps == self.config.max_steps_per_advance {
+            self.accumulator = self.accumulator.min(self.config.timestep);
+        }
+        steps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world() -> World {
+        World::new(WorldConfig { timestep: Time::seconds(0.01), ..WorldConfig::default() })
+    }
+
+    #[test]
+    fn free_fall() {
+        let mut w = world();
+        let id = w.add_body(Body::new(Mass::kilograms(2.0), Vertex3D { x: 0.0, y: 100.0, z: 0.0 })
+            .with_velocity(Vertex3D { x: 3.0, y: 0.0, z: 0.0 }));
+        assert_eq!(w.run_for(Time::seconds(2.0)), 200);
+        let body = w.body(id).unwrap();
+        // semi-implicit Euler overshoots the parabola by g t dt / 2
+        let g = ACCELERATION_GRAVITY_EARTH.in_meters_per_second_squared();
+        let expected = 100.0 - 0.5 * g * 4.0;
+        assert!((body.position.y - expected).abs() < g * 2.0 * 0.01);
+        assert!((body.position.x - 6.0).abs() < 1e-3);
+        assert!((body.velocity.y + g * 2.0).abs() < 1e-3);
+        assert!((w.time() - Time::seconds(2.0)).abs() < Time::seconds(1e-6));
+    }
+
+    #[test]
+    fn forces_are_cleared_each_step() {
+        let mut w = world();
+        w.set_gravity(Vertex3D::ZERO);
+        let id = w.add_body(Body::new(Mass::kilograms(4.0), Vertex3D::ZERO));
+        let anchor = w.add_body(Body::fixed(Vertex3D::ZERO));
+        w.apply_force(id, Vertex3D { x: 8.0, y: 0.0, z: 0.0 });
+        w.apply_force(anchor, Vertex3D { x: 8.0, y: 0.0, z: 0.0 });
+        w.step();
+        assert!((w.body(id).unwrap().velocity.x - 0.02).abs() < 1e-6);
+        assert_eq!(w.body(id).unwrap().force(), Vertex3D::ZERO);
+        w.step();
+        assert!((w.body(id).unwrap().velocity.x - 0.02).abs() < 1e-6);
+        assert_eq!(w.body(anchor).unwrap().position, Vertex3D::ZERO);
+    }
+
+    #[test]
+    fn pause_and_advance() {
+        let mut w = world();
+        let id = w.add_body(Body::new(Mass::kilograms(1.0), Vertex3D::ZERO));
+        assert_eq!(w.advance(Time::seconds(0.025)), 2);
+        assert_eq!(w.advance(Time::seconds(0.006)), 1);
+        w.pause();
+        let paused_at = w.body(id).unwrap().position;
+        assert_eq!(w.advance(Time::seconds(1.0)), 0);
+        assert_eq!(w.body(id).unwrap().position, paused_at);
+        w.step();
+        assert_ne!(w.body(id).unwrap().position, paused_at);
+        assert_eq!(w.steps(), 4);
+        w.resume();
+        // capped, and the backlog is dropped
+        assert_eq!(w.advance(Time::seconds(1.0)), 8);
+        assert_eq!(w.advance(Time::seconds(0.0)), 1);
+    }
+
+    #[test]
+    fn deterministic() {
+        let run = |seed| {
+            let mut w = World::new(WorldConfig { seed, ..WorldConfig::default() });
+            for _ in 0 .. 20 {
+                let position = Vertex3D { x: w.rng().range(-10.0, 10.0), y: w.rng().range(0.0, 50.0), z: w.rng().range(-10.0, 10.0) };
+                let mass = w.rng().range(0.5, 5.0);
+                w.add_body(Body::new(Mass::kilograms(mass), position));
+            }
+            for i in 0 .. 300 {
+                let push = Vertex3D { x: w.rng().range(-1.0, 1.0), y: 0.0, z: 0.0 };
+                w.apply_force(i % 20, push);
+                w.step();
+            }
+            w.bodies().map(|(_, b)| b.clone()).collect::<Vec<Body>>()
+        };
+        assert_eq!(run(42), run(42));
+        assert_ne!(run(42), run(43));
+    }
+
+    #[test]
+    fn remove() {
+        let mut w = world();
+        let a = w.add_body(Body::new(Mass::kilograms(1.0), Vertex3D::ZERO));
+        let b = w.add_body(Body::new(Mass::kilograms(1.0), Vertex3D::ZERO));
+        assert!(w.remove_body(a).is_some());
+        assert!(w.remove_body(a).is_none());
+        assert_eq!(w.len(), 1);
+        assert!(w.body(b).is_some());
+        assert_eq!(w.add_body(Body::new(Mass::kilograms(1.0), Vertex3D::ZERO)), 2);
+    }
+
+    #[test]
+    fn colliders_turn_with_the_body() {
+        let v = |x, y, z| Vertex3D { x, y, z };
+        // a plank sticking out along x from the body's position
+        let plank = Shape3D::Cuboid { center: v(1.0, 0.0, 0.0), width: 2.0, height: 0.1, length: 0.2, orientation: Quaternion::IDENTITY };
+        let mut body = Body::new(Mass::kilograms(1.0), v(0.0, 5.0, 0.0)).with_collider(plank);
+        body.orientation = Quaternion::from_axis_angle(v(0.0, 0.0, 1.0), std::f32::consts::PI / 2.0);
+        let shape = body.shape().unwrap();
+        // now pointing straight up
+        assert!(v(0.0, 6.9, 0.0).is_inside(&shape));
+        assert!(v(1.5, 5.0, 0.0).is_outside(&shape));
+        assert!(shape.orientation().approx_eq(body.orientation, 1e-6));
+    }
+
+    #[test]
+    fn broad_phase_finds_every_contact() {
+        let mut w = world();
+        for _ in 0 .. 60 {
+            let position = Vertex3D { x: w.rng().range(-5.0, 5.0), y: w.rng().range(-5.0, 5.0), z: w.rng().range(-5.0, 5.0) };
+            let axis = Vertex3D { x: w.rng().range(-1.0, 1.0), y: 1.0, z: w.rng().range(-1.0, 1.0) };
+            let collider = match w.rng().next_u64() % 3 {
+                0 => Shape3D::Sphere { center: Vertex3D::ZERO, radius: w.rng().range(0.2, 1.5) },
+                1 => Shape3D::Cuboid { center: Vertex3D::ZERO, width: 2.0, height: 0.5, length: 1.0, orientation: Quaternion::IDENTITY },
+                _ => Shape3D::Cylinder { center: Vertex3D::ZERO, radius: 0.3, height: 3.0, orientation: Quaternion::IDENTITY },
+            };
+            let mut body = Body::new(Mass::kilograms(1.0), position).with_collider(collider);
+            body.orientation = Quaternion::from_axis_angle(axis, w.rng().range(0.0, 3.0));
+            w.add_body(body);
+        }
+        let shapes: Vec<(BodyId, Shape3D)> = w.bodies().map(|(id, b)| (id, b.shape().unwrap())).collect();
+        let mut every_pair = Vec::new();
+        for (i, (a, shape_a)) in shapes.iter().enumerate() {
+            for (b, shape_b) in shapes[i + 1 ..].iter() {
+                if let Some(contact) = collision::collide(shape_a, shape_b) {
+                    every_pair.push(BodyContact { a: *a, b: *b, contact, impulse: 0.0 });
+                }
+            }
+        }
+        assert!(every_pair.len() > 10);
+        assert_eq!(w.find_contacts(), every_pair);
+    }
+}
@@ -0,0 +1,526 @@
+use crate::auth::Authenticator;
+use rocket::Build;
+use rocket::Data;
+use rocket::Request;
+use rocket::Response;
+use rocket::Rocket;
+use rocket::fairing;
+use rocket::fairing::Fairing;
+use rocket::fairing::Info;
+use rocket::fairing::Kind;
+use rocket::http::Header;
+use rocket::http::ext::IntoOwned;
+use rocket::http::Method;
+use rocket::http::Status;
+use rocket::http::uri::Origin;
+use rocket::request;
+use rocket::request::FromRequest;
+use rocket::request::Outcome;
+use rocket::get;
+use rocket::routes;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::sync::RwLock;
+use std::time::Duration;
+use std::time::Instant;
+
+// rejected requests are rerouted here so their handlers never run; asked
+// for directly, the route forwards as if it weren't mounted
+const LIMITED_PATH: &str = "/__rate_limited";
+// past this many tracked clients, the least recently seen are dropped
+const MAX_BUCKETS: usize = 10_000;
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    pub read_per_second: f64,
+    pub read_burst: f64,
+    pub write_per_second: f64,
+    pub write_burst: f64,
+    // limit anonymous clients by the address in Rocket's ip_header
+    // (X-Real-IP unless configured otherwise) rather than the connection's.
+    // Only safe behind a proxy that always sets it.
+    pub trust_proxy_headers: bool,
+}
+
+impl RateLimitConfig {
+    fn validate(&self) -> Result<(), String> {
+        let budgets = [
+            ("read", self.read_per_second, self.read_burst),
+            ("write", self.write_per_second, self.write_burst),
+        ];
+        for (name, rate, burst) in budgets {
+            if !(rate > 0.0 && rate.is_finite()) {
+                return Err(format!("{}_per_second must be positive, not {}", name, rate));
+            }
+            if !(burst >= 1.0 && burst.is_finite()) {
+                return Err(format!("{}_burst must be at least 1, not {}", name, burst));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> RateLimitConfig {
+        RateLimitConfig {
+            read_per_second: 50.0,
+            read_burst: 100.0,
+            write_per_second: 10.0,
+            write_burst: 20.0,
+            trust_proxy_headers: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Budget {
+    Read,
+    Write,
+}
+
+impl Budget {
+    fn for_method(method: Method) -> Budget {
+        match method {
+            Method::Get | Method::Head | Method::Options => Budget::Read,
+            _ => Budget::Write,
+        }
+    }
+
+    // (tokens per second, bucket size)
+    fn limits(&self, config: &RateLimitConfig) -> (f64, f64) {
+        match self {
+            Budget::Read => (config.read_per_second, config.read_burst),
+            Budget::Write => (config.write_per_second, config.write_burst),
+        }
+    }
+}
+
+struct TokenBucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(burst: f64, now: Instant) -> TokenBucket {
+        TokenBucket { tokens: burst, updated: now }
+    }
+
+    fn refill(&mut self, rate: f64, burst: f64, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(burst);
+        self.updated = now;
+    }
+
+    // Takes a token, or returns how long until one is available.
+    fn take(&mut self, rate: f64, burst: f64, now: Instant) -> Result<(), Duration> {
+        self.refill(rate, burst, now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return Ok(());
+        }
+        // a tiny rate can put the wait beyond what a Duration holds
+        Err(Duration::try_from_secs_f64((1.0 - self.tokens) / rate).unwrap_or(Duration::MAX))
+    }
+}
+
+// How a rejected request arrived, before it was rerouted: the fairings that
+// report on responses describe this rather than the rate limit route.
+pub struct OriginalRequest {
+    pub method: Method,
+    pub uri: Origin<'static>,
+    // the route it would have reached, going by method and path alone
+    pub route: Option<String>,
+}
+
+struct Rejection {
+    retry_after: Duration,
+    original: OriginalRequest,
+}
+
+struct RateLimited(Option<Rejection>);
+
+// None unless the rate limiter turned the request away.
+pub fn original_request<'r>(request: &'r Request<'_>) -> Option<&'r OriginalRequest> {
+    match request.local_cache(|| RateLimited(None)) {
+        RateLimited(Some(rejection)) => Some(&rejection.original),
+        RateLimited(None) => None,
+    }
+}
+
+fn route_for(request: &Request<'_>, method: Method, path: &str) -> Option<String> {
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    let matches = |pattern: &str| {
+        let mut pattern = pattern.split('/').filter(|s| !s.is_empty());
+        let mut segments = segments.iter();
+        loop {
+            match (pattern.next(), segments.next()) {
+                (Some(p), _) if p.starts_with('<') && p.ends_with("..>") => return true,
+                (Some(p), Some(s)) if p == *s || (p.starts_with('<') && p.ends_with('>')) => continue,
+                (None, None) => return true,
+                _ => return false,
+            }
+        }
+    };
+    request.rocket().routes()
+        .filter(|route| route.method == method && matches(route.uri.path()))
+        .min_by_key(|route| route.rank)
+        .map(|route| route.uri.to_string())
+}
+
+#[derive(Default)]
+struct Buckets {
+    // with when each was last used
+    by_client: HashMap<(String, Budget), (TokenBucket, u64)>,
+    // least recently used first
+    order: BTreeMap<u64, (String, Budget)>,
+    next_use: u64,
+}
+
+impl Buckets {
+    // Drops buckets that have sat idle long enough to refill, which loses
+    // nothing, then the least recently used past the cap.
+    fn evict(&mut self, config: &RateLimitConfig, now: Instant) {
+        while let Some((&used, key)) = self.order.first_key_value() {
+            let (bucket, _) = &self.by_client[key];
+            let (rate, burst) = key.1.limits(config);
+            let idle = now.saturating_duration_since(bucket.updated).as_secs_f64();
+            if bucket.tokens + idle * rate < burst && self.order.len() <= MAX_BUCKETS {
+                break;
+            }
+            if let Some(key) = self.order.remove(&used) {
+                self.by_client.remove(&key);
+            }
+        }
+    }
+}
+
+pub struct RateLimiter {
+    config: RwLock<RateLimitConfig>,
+    buckets: Mutex<Buckets>,
+}
+
+impl RateLimiter {
+    pub fn new() -> RateLimiter {
+        RateLimiter {
+            config: RwLock::new(RateLimitConfig::default()),
+            buckets: Mutex::new(Buckets::default()),
+        }
+    }
+
+    pub fn config(&self) -> RateLimitConfig {
+        *self.config.read().expect("Unable to get read lock on rate limit config")
+    }
+
+    // Authenticated clients are limited by subject, everyone else by address.
+    fn client_identity(request: &Request<'_>, trust_proxy_headers: bool) -> String {
+        let subject = request.rocket().state::<Authenticator>().and_then(|auth| {
+            let token = request.headers().get_one("Authorization")?.strip_prefix("Bearer ")?;
+            auth.verify(token.trim()).ok()
+        });
+        if let Some(claims) = subject {
+            return format!("sub:{}", claims.sub);
+        }
+        let ip = if trust_proxy_headers {
+            request.client_ip()
+        } else {
+            request.remote().map(|remote| remote.ip())
+        };
+        match ip {
+            Some(ip) => format!("ip:{}", ip),
+            None => "unknown".to_string(),
+        }
+    }
+
+    fn check(&self, client: String, budget: Budget, now: Instant) -> Result<(), Duration> {
+        let config = self.config();
+        let (rate, burst) = budget.limits(&config);
+
+        let mut buckets = self.buckets.lock()
+        .expect("Unable to lock rate limit buckets");
+        let Buckets { by_client, order, next_use } = &mut *buckets;
+        let used = *next_use;
+        *next_use += 1;
+        let key = (client, budget);
+        let result = match by_client.get_mut(&key) {
+            Some((bucket, last_used)) => {
+                order.remove(last_used);
+                *last_used = used;
+                bucket.take(rate, burst, now)
+            },
+            None => {
+                let mut bucket = TokenBucket::new(burst, now);
+                let result = bucket.take(rate, burst, now);
+                by_client.insert(key.clone(), (bucket, used));
+                result
+            },
+        };
+        order.insert(used, key);
+        buckets.evict(&config, now);
+        result
+    }
+}
+
+impl Default for RateLimiter {
+    fn default() -> RateLimiter {
+        RateLimiter::new()
+    }
+}
+
+// Present only on requests the fairing rerouted.
+struct Rerouted;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Rerouted {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Rerouted, ()> {
+        match request.local_cache(|| RateLimited(None)) {
+            RateLimited(Some(_)) => Outcome::Success(Rerouted),
+            RateLimited(None) => Outcome::Forward(Status::NotFound),
+        }
+    }
+}
+
+#[get("/")]
+fn rate_limited(_rerouted: Rerouted) -> (Status, &'static str) {
+    (Status::TooManyRequests, "Rate limit exceeded")
+}
+
+#[rocket::async_trait]
+impl Fairing for RateLimiter {
+    fn info(&self) -> Info {
+        Info {
+            name: "Rate Limiter",
+            kind: Kind::Ignite | Kind::Request | Kind::Response
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        let config = match rocket.figment().find_value("rate_limit") {
+            Ok(_) => match rocket.figment().extract_inner::<RateLimitConfig>("rate_limit") {
+                Ok(config) => config,
+                Err(e) => {
+                    log::error!("Invalid rate_limit config: {}", e);
+                    return Err(rocket);
+                },
+            },
+            Err(_) => RateLimitConfig::default(),
+        };
+        if let Err(e) = config.validate() {
+            log::error!("Invalid rate_limit config: {}", e);
+            return Err(rocket);
+        }
+        *self.config.write().expect("Unable to get write lock on rate limit config") = config;
+        Ok(rocket.mount(LIMITED_PATH, routes![rate_limited]))
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        let budget = Budget::for_method(request.method());
+        let client = RateLimiter::client_identity(request, self.config().trust_proxy_headers);
+        if let Err(retry_after) = self.check(client, budget, Instant::now()) {
+            let original = OriginalRequest {
+                method: request.method(),
+                uri: request.uri().clone().into_owned(),
+                route: route_for(request, request.method(), request.uri().path().as_str()),
+            };
+            request.local_cache(|| RateLimited(Some(Rejection { retry_after, original })));
+            request.set_method(Method::Get);
+            request.set_uri(Origin::parse(LIMITED_PATH).expect("valid rate limit path"));
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        if let RateLimited(Some(Rejection { retry_after, .. })) = request.local_cache(|| RateLimited(None)) {
+            let secs = if *retry_after == Duration::MAX {
+                u64::MAX
+            } else {
+                retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0)
+            };
+            response.set_status(Status::TooManyRequests);
+            response.set_header(Header::new("Retry-After", secs.to_string()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::fairing::AdHoc;
+    use rocket::local::blocking::Client;
+    use rocket::post;
+
+    #[get("/")]
+    fn read() -> &'static str {
+        "read"
+    }
+
+    #[post("/")]
+    fn write() -> &'static str {
+        "write"
+    }
+
+    #[get("/items/<id>")]
+    fn read_item(id: u32) -> String {
+        id.to_string()
+    }
+
+    fn client(config: RateLimitConfig) -> Client {
+        let figment = rocket::Config::figment()
+            .merge(("rate_limit", config_table(config)))
+            .merge(("rate_limit.trust_proxy_headers", config.trust_proxy_headers));
+        let rocket = rocket::custom(figment)
+            .attach(RateLimiter::new())
+            .mount("/", routes![read, write, read_item])
+            .attach(AdHoc::on_response("Original Request", |request, response| Box::pin(async move {
+                if let Some(original) = original_request(request) {
+                    let route = original.route.clone().unwrap_or_default();
+                    response.set_header(Header::new("X-Original", format!("{} {} {}", original.method, original.uri, route)));
+                }
+            })));
+        Client::tracked(rocket).expect("valid rocket instance")
+    }
+
+    fn config_table(config: RateLimitConfig) -> HashMap<&'static str, f64> {
+        HashMap::from([
+            ("read_per_second", config.read_per_second),
+            ("read_burst", config.read_burst),
+            ("write_per_second", config.write_per_second),
+            ("write_burst", config.write_burst),
+        ])
+    }
+
+    #[test]
+    fn bucket_refills() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(2.0, start);
+        assert!(bucket.take(1.0, 2.0, start).is_ok());
+        assert!(bucket.take(1.0, 2.0, start).is_ok());
+        assert_eq!(bucket.take(1.0, 2.0, start), Err(Duration::from_secs(1)));
+        assert!(bucket.take(1.0, 2.0, start + Duration::from_secs(1)).is_ok());
+
+        // too long a wait to represent
+        let mut bucket = TokenBucket::new(1.0, start);
+        assert!(bucket.take(1e-300, 1.0, start).is_ok());
+        assert_eq!(bucket.take(1e-300, 1.0, start), Err(Duration::MAX));
+    }
+
+    #[test]
+    fn bad_config_refuses_launch() {
+        for config in [
+            RateLimitConfig { read_per_second: 0.0, ..RateLimitConfig::default() },
+            RateLimitConfig { write_per_second: -1.0, ..RateLimitConfig::default() },
+            RateLimitConfig { write_burst: 0.5, ..RateLimitConfig::default() },
+        ] {
+            assert!(config.validate().is_err());
+            let figment = rocket::Config::figment().merge(("rate_limit", config_table(config)));
+            let Err(error) = Client::tracked(rocket::custom(figment).attach(RateLimiter::new())) else {
+                panic!("launch should fail");
+            };
+            assert!(matches!(error.kind(), rocket::error::ErrorKind::FailedFairings(_)));
+        }
+        assert_eq!(RateLimitConfig::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn limited_route_is_not_reachable_directly() {
+        let client = client(RateLimitConfig::default());
+        let response = client.get(LIMITED_PATH).remote("10.0.0.4:5000".parse().unwrap()).dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        assert!(response.headers().get_one("Retry-After").is_none());
+    }
+
+    #[test]
+    fn writes_are_limited_separately_from_reads() {
+        let client = client(RateLimitConfig {
+            read_per_second: 100.0,
+            read_burst: 100.0,
+            write_per_second: 0.5,
+            write_burst: 2.0,
+            ..RateLimitConfig::default()
+        });
+
+        for _ in 0..2 {
+            assert_eq!(client.post("/").remote("10.0.0.1:5000".parse().unwrap()).dispatch().status(), Status::Ok);
+        }
+        let response = client.post("/").remote("10.0.0.1:5000".parse().unwrap()).dispatch();
+        assert_eq!(response.status(), Status::TooManyRequests);
+        assert_eq!(response.headers().get_one("Retry-After"), Some("2"));
+
+        // reads and other clients have their own budgets
+        assert_eq!(client.get("/").remote("10.0.0.1:5000".parse().unwrap()).dispatch().status(), Status::Ok);
+        assert_eq!(client.post("/").remote("10.0.0.2:5000".parse().unwrap()).dispatch().status(), Status::Ok);
+    }
+
+    #[test]
+    fn config_is_read_from_rocket() {
+        let config = RateLimitConfig {
+            read_per_second: 1.0,
+            read_burst: 3.0,
+            write_per_second: 2.0,
+            write_burst: 4.0,
+            ..RateLimitConfig::default()
+        };
+        let client = client(config);
+        for _ in 0..3 {
+            assert_eq!(client.get("/").remote("10.0.0.3:5000".parse().unwrap()).dispatch().status(), Status::Ok);
+        }
+        assert_eq!(client.get("/").remote("10.0.0.3:5000".parse().unwrap()).dispatch().status(), Status::TooManyRequests);
+    }
+
+    #[test]
+    fn forwarded_address_is_trusted_only_when_configured() {
+        let strict = RateLimitConfig { read_burst: 1.0, ..RateLimitConfig::default() };
+        for (config, second) in [
+            (strict, Status::TooManyRequests),
+            (RateLimitConfig { trust_proxy_headers: true, ..strict }, Status::Ok),
+        ] {
+            let client = client(config);
+            for (forwarded, status) in [("192.0.2.1", Status::Ok), ("192.0.2.2", second)] {
+                let response = client.get("/")
+                    .remote("10.0.0.5:5000".parse().unwrap())
+                    .header(Header::new("X-Real-IP", forwarded))
+                    .dispatch();
+                assert_eq!(response.status(), status);
+            }
+        }
+    }
+
+    #[test]
+    fn rejected_requests_remember_where_they_were_going() {
+        let client = client(RateLimitConfig { read_burst: 1.0, ..RateLimitConfig::default() });
+        let remote = "10.0.0.6:5000".parse().unwrap();
+        let response = client.get("/items/7?verbose=1").remote(remote).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert!(response.headers().get_one("X-Original").is_none());
+        let response = client.get("/items/7?verbose=1").remote(remote).dispatch();
+        assert_eq!(response.status(), Status::TooManyRequests);
+        assert_eq!(response.headers().get_one("X-Original"), Some("GET /items/7?verbose=1 /items/<id>"));
+    }
+
+    #[test]
+    fn buckets_are_evicted_least_recently_used_first() {
+        let limiter = RateLimiter::new();
+        let start = Instant::now();
+        let tracked = |limiter: &RateLimiter| limiter.buckets.lock().unwrap().by_client.len();
+        let read = |i: usize| (format!("ip:{}", i), Budget::Read);
+
+        for i in 0 .. MAX_BUCKETS + 10 {
+            assert!(limiter.check(read(i).0, Budget::Read, start).is_ok());
+            // the first client keeps coming back, even once it's turned away
+            let _ = limiter.check(read(0).0, Budget::Read, start);
+        }
+        assert_eq!(tracked(&limiter), MAX_BUCKETS);
+        let buckets = limiter.buckets.lock().unwrap();
+        assert!(buckets.by_client.contains_key(&read(0)));
+        assert!(!buckets.by_client.contains_key(&read(1)));
+        assert!(buckets.by_client.contains_key(&read(MAX_BUCKETS + 9)));
+        drop(buckets);
+
+        // once they've had time to refill, idle buckets go without waiting for the cap
+        assert!(limiter.check("ip:late".to_string(), Budget::Read, start + Duration::from_secs(60)).is_ok());
+        assert_eq!(tracked(&limiter), 1);
+    }
+}
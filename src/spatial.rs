@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::hash::Hash;
+use crate::geometry::Aabb;
+use crate::geometry::Vertex3D;
+
+type Cell = (i32, i32, i32);
+
+// Uniform grid over 3D space. Each key is stored in the one cell that
+// contains its location; queries visit every cell overlapping the search
+// box, or the sphere's bounding box, and return the keys found there as
+// candidates.
+#[derive(Clone, Debug)]
+pub struct GridIndex<K> {
+    cell_size: f32,
+    cells: HashMap<Cell, HashSet<K>>,
+    locations: HashMap<K, Cell>,
+}
+
+impl<K: Copy + Eq + Hash> GridIndex<K> {
+    pub fn new(cell_size: f32) -> GridIndex<K> {
+        assert!(cell_size > 0.0, "Grid cell size must be positive");
+        GridIndex {
+            cell_size,
+            cells: HashMap::new(),
+            locations: HashMap::new(),
+        }
+    }
+
+    fn cell_for(&self, v: &Vertex3D) -> Cell {
+        (
+            (v.x / self.cell_size).floor() as i32,
+            (v.y / self.cell_size).floor() as i32,
+            (v.z / self.cell_size).floor() as i32,
+        )
+    }
+
+    pub fn len(&self) -> usize {
+        self.locations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.locations.is_empty()
+    }
+
+    pub fn insert(&mut self, key: K, location: &Vertex3D) {
+        self.remove(&key);
+        let cell = self.cell_for(location);
+        self.cells.entry(cell).or_default().insert(key);
+        self.locations.insert(key, cell);
+    }
+
+    pub fn remove(&mut self, key: &K) -> bool {
+        let cell = if let Some(cell) = self.locations.remove(key) {
+            cell
+        } else {
+            return false;
+        };
+        if let Some(keys) = self.cells.get_mut(&cell) {
+            keys.remove(key);
+            if keys.is_empty() {
+                self.cells.remove(&cell);
+            }
+        }
+        true
+    }
+
+    // Keys whose cells overlap the sphere; callers still need an exact test.
+    pub fn query_sphere(&self, center: &Vertex3D, radius: f32) -> Vec<K> {
+        self.query_aabb(&Aabb::around(*center, Vertex3D { x: radius, y: radius, z: radius }))
+    }
+
+    // Keys whose cells overlap the box; callers still need an exact test.
+    pub fn query_aabb(&self, bounds: &Aabb) -> Vec<K> {
+        if bounds.is_empty() {
+            return Vec::new();
+        }
+        let min = self.cell_for(&bounds.min);
+        let max = self.cell_for(&bounds.max);
+
+        let mut found = Vec::new();
+        // cells saturate at the ends of i32, so a huge or infinite box can
+        // span more cells than fit in an i64 product
+        let span = |low: i32, high: i32| high as i64 - low as i64 + 1;
+        let visits = span(min.0, max.0).checked_mul(span(min.1, max.1))
+            .and_then(|n| n.checked_mul(span(min.2, max.2)));
+        if visits.is_none_or(|n| n > self.cells.len() as i64) {
+            // the search covers more cells than are occupied, so walk those instead
+            for (cell, keys) in self.cells.iter() {
+                if cell.0 >= min.0 && cell.0 <= max.0
+                    && cell.1 >= min.1 && cell.1 <= max.1
+                    && cell.2 >= min.2 && cell.2 <= max.2 {
+                    found.extend(keys.iter().copied());
+                }
+            }
+            return found;
+        }
+
+        for x in min.0 ..= max.0 {
+            for y in min.1 ..= max.1 {
+                for z in min.2 ..= max.2 {
+                    if let Some(keys) = self.cells.get(&(x, y, z)) {
+                        found.extend(keys.iter().copied());
+                    }
+                }
+            }
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(x: f32, y: f32, z: f32) -> Vertex3D {
+        Vertex3D { x, y, z }
+    }
+
+    #[test]
+    fn query_finds_nearby_keys() {
+        let mut index = GridIndex::new(10.0);
+        index.insert(1, &v(0.0, 0.0, 0.0));
+        index.insert(2, &v(25.0, 0.0, 0.0));
+        index.insert(3, &v(-500.0, 0.0, 0.0));
+
+        let mut found = index.query_sphere(&v(0.0, 0.0, 0.0), 30.0);
+        found.sort();
+        assert_eq!(found, vec![1, 2]);
+        assert_eq!(index.len(), 3);
+    }
+
+    #[test]
+    fn insert_moves_existing_key() {
+        let mut index = GridIndex::new(1.0);
+        index.insert(1, &v(0.5, 0.5, 0.5));
+        index.insert(1, &v(100.5, 0.5, 0.5));
+
+        assert!(index.query_sphere(&v(0.0, 0.0, 0.0), 2.0).is_empty());
+        assert_eq!(index.query_sphere(&v(100.0, 0.0, 0.0), 2.0), vec![1]);
+        assert_eq!(index.len(), 1);
+    }
+
+    #[test]
+    fn remove() {
+        let mut index = GridIndex::new(1.0);
+        index.insert(1, &v(0.0, 0.0, 0.0));
+        assert!(index.remove(&1));
+        assert!(!index.remove(&1));
+        assert!(index.is_empty());
+        assert!(index.query_sphere(&v(0.0, 0.0, 0.0), 5.0).is_empty());
+    }
+
+    #[test]
+    fn query_by_box() {
+        let mut index = GridIndex::new(1.0);
+        index.insert(1, &v(0.5, 0.5, 0.5));
+        index.insert(2, &v(5.5, 0.5, 0.5));
+        index.insert(3, &v(5.5, 9.5, 0.5));
+
+        let mut found = index.query_aabb(&Aabb::new(v(0.0, 0.0, 0.0), v(6.0, 1.0, 1.0)));
+        found.sort();
+        assert_eq!(found, vec![1, 2]);
+        assert!(index.query_aabb(&Aabb::EMPTY).is_empty());
+    }
+
+    #[test]
+    fn query_by_unbounded_box() {
+        let mut index = GridIndex::new(1.0);
+        index.insert(1, &v(0.5, 0.5, 0.5));
+        index.insert(2, &v(-1e6, 3e7, 0.5));
+
+        let everywhere = [
+            Aabb::new(v(-f32::MAX, -f32::MAX, -f32::MAX), v(f32::MAX, f32::MAX, f32::MAX)),
+            Aabb::new(v(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY), v(f32::INFINITY, f32::INFINITY, f32::INFINITY)),
+        ];
+        for bounds in everywhere {
+            let mut found = index.query_aabb(&bounds);
+            found.sort();
+            assert_eq!(found, vec![1, 2]);
+        }
+        // unbounded on one axis only
+        let slab = Aabb::new(v(0.0, f32::NEG_INFINITY, 0.0), v(1.0, f32::INFINITY, 1.0));
+        assert_eq!(index.query_aabb(&slab), vec![1]);
+    }
+}
@@ -0,0 +1,465 @@
+use crate::http_client;
+use rocket::serde::json::serde_json;
+use rocket::serde::json::serde_json::json;
+use rocket::serde::json::serde_json::Value;
+use serde::Deserialize;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Write;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+use uuid::Uuid;
+
+// Spans are batched on a background thread and written as OTLP/JSON, either
+// POSTed to a collector (`endpoint`, host:port) or appended to `file` one
+// export request per line. With neither configured spans are discarded.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct TelemetryConfig {
+    pub service_name: String,
+    pub endpoint: Option<String>,
+    pub path: String,
+    pub file: Option<String>,
+    pub batch_size: usize,
+    pub flush_interval_ms: u64,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> TelemetryConfig {
+        TelemetryConfig {
+            service_name: "bangbang".to_string(),
+            endpoint: None,
+            path: "/v1/traces".to_string(),
+            file: None,
+            batch_size: 512,
+            flush_interval_ms: 5000,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpanContext {
+    pub trace_id: [u8; 16],
+    pub span_id: [u8; 8],
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex<const N: usize>(s: &str) -> Option<[u8; N]> {
+    if s.len() != N * 2 || !s.is_ascii() {
+        return None;
+    }
+    let mut out = [0u8; N];
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&s[i * 2 .. i * 2 + 2], 16).ok()?;
+    }
+    Some(out)
+}
+
+impl SpanContext {
+    pub fn trace_id_hex(&self) -> String {
+        hex(&self.trace_id)
+    }
+
+    pub fn span_id_hex(&self) -> String {
+        hex(&self.span_id)
+    }
+
+    // W3C trace context, e.g. for a `traceparent` header on outgoing calls
+    pub fn to_traceparent(&self) -> String {
+        format!("00-{}-{}-01", self.trace_id_hex(), self.span_id_hex())
+    }
+
+    pub fn from_traceparent(header: &str) -> Option<SpanContext> {
+        let mut parts = header.trim().split('-');
+        let _version = parts.next()?;
+        let trace_id = unhex::<16>(parts.next()?)?;
+        let span_id = unhex::<8>(parts.next()?)?;
+        if trace_id == [0; 16] || span_id == [0; 8] {
+            return None;
+        }
+        Some(SpanContext { trace_id, span_id })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SpanKind {
+    Internal = 1,
+    Server = 2,
+    Client = 3,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AttributeValue {
+    String(String),
+    Int(i64),
+    Double(f64),
+    Bool(bool),
+}
+
+impl From<&str> for AttributeValue {
+    fn from(v: &str) -> AttributeValue { AttributeValue::String(v.to_string()) }
+}
+impl From<String> for AttributeValue {
+    fn from(v: String) -> AttributeValue { AttributeValue::String(v) }
+}
+impl From<i64> for AttributeValue {
+    fn from(v: i64) -> AttributeValue { AttributeValue::Int(v) }
+}
+impl From<usize> for AttributeValue {
+    fn from(v: usize) -> AttributeValue { AttributeValue::Int(v as i64) }
+}
+impl From<u16> for AttributeValue {
+    fn from(v: u16) -> AttributeValue { AttributeValue::Int(v as i64) }
+}
+impl From<f64> for AttributeValue {
+    fn from(v: f64) -> AttributeValue { AttributeValue::Double(v) }
+}
+impl From<f32> for AttributeValue {
+    fn from(v: f32) -> AttributeValue { AttributeValue::Double(v as f64) }
+}
+impl From<bool> for AttributeValue {
+    fn from(v: bool) -> AttributeValue { AttributeValue::Bool(v) }
+}
+
+impl AttributeValue {
+    fn to_otlp(&self) -> Value {
+        match self {
+            AttributeValue::String(v) => json!({ "stringValue": v }),
+            // OTLP/JSON carries 64 bit integers as strings
+            AttributeValue::Int(v) => json!({ "intValue": v.to_string() }),
+            AttributeValue::Double(v) => json!({ "doubleValue": v }),
+            AttributeValue::Bool(v) => json!({ "boolValue": v }),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpanData {
+    pub context: SpanContext,
+    pub parent_span_id: Option<[u8; 8]>,
+    pub name: String,
+    pub kind: SpanKind,
+    pub start_unix_nanos: u128,
+    pub end_unix_nanos: u128,
+    pub attributes: Vec<(String, AttributeValue)>,
+    pub error: Option<String>,
+}
+
+impl SpanData {
+    fn to_otlp(&self) -> Value {
+        let mut span = json!({
+            "traceId": self.context.trace_id_hex(),
+            "spanId": self.context.span_id_hex(),
+            "name": self.name,
+            "kind": self.kind as i32,
+            "startTimeUnixNano": self.start_unix_nanos.to_string(),
+            "endTimeUnixNano": self.end_unix_nanos.to_string(),
+            "attributes": self.attributes.iter()
+                .map(|(k, v)| json!({ "key": k, "value": v.to_otlp() }))
+                .collect::<Vec<Value>>(),
+            "status": match &self.error {
+                Some(message) => json!({ "code": 2, "message": message }),
+                None => json!({ "code": 0 }),
+            },
+        });
+        if let Some(parent) = self.parent_span_id {
+            span["parentSpanId"] = json!(hex(&parent));
+        }
+        span
+    }
+}
+
+// Wraps finished spans in an OTLP ExportTraceServiceRequest.
+pub fn to_otlp_json(service_name: &str, spans: &[SpanData]) -> String {
+    json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [{ "key": "service.name", "value": { "stringValue": service_name } }],
+            },
+            "scopeSpans": [{
+                "scope": { "name": "bangbang", "version": env!("CARGO_PKG_VERSION") },
+                "spans": spans.iter().map(|s| s.to_otlp()).collect::<Vec<Value>>(),
+            }],
+        }],
+    }).to_string()
+}
+
+fn now_unix_nanos() -> u128 {
+    SystemTime::now().duration_since(UNIX_EPOCH)
+    .expect("System clock is before the unix epoch")
+    .as_nanos()
+}
+
+fn new_span_id() -> [u8; 8] {
+    let mut id = [0u8; 8];
+    id.copy_from_slice(&Uuid::new_v4().as_bytes()[..8]);
+    id
+}
+
+enum Command {
+    Span(Box<SpanData>),
+    Flush(mpsc::Sender<()>),
+}
+
+pub struct Tracer {
+    sender: Option<Mutex<mpsc::Sender<Command>>>,
+}
+
+impl Tracer {
+    // A tracer that drops everything, for when telemetry is not configured.
+    pub fn disabled() -> Arc<Tracer> {
+        Arc::new(Tracer { sender: None })
+    }
+
+    pub fn new(config: TelemetryConfig) -> Arc<Tracer> {
+        if config.endpoint.is_none() && config.file.is_none() {
+            return Tracer::disabled();
+        }
+        let (sender, receiver) = mpsc::channel();
+        thread::Builder::new()
+            .name("span exporter".to_string())
+            .spawn(move || export_loop(config, receiver))
+            .expect("Unable to start span exporter");
+        Arc::new(Tracer { sender: Some(Mutex::new(sender)) })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.sender.is_some()
+    }
+
+    pub fn start_span(self: &Arc<Tracer>, name: &str, kind: SpanKind, parent: Option<&SpanContext>) -> Span {
+        let context = SpanContext {
+            trace_id: match parent {
+                Some(p) => p.trace_id,
+                None => *Uuid::new_v4().as_bytes(),
+            },
+            span_id: new_span_id(),
+        };
+        Span {
+            tracer: self.clone(),
+            data: Some(SpanData {
+                context,
+                parent_span_id: parent.map(|p| p.span_id),
+                name: name.to_string(),
+                kind,
+                start_unix_nanos: now_unix_nanos(),
+                end_unix_nanos: 0,
+                attributes: Vec::new(),
+                error: None,
+            }),
+        }
+    }
+
+    fn record(&self, span: SpanData) {
+        if let Some(sender) = &self.sender {
+            let _ = sender.lock().expect("Unable to lock span sender").send(Command::Span(Box::new(span)));
+        }
+    }
+
+    // Blocks until everything recorded so far has been exported.
+    pub fn flush(&self) {
+        if let Some(sender) = &self.sender {
+            let (done, wait) = mpsc::channel();
+            if sender.lock().expect("Unable to lock span sender").send(Command::Flush(done)).is_ok() {
+                let _ = wait.recv();
+            }
+        }
+    }
+}
+
+fn export(config: &TelemetryConfig, spans: &mut Vec<SpanData>) {
+    if spans.is_empty() {
+        return;
+    }
+    let body = to_otlp_json(&config.service_name, spans);
+    spans.clear();
+
+    let result: io::Result<()> = (|| {
+        if let Some(endpoint) = &config.endpoint {
+            let response = http_client::request(
+                "POST",
+                endpoint,
+                &config.path,
+                &[("Content-Type", "application/json")],
+                body.as_bytes(),
+                Duration::from_secs(5),
+            )?;
+            if response.status >= 300 {
+                return Err(io::Error::other(format!("Collector returned {}", response.status)));
+            }
+        }
+        if let Some(file) = &config.file {
+            let mut file = OpenOptions::new().create(true).append(true).open(file)?;
+            writeln!(file, "{}", body)?;
+        }
+        Ok(())
+    })();
+    if let Err(e) = result {
+        log::warn!("Unable to export spans: {}", e);
+    }
+}
+
+fn export_loop(config: TelemetryConfig, receiver: mpsc::Receiver<Command>) {
+    let interval = Duration::from_millis(config.flush_interval_ms.max(1));
+    let mut spans = Vec::new();
+    loop {
+        match receiver.recv_timeout(interval) {
+            Ok(Command::Span(span)) => {
+                spans.push(*span);
+                if spans.len() >= config.batch_size {
+                    export(&config, &mut spans);
+                }
+            },
+            Ok(Command::Flush(done)) => {
+                export(&config, &mut spans);
+                let _ = done.send(());
+            },
+            Err(mpsc::RecvTimeoutError::Timeout) => export(&config, &mut spans),
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                export(&config, &mut spans);
+                return;
+            },
+        }
+    }
+}
+
+// An in-progress span. It is recorded when ended or dropped.
+pub struct Span {
+    tracer: Arc<Tracer>,
+    data: Option<SpanData>,
+}
+
+impl Span {
+    pub fn context(&self) -> SpanContext {
+        self.data.as_ref().expect("Span already ended").context
+    }
+
+    pub fn set_name(&mut self, name: &str) {
+        if let Some(data) = self.data.as_mut() {
+            data.name = name.to_string();
+        }
+    }
+
+    pub fn set_attribute<V: Into<AttributeValue>>(&mut self, key: &str, value: V) {
+        if let Some(data) = self.data.as_mut() {
+            data.attributes.push((key.to_string(), value.into()));
+        }
+    }
+
+    pub fn set_error(&mut self, message: &str) {
+        if let Some(data) = self.data.as_mut() {
+            data.error = Some(message.to_string());
+        }
+    }
+
+    pub fn end(mut self) {
+        self.finish();
+    }
+
+    fn finish(&mut self) {
+        if let Some(mut data) = self.data.take() {
+            data.end_unix_nanos = now_unix_nanos();
+            self.tracer.record(data);
+        }
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+// Reads back a file written by the file exporter, mostly for tests.
+pub fn read_exported_spans(path: &str) -> io::Result<Vec<Value>> {
+    let contents = std::fs::read_to_string(path)?;
+    let mut spans = Vec::new();
+    for line in contents.lines().filter(|l| !l.is_empty()) {
+        let request: Value = serde_json::from_str(line)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        for resource in request["resourceSpans"].as_array().into_iter().flatten() {
+            for scope in resource["scopeSpans"].as_array().into_iter().flatten() {
+                spans.extend(scope["spans"].as_array().into_iter().flatten().cloned());
+            }
+        }
+    }
+    Ok(spans)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_file() -> String {
+        std::env::temp_dir()
+        .join(format!("bangbang-spans-{}.json", Uuid::new_v4().as_simple()))
+        .to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn traceparent() {
+        let ctx = SpanContext { trace_id: [1; 16], span_id: [2; 8] };
+        let header = ctx.to_traceparent();
+        assert_eq!(header, "00-01010101010101010101010101010101-0202020202020202-01");
+        assert_eq!(SpanContext::from_traceparent(&header), Some(ctx));
+        assert_eq!(SpanContext::from_traceparent("00-zz-0202020202020202-01"), None);
+        assert_eq!(SpanContext::from_traceparent("00-00000000000000000000000000000000-0202020202020202-01"), None);
+    }
+
+    #[test]
+    fn disabled_tracer_drops_spans() {
+        let tracer = Tracer::disabled();
+        let mut span = tracer.start_span("nothing", SpanKind::Internal, None);
+        span.set_attribute("k", 1i64);
+        span.end();
+        tracer.flush();
+        assert!(!tracer.is_enabled());
+    }
+
+    #[test]
+    fn file_export() {
+        let file = temp_file();
+        let tracer = Tracer::new(TelemetryConfig {
+            file: Some(file.clone()),
+            service_name: "test".to_string(),
+            ..TelemetryConfig::default()
+        });
+
+        let mut parent = tracer.start_span("parent", SpanKind::Server, None);
+        parent.set_attribute("http.method", "GET");
+        {
+            let mut child = tracer.start_span("child", SpanKind::Internal, Some(&parent.context()));
+            child.set_attribute("results", 3usize);
+            child.set_error("boom");
+        }
+        let parent_ctx = parent.context();
+        parent.end();
+        tracer.flush();
+
+        let spans = read_exported_spans(&file).unwrap();
+        assert_eq!(spans.len(), 2);
+        let child = &spans[0];
+        assert_eq!(child["name"], "child");
+        assert_eq!(child["traceId"], parent_ctx.trace_id_hex());
+        assert_eq!(child["parentSpanId"], parent_ctx.span_id_hex());
+        assert_eq!(child["attributes"][0]["value"]["intValue"], "3");
+        assert_eq!(child["status"]["code"], 2);
+        let parent = &spans[1];
+        assert_eq!(parent["kind"], 2);
+        assert!(parent.get("parentSpanId").is_none());
+        assert_eq!(parent["attributes"][0]["value"]["stringValue"], "GET");
+        let start: u128 = parent["startTimeUnixNano"].as_str().unwrap().parse().unwrap();
+        let end: u128 = parent["endTimeUnixNano"].as_str().unwrap().parse().unwrap();
+        assert!(end >= start);
+
+        std::fs::remove_file(&file).unwrap();
+    }
+}
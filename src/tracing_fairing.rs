@@ -0,0 +1,215 @@
+use crate::logger_fairing::REQUEST_ID_HEADER;
+use crate::rate_limit_fairing;
+use crate::telemetry::Span;
+use crate::telemetry::SpanContext;
+use crate::telemetry::SpanKind;
+use crate::telemetry::TelemetryConfig;
+use crate::telemetry::Tracer;
+use rocket::Build;
+use rocket::Data;
+use rocket::Orbit;
+use rocket::Request;
+use rocket::Response;
+use rocket::Rocket;
+use rocket::fairing;
+use rocket::fairing::Fairing;
+use rocket::fairing::Info;
+use rocket::fairing::Kind;
+use rocket::request::FromRequest;
+use rocket::request::Outcome;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::RwLock;
+
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+
+struct ServerSpan {
+    context: Option<SpanContext>,
+    span: Mutex<Option<Span>>,
+}
+
+// Handle on the current request's span for starting child spans. Without
+// the Tracing fairing attached the children go nowhere.
+pub struct RequestSpan {
+    tracer: Arc<Tracer>,
+    context: Option<SpanContext>,
+}
+
+impl RequestSpan {
+    pub fn context(&self) -> Option<SpanContext> {
+        self.context
+    }
+
+    pub fn child(&self, name: &str) -> Span {
+        self.tracer.start_span(name, SpanKind::Internal, self.context.as_ref())
+    }
+
+    // For forwarding on outgoing calls, so peers join the same trace.
+    pub fn traceparent(&self) -> Option<(&'static str, String)> {
+        self.context.map(|c| (TRACEPARENT_HEADER, c.to_traceparent()))
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RequestSpan {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let tracer = match request.rocket().state::<Arc<Tracer>>() {
+            Some(tracer) => tracer.clone(),
+            None => Tracer::disabled(),
+        };
+        let context = request.local_cache(|| ServerSpan { context: None, span: Mutex::new(None) }).context;
+        Outcome::Success(RequestSpan { tracer, context })
+    }
+}
+
+// One server span per request, named after the matched route. An incoming
+// W3C `traceparent` header makes it a child of the caller's span. Export is
+// configured by the `telemetry` table.
+pub struct Tracing {
+    tracer: RwLock<Arc<Tracer>>,
+}
+
+impl Tracing {
+    pub fn new() -> Tracing {
+        Tracing { tracer: RwLock::new(Tracer::disabled()) }
+    }
+
+    pub fn with_tracer(tracer: Arc<Tracer>) -> Tracing {
+        Tracing { tracer: RwLock::new(tracer) }
+    }
+
+    fn tracer(&self) -> Arc<Tracer> {
+        self.tracer.read().expect("Unable to get read lock on tracer").clone()
+    }
+}
+
+impl Default for Tracing {
+    fn default() -> Tracing {
+        Tracing::new()
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for Tracing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request Tracing",
+            kind: Kind::Ignite | Kind::Request | Kind::Response | Kind::Shutdown
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        if rocket.figment().find_value("telemetry").is_ok() {
+            match rocket.figment().extract_inner::<TelemetryConfig>("telemetry") {
+                Ok(config) => {
+                    *self.tracer.write().expect("Unable to get write lock on tracer") = Tracer::new(config);
+                },
+                Err(e) => {
+                    log::error!("Invalid telemetry config: {}", e);
+                    return Err(rocket);
+                },
+            }
+        }
+        Ok(rocket.manage(self.tracer()))
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        let tracer = self.tracer();
+        if !tracer.is_enabled() {
+            return;
+        }
+        let parent = request.headers().get_one(TRACEPARENT_HEADER)
+        .and_then(SpanContext::from_traceparent);
+        let mut span = tracer.start_span(&request.method().to_string(), SpanKind::Server, parent.as_ref());
+        span.set_attribute("http.method", request.method().as_str());
+        span.set_attribute("url.path", request.uri().path().as_str());
+        if let Some(ip) = request.client_ip() {
+            span.set_attribute("client.address", ip.to_string());
+        }
+        request.local_cache(|| ServerSpan {
+            context: Some(span.context()),
+            span: Mutex::new(Some(span)),
+        });
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let server = request.local_cache(|| ServerSpan { context: None, span: Mutex::new(None) });
+        let mut span = match server.span.lock().expect("Unable to lock request span").take() {
+            Some(span) => span,
+            None => return,
+        };
+        let (method, route) = match rate_limit_fairing::original_request(request) {
+            Some(original) => (original.method, original.route.clone()),
+            None => (request.method(), request.route().map(|route| route.uri.to_string())),
+        };
+        if let Some(route) = route {
+            span.set_name(&format!("{} {}", method, route));
+            span.set_attribute("http.route", route);
+        }
+        let status = response.status();
+        span.set_attribute("http.status_code", status.code);
+        if let Some(id) = response.headers().get_one(REQUEST_ID_HEADER) {
+            span.set_attribute("request.id", id);
+        }
+        if status.code >= 500 {
+            span.set_error(status.reason_lossy());
+        }
+        span.end();
+    }
+
+    async fn on_shutdown(&self, _: &Rocket<Orbit>) {
+        self.tracer().flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::read_exported_spans;
+    use rocket::get;
+    use rocket::http::Header;
+    use rocket::local::blocking::Client;
+    use rocket::routes;
+
+    #[get("/work/<n>")]
+    fn work(span: RequestSpan, n: u32) -> String {
+        let mut child = span.child("work");
+        child.set_attribute("n", n as i64);
+        span.context().map(|c| c.trace_id_hex()).unwrap_or_default()
+    }
+
+    #[test]
+    fn request_spans() {
+        let file = std::env::temp_dir()
+        .join(format!("bangbang-trace-{}.json", uuid::Uuid::new_v4().as_simple()))
+        .to_str().unwrap().to_string();
+        let tracer = Tracer::new(TelemetryConfig { file: Some(file.clone()), ..TelemetryConfig::default() });
+        let rocket = rocket::build()
+            .attach(Tracing::with_tracer(tracer.clone()))
+            .mount("/", routes![work]);
+        let client = Client::tracked(rocket).unwrap();
+
+        let parent = SpanContext { trace_id: [7; 16], span_id: [9; 8] };
+        let response = client.get("/work/3")
+            .header(Header::new(TRACEPARENT_HEADER, parent.to_traceparent()))
+            .dispatch();
+        assert_eq!(response.into_string().unwrap(), parent.trace_id_hex());
+        tracer.flush();
+
+        let spans = read_exported_spans(&file).unwrap();
+        assert_eq!(spans.len(), 2);
+        let (child, server) = (&spans[0], &spans[1]);
+        assert_eq!(child["name"], "work");
+        assert_eq!(server["name"], "GET /work/<n>");
+        assert_eq!(server["kind"], 2);
+        assert_eq!(server["traceId"], parent.trace_id_hex());
+        assert_eq!(server["parentSpanId"], parent.span_id_hex());
+        assert_eq!(child["parentSpanId"], server["spanId"]);
+        assert!(server["attributes"].as_array().unwrap().iter()
+            .any(|a| a["key"] == "http.status_code" && a["value"]["intValue"] == "200"));
+
+        std::fs::remove_file(&file).unwrap();
+    }
+}
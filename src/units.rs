@@ -0,0 +1,239 @@
+use serde::{Serialize, Deserialize};
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Div, Mul, Neg, Sub, SubAssign};
+
+// Quantities are stored in SI base units and only convert at the edges, so
+// metres can't be added to centimetres or a mass passed where a length is
+// expected. Each one serializes as a bare number in its SI unit.
+
+macro_rules! quantity {
+    ($name:ident, $symbol:expr, $si:ident, $in_si:ident $(, $unit:ident, $in_unit:ident, $factor:expr)*) => {
+        #[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+        #[serde(transparent)]
+        pub struct $name(f32);
+
+        impl $name {
+            pub const ZERO: $name = $name(0.0);
+
+            pub const fn $si(value: f32) -> $name {
+                $name(value)
+            }
+
+            pub const fn $in_si(self) -> f32 {
+                self.0
+            }
+
+            $(
+            pub fn $unit(value: f32) -> $name {
+                $name(value * $factor)
+            }
+
+            pub fn $in_unit(self) -> f32 {
+                self.0 / $factor
+            }
+            )*
+
+            pub fn abs(self) -> $name {
+                $name(self.0.abs())
+            }
+
+            pub fn min(self, other: $name) -> $name {
+                $name(self.0.min(other.0))
+            }
+
+            pub fn max(self, other: $name) -> $name {
+                $name(self.0.max(other.0))
+            }
+
+            pub fn is_finite(self) -> bool {
+                self.0.is_finite()
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{} {}", self.0, $symbol)
+            }
+        }
+
+        impl Add for $name {
+            type Output = $name;
+            fn add(self, other: $name) -> $name {
+                $name(self.0 + other.0)
+            }
+        }
+
+        impl Sub for $name {
+            type Output = $name;
+            fn sub(self, other: $name) -> $name {
+                $name(self.0 - other.0)
+            }
+        }
+
+        impl AddAssign for $name {
+            fn add_assign(&mut self, other: $name) {
+                self.0 += other.0;
+            }
+        }
+
+        impl SubAssign for $name {
+            fn sub_assign(&mut self, other: $name) {
+                self.0 -= other.0;
+            }
+        }
+
+        impl Neg for $name {
+            type Output = $name;
+            fn neg(self) -> $name {
+                $name(-self.0)
+            }
+        }
+
+        impl Mul<f32> for $name {
+            type Output = $name;
+            fn mul(self, scale: f32) -> $name {
+                $name(self.0 * scale)
+            }
+        }
+
+        impl Mul<$name> for f32 {
+            type Output = $name;
+            fn mul(self, quantity: $name) -> $name {
+                $name(self * quantity.0)
+            }
+        }
+
+        impl Div<f32> for $name {
+            type Output = $name;
+            fn div(self, scale: f32) -> $name {
+                $name(self.0 / scale)
+            }
+        }
+
+        // a ratio of like quantities has no unit
+        impl Div for $name {
+            type Output = f32;
+            fn div(self, other: $name) -> f32 {
+                self.0 / other.0
+            }
+        }
+
+        impl Sum for $name {
+            fn sum<I: Iterator<Item = $name>>(iter: I) -> $name {
+                $name(iter.map(|q| q.0).sum())
+            }
+        }
+    };
+}
+
+// a * b = c, along with b * a and both divisions of c
+macro_rules! product {
+    ($a:ident * $b:ident = $c:ident) => {
+        impl Mul<$b> for $a {
+            type Output = $c;
+            fn mul(self, other: $b) -> $c {
+                $c(self.0 * other.0)
+            }
+        }
+
+        impl Div<$a> for $c {
+            type Output = $b;
+            fn div(self, other: $a) -> $b {
+                $b(self.0 / other.0)
+            }
+        }
+    };
+    ($a:ident * $b:ident = $c:ident, commutes) => {
+        product!($a * $b = $c);
+        product!($b * $a = $c);
+    };
+}
+
+quantity!(Length, "m", meters, in_meters,
+    millimeters, in_millimeters, 1e-3,
+    centimeters, in_centimeters, 1e-2,
+    kilometers, in_kilometers, 1e3);
+quantity!(Area, "m^2", square_meters, in_square_meters,
+    square_centimeters, in_square_centimeters, 1e-4);
+quantity!(Volume, "m^3", cubic_meters, in_cubic_meters,
+    liters, in_liters, 1e-3);
+quantity!(Mass, "kg", kilograms, in_kilograms,
+    grams, in_grams, 1e-3);
+quantity!(Time, "s", seconds, in_seconds,
+    milliseconds, in_milliseconds, 1e-3);
+quantity!(Velocity, "m s^-1", meters_per_second, in_meters_per_second,
+    kilometers_per_hour, in_kilometers_per_hour, 1.0 / 3.6);
+quantity!(Acceleration, "m s^-2", meters_per_second_squared, in_meters_per_second_squared);
+quantity!(Force, "N", newtons, in_newtons);
+quantity!(Energy, "J", joules, in_joules);
+
+product!(Length * Length = Area);
+product!(Length * Area = Volume, commutes);
+product!(Velocity * Time = Length, commutes);
+product!(Acceleration * Time = Velocity, commutes);
+product!(Mass * Acceleration = Force, commutes);
+product!(Force * Length = Energy, commutes);
+
+impl Area {
+    // side of the square with this area
+    pub fn sqrt(self) -> Length {
+        Length(self.0.sqrt())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::serde::json::serde_json;
+
+    #[test]
+    fn conversions() {
+        assert_eq!(Length::centimeters(250.0), Length::meters(2.5));
+        assert!((Length::kilometers(1.2).in_meters() - 1200.0).abs() < 1e-3);
+        assert!((Length::meters(0.5).in_millimeters() - 500.0).abs() < 1e-3);
+        assert!((Mass::grams(8.0).in_kilograms() - 0.008).abs() < 1e-9);
+        assert!((Velocity::kilometers_per_hour(36.0).in_meters_per_second() - 10.0).abs() < 1e-5);
+        assert!((Volume::liters(1.0).in_cubic_meters() - 0.001).abs() < 1e-9);
+        assert!((Time::milliseconds(1500.0).in_seconds() - 1.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn dimensions_combine() {
+        let side = Length::meters(2.0);
+        let area: Area = side * side;
+        let volume: Volume = area * side;
+        assert_eq!(volume, Volume::cubic_meters(8.0));
+        assert_eq!(volume / side, area);
+        assert_eq!(area / side, side);
+        assert_eq!(area.sqrt(), side);
+
+        let speed: Velocity = Length::meters(100.0) / Time::seconds(4.0);
+        assert_eq!(speed, Velocity::meters_per_second(25.0));
+        assert_eq!(speed * Time::seconds(2.0), Length::meters(50.0));
+        assert_eq!(Length::meters(100.0) / speed, Time::seconds(4.0));
+
+        let force: Force = Mass::kilograms(2.0) * Acceleration::meters_per_second_squared(3.0);
+        assert_eq!(force, Force::newtons(6.0));
+        assert_eq!(force / Mass::kilograms(2.0), Acceleration::meters_per_second_squared(3.0));
+        assert_eq!(force * Length::meters(2.0), Energy::joules(12.0));
+    }
+
+    #[test]
+    fn like_quantities() {
+        let total: Length = [Length::meters(1.0), Length::centimeters(50.0), -Length::meters(0.25)].into_iter().sum();
+        assert_eq!(total, Length::meters(1.25));
+        assert_eq!(Mass::kilograms(3.0) / Mass::kilograms(1.5), 2.0);
+        assert_eq!(2.0 * Time::seconds(1.5), Time::seconds(3.0));
+        assert!(Length::millimeters(999.0) < Length::meters(1.0));
+        assert_eq!(Length::meters(-2.0).abs().max(Length::meters(1.0)), Length::meters(2.0));
+        assert_eq!(Force::newtons(9.5).to_string(), "9.5 N");
+    }
+
+    #[test]
+    fn serializes_as_si_numbers() {
+        assert_eq!(serde_json::to_string(&Length::centimeters(150.0)).unwrap(), "1.5");
+        let mass: Mass = serde_json::from_str("2.25").unwrap();
+        assert_eq!(mass, Mass::kilograms(2.25));
+    }
+}